@@ -0,0 +1,70 @@
+#![feature(test)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+extern crate test;
+use test::Bencher;
+
+use dir_signature::{HashType, DEFAULT_BLOCK_SIZE, v1};
+
+/// Keeps track of the currently allocated and the peak number of bytes
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let cur = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst)
+            + layout.size();
+        PEAK.fetch_max(cur, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const GIGABYTE: u64 = 1 << 30;
+const STREAM_SIZE: u64 = 64 << 20;
+
+/// Hashes 1GB stream and checks that only the hashes are kept in memory
+fn check_memory(hash_type: HashType) {
+    let blocks = GIGABYTE / DEFAULT_BLOCK_SIZE;
+    let hashes_size = (blocks * 32) as usize;
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let (size, hashes) = v1::hash_reader(io::repeat(0x5a).take(GIGABYTE),
+        hash_type, DEFAULT_BLOCK_SIZE).unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    assert_eq!(size, GIGABYTE);
+    assert_eq!(hashes.len() as u64, blocks);
+    // growing a vector of hashes may temporarily need twice its size
+    assert!(peak <= 3 * hashes_size,
+        "peak memory {} for {} bytes of hashes", peak, hashes_size);
+}
+
+#[bench]
+fn bench_hash_reader_blake3(bencher: &mut Bencher) {
+    check_memory(HashType::blake3_256());
+    bencher.bytes = STREAM_SIZE;
+    bencher.iter(|| {
+        v1::hash_reader(io::repeat(0x5a).take(STREAM_SIZE),
+            HashType::blake3_256(), DEFAULT_BLOCK_SIZE).unwrap()
+    });
+}
+
+#[bench]
+fn bench_hash_reader_sha512(bencher: &mut Bencher) {
+    bencher.bytes = STREAM_SIZE;
+    bencher.iter(|| {
+        v1::hash_reader(io::repeat(0x5a).take(STREAM_SIZE),
+            HashType::sha512_256(), DEFAULT_BLOCK_SIZE).unwrap()
+    });
+}
@@ -8,7 +8,7 @@ use std::process;
 extern crate test;
 use test::Bencher;
 
-use dir_signature::v1::{Entry, Parser};
+use dir_signature::v1::{Entry, EntryRef, Parser};
 use dir_signature::v1::merge::FileMergeBuilder;
 
 #[bench]
@@ -30,11 +30,61 @@ fn bench_parser_iterator_ubuntu(bencher: &mut Bencher) {
                 Entry::Dir(_) => {},
                 Entry::File{..} => {},
                 Entry::Link(..) => {},
+                _ => {},
             }
         }
     });
 }
 
+#[bench]
+fn bench_parser_borrowed_ubuntu(bencher: &mut Bencher) {
+    let sig_path = get_ubuntu_signature_path();
+    warmup_signature_file(sig_path);
+
+    bencher.iter(|| {
+        let idx_file = File::open(sig_path).unwrap();
+        let reader = BufReader::new(idx_file);
+        let mut signature_parser = Parser::new(reader).unwrap();
+        let mut entries = signature_parser.iter_borrowed();
+        while let Some(entry) = entries.next_entry() {
+            match entry.unwrap() {
+                EntryRef::Dir(_) => {},
+                EntryRef::File{..} => {},
+                EntryRef::Link(..) => {},
+                EntryRef::Whiteout(..) => {},
+            }
+        }
+    });
+}
+
+fn bench_parser_capacity(bencher: &mut Bencher, capacity: usize) {
+    let sig_path = get_ubuntu_signature_path();
+    warmup_signature_file(sig_path);
+
+    bencher.iter(|| {
+        let mut signature_parser = Parser::open_with_capacity(
+            sig_path, capacity).unwrap();
+        for entry in signature_parser.iter() {
+            entry.unwrap();
+        }
+    });
+}
+
+#[bench]
+fn bench_parser_capacity_8k(bencher: &mut Bencher) {
+    bench_parser_capacity(bencher, 8 << 10);
+}
+
+#[bench]
+fn bench_parser_capacity_64k(bencher: &mut Bencher) {
+    bench_parser_capacity(bencher, 64 << 10);
+}
+
+#[bench]
+fn bench_parser_capacity_1m(bencher: &mut Bencher) {
+    bench_parser_capacity(bencher, 1 << 20);
+}
+
 #[bench]
 fn bench_merged_iterator(bencher: &mut Bencher) {
     let sig_path = get_ubuntu_signature_path();
@@ -55,6 +105,7 @@ fn bench_merged_iterator(bencher: &mut Bencher) {
                     Entry::Dir(_) => {},
                     Entry::File{..} => {},
                     Entry::Link(..) => {},
+                    _ => {},
                 }
             }
         }
@@ -83,6 +134,7 @@ fn warmup_signature_file(path: &Path) -> (usize, usize, usize) {
             Entry::Dir(_) => num_dirs += 1,
             Entry::File{..} => num_files += 1,
             Entry::Link(..) => num_links += 1,
+            _ => {},
         }
     }
     (num_dirs, num_files, num_links)
@@ -0,0 +1,55 @@
+#![feature(test)]
+
+use std::fs;
+use std::path::Path;
+
+extern crate test;
+use test::Bencher;
+
+use dir_signature::{ScannerConfig, v1};
+
+const DIRS: usize = 100;
+const FILES: usize = 100;
+
+/// Creates a tree of `DIRS * FILES` small files
+fn synthetic_tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for d in 0..DIRS {
+        let sub = dir.path().join(format!("dir{:03}", d));
+        fs::create_dir(&sub).unwrap();
+        for f in 0..FILES {
+            fs::write(sub.join(format!("file{:03}.txt", f)),
+                      format!("{}:{}\n", d, f)).unwrap();
+        }
+    }
+    dir
+}
+
+fn config(path: &Path) -> ScannerConfig {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(path, "/");
+    cfg
+}
+
+#[bench]
+fn bench_scan_to_vec_default(bencher: &mut Bencher) {
+    let tree = synthetic_tree();
+    let cfg = config(tree.path());
+    bencher.iter(|| {
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        buf
+    });
+}
+
+#[bench]
+fn bench_scan_to_vec_presized(bencher: &mut Bencher) {
+    let tree = synthetic_tree();
+    let cfg = config(tree.path());
+    bencher.iter(|| {
+        let size = v1::estimate_size(&cfg).unwrap();
+        let mut buf = Vec::with_capacity(size as usize);
+        v1::scan(&cfg, &mut buf).unwrap();
+        buf
+    });
+}
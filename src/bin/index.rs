@@ -4,17 +4,45 @@ use env_logger;
 
 
 
+use std::fmt;
 use std::io::{self, Write};
 use std::env;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::FromStr;
+use std::time::Duration;
 
 use argparse::{ArgumentParser, List, ParseOption, Store, StoreTrue, StoreFalse};
 #[cfg(feature="threads")]
 use num_cpus::get as get_num_cpus;
 
-use dir_signature::{v1, ScannerConfig, HashType};
+use dir_signature::{v1, ScannerConfig, HashType, ImageId, ProgressFormat};
+use dir_signature::v1::verify::{verify_dir, Discrepancy};
+
+/// Exit code when directory matches the index
+const EXIT_MATCH: i32 = 0;
+/// Exit code when there are differences between directory and the index
+const EXIT_DIFFERENT: i32 = 1;
+/// Exit code for any kind of error when comparing
+const EXIT_ERROR: i32 = 2;
+
+#[derive(Clone, Copy)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = ();
+    fn from_str(val: &str) -> Result<Format, ()> {
+        match val {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(()),
+        }
+    }
+}
 
 #[cfg(not(feature="threads"))]
 fn get_num_cpus() -> usize {
@@ -32,6 +60,11 @@ pub fn run() -> i32 {
     let mut dirs = Vec::<String>::new();
     let mut hash_type = HashType::sha512_256();
     let mut progress = true;
+    let mut progress_lines = false;
+    let mut progress_interval = None::<u64>;
+    let mut verify = None::<PathBuf>;
+    let mut format = Format::Text;
+    let mut id_only = false;
     {
         let mut ap = ArgumentParser::new();
         ap.set_description("
@@ -52,6 +85,30 @@ pub fn run() -> i32 {
                 "Do not output progress or anything except errors")
             .add_option(&["--progress"], StoreTrue,
                 "Show progress (default)");
+        ap.refer(&mut progress_lines)
+            .add_option(&["--progress-lines"], StoreTrue,
+                "Print every progress update on a separate line instead of
+                 updating a single line in place. Useful when stderr is
+                 redirected to a file.");
+        ap.refer(&mut progress_interval)
+            .add_option(&["--progress-interval"], ParseOption,
+                "Minimum interval between progress updates in milliseconds
+                 (default 100)")
+            .metavar("MS");
+        ap.refer(&mut verify)
+            .add_option(&["--verify"], ParseOption,
+                "Instead of writing an index, compare DIR to the index at
+                 PATH. Exits with 0 if directory matches, 1 if there are
+                 differences and 2 on error.")
+            .metavar("PATH");
+        ap.refer(&mut format)
+            .add_option(&["--format"], Store,
+                "Output format of differences found by `--verify`.
+                 Options: `text` (default), `json` (one object per line).")
+            .metavar("FORMAT");
+        ap.refer(&mut id_only)
+            .add_option(&["--id-only"], StoreTrue,
+                "Do not write the index, print only its hash (image id)");
         ap.refer(&mut hash_type)
             .add_option(&["--hash"], Store,
                 "Use specified hasher.
@@ -76,12 +133,26 @@ pub fn run() -> i32 {
         }
     }
 
+    if let Some(index) = verify {
+        if dirs.len() != 1 {
+            error!("Exactly one directory must be specified for --verify");
+            return EXIT_ERROR;
+        }
+        return run_verify(&index, Path::new(&dirs[0]), format);
+    }
+
     let mut cfg = ScannerConfig::new();
     cfg.threads(threads + 1);
     cfg.hash(hash_type);
     if progress {
         cfg.print_progress();
     }
+    if progress_lines {
+        cfg.progress_format(ProgressFormat::Lines);
+    }
+    if let Some(ms) = progress_interval {
+        cfg.progress_interval(Duration::from_millis(ms));
+    }
     if dirs.len() > 1 {
         warn!("Using more than one source dir is not recommended as it's \
                not implemented properly yet");
@@ -99,6 +170,21 @@ pub fn run() -> i32 {
         cfg.add_dir(path, prefix);
     }
 
+    if id_only {
+        return match v1::scan_combined_hash(&cfg)
+            .and_then(|hash| ImageId::new(hash_type, hash))
+        {
+            Ok(id) => {
+                println!("{:x}", id);
+                0
+            }
+            Err(e) => {
+                writeln!(&mut io::stderr(), "Error: {}", e).ok();
+                1
+            }
+        };
+    }
+
     let res = if let Some(path) = index {
         let file = match File::create(&path) {
             Ok(f) => f,
@@ -120,6 +206,91 @@ pub fn run() -> i32 {
     }
 }
 
+fn run_verify(index: &Path, dir: &Path, format: Format) -> i32 {
+    let mut parser = match v1::Parser::open(index) {
+        Ok(p) => p,
+        Err(e) => {
+            writeln!(&mut io::stderr(), "Can't read index: {}", e).ok();
+            return EXIT_ERROR;
+        }
+    };
+    let diff = match verify_dir(&mut parser, dir) {
+        Ok(diff) => diff,
+        Err(e) => {
+            writeln!(&mut io::stderr(), "Error: {}", e).ok();
+            return EXIT_ERROR;
+        }
+    };
+    let out = io::stdout();
+    let mut out = out.lock();
+    for item in &diff {
+        let res = match format {
+            Format::Text => writeln!(out, "{}", item),
+            Format::Json => writeln!(out, "{}", Json(item)),
+        };
+        if let Err(e) = res {
+            writeln!(&mut io::stderr(), "Error: {}", e).ok();
+            return EXIT_ERROR;
+        }
+    }
+    if diff.is_empty() {
+        EXIT_MATCH
+    } else {
+        EXIT_DIFFERENT
+    }
+}
+
+/// Formats a discrepancy as a single-line JSON object
+struct Json<'a>(&'a Discrepancy);
+
+/// Formats a path as a JSON string (non-utf-8 chars are replaced)
+struct JsonStr<'a>(&'a Path);
+
+impl<'a> fmt::Display for JsonStr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.to_string_lossy().chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+impl<'a> fmt::Display for Json<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Discrepancy::*;
+        let kind = match *self.0 {
+            Missing(..) => "missing",
+            Extra(..) => "extra",
+            TypeMismatch(..) => "type_mismatch",
+            ExeMismatch(..) => "exe_mismatch",
+            SizeMismatch { .. } => "size_mismatch",
+            ContentMismatch(..) => "content_mismatch",
+            LinkMismatch { .. } => "link_mismatch",
+        };
+        write!(f, r#"{{"kind":"{}","path":{}"#, kind, JsonStr(self.0.path()))?;
+        match *self.0 {
+            SizeMismatch { expected, actual, .. } => {
+                write!(f, r#","expected":{},"actual":{}"#, expected, actual)?;
+            }
+            LinkMismatch { ref expected, ref actual, .. } => {
+                write!(f, r#","expected":{},"actual":{}"#,
+                    JsonStr(expected), JsonStr(actual))?;
+            }
+            _ => {}
+        }
+        f.write_str("}")
+    }
+}
+
 fn main() {
     exit(run());
 }
@@ -1,6 +1,8 @@
 use std::path::Path;
+use std::time::{Duration, SystemTime};
 
-use crate::{ScannerConfig, HashType, HashTypeEnum};
+use crate::{ScannerConfig, HashType, HashTypeEnum, CollisionPolicy};
+use crate::{TargetMode, ExeBitMode, ProgressFormat, DEFAULT_BLOCK_SIZE};
 
 
 impl ScannerConfig {
@@ -13,9 +15,25 @@ impl ScannerConfig {
             threads: 0,
             queue_size: None,
             hash: HashType(HashTypeEnum::Sha512_256),
-            block_size: 32768,
+            block_size: DEFAULT_BLOCK_SIZE,
+            auto_block_size: false,
+            content_defined_chunks: false,
+            io_retries: 0,
+            stable_read: 0,
+            mtime_cutoff: None,
+            store_ownership: false,
+            on_collision: CollisionPolicy::LastWins,
+            symlink_targets: TargetMode::Verbatim,
+            exe_bit: ExeBitMode::OwnerOnly,
+            overlay_whiteouts: false,
+            marked_footer: false,
+            collect_skipped: false,
+            prune_empty_dirs: false,
+            max_depth: None,
             dirs: Vec::new(),
             print_progress: false,
+            progress_format: ProgressFormat::Overwrite,
+            progress_interval: Duration::from_millis(100),
         }
     }
     /// Use different hash type
@@ -23,6 +41,47 @@ impl ScannerConfig {
         self.hash = hash;
         self
     }
+    /// Returns block size that will be used for hashing files
+    ///
+    /// With [`auto_block_size`](#method.auto_block_size) the size is only
+    /// known at scan time, use
+    /// [`v1::choose_block_size`](v1/fn.choose_block_size.html) to find it
+    /// out in advance.
+    pub fn get_block_size(&self) -> u64 {
+        self.block_size
+    }
+    /// Choose block size automatically based on the size of the tree
+    ///
+    /// An additional pass over the directories is made before scanning,
+    /// reading only metadata. The smallest power of two is chosen so that
+    /// the whole index has no more than about a million hashes and a single
+    /// file no more than 4096 hashes, but never less than
+    /// [`DEFAULT_BLOCK_SIZE`](constant.DEFAULT_BLOCK_SIZE.html). So small
+    /// trees get the default block size, and large files and trees get
+    /// larger blocks, which keeps the index small at the cost of
+    /// coarser granularity. Block size chosen is written to the header as
+    /// usual.
+    pub fn auto_block_size(&mut self) -> &mut Self {
+        self.auto_block_size = true;
+        self
+    }
+    /// Split files into content-defined chunks instead of fixed blocks
+    ///
+    /// Boundaries of chunks are chosen by the data itself (see
+    /// [`v1::cdc`](v1/cdc/index.html)), so inserting a byte into a file
+    /// only changes the hash of a single chunk rather than of all the
+    /// following blocks, which makes deduplication of edited files
+    /// much better. Block size is used as the average chunk size, it must
+    /// be in the range of `v1::cdc::MIN_AVG_SIZE..=v1::cdc::MAX_AVG_SIZE`.
+    ///
+    /// Lengths of the chunks are written to the index along with hashes
+    /// and header gets `chunking=cdc` attribute. This is an extension to
+    /// the v1 format: older versions of this library fail to parse such
+    /// index files.
+    pub fn content_defined_chunks(&mut self) -> &mut Self {
+        self.content_defined_chunks = true;
+        self
+    }
     /// Set number of threads to use for scanning
     ///
     /// Default is 0 which means don't create additional threads and do
@@ -30,7 +89,8 @@ impl ScannerConfig {
     /// create num threads for hashing and will use current thread for
     /// scanning directories and priting progress.
     ///
-    /// This parameter is ignored if "threads" feature is disabled
+    /// This parameter is ignored if both "threads" and "std-threads"
+    /// features are disabled
     pub fn threads(&mut self, num: usize) -> &mut Self {
         self.threads = num;
         self
@@ -45,10 +105,19 @@ impl ScannerConfig {
         self
     }
 
+    /// Set number of threads to the number of CPU cores on the system
+    #[cfg(all(feature="std-threads", not(feature="threads")))]
+    pub fn auto_threads(&mut self) -> &mut Self {
+        self.threads = ::std::thread::available_parallelism()
+            .map_or(1, |n| n.get());
+        self
+    }
+
     /// Set number of threads to the number of CPU cores on the system
     ///
-    /// This method does nothing if "threads" feature is disabled
-    #[cfg(not(feature="threads"))]
+    /// This method does nothing if both "threads" and "std-threads"
+    /// features are disabled
+    #[cfg(not(any(feature="threads", feature="std-threads")))]
     pub fn auto_threads(&mut self) -> &mut Self {
         self
     }
@@ -62,6 +131,169 @@ impl ScannerConfig {
         self.queue_size = Some(num);
         self
     }
+    /// Retry reading a file on transient errors
+    ///
+    /// When reading files from a network filesystem, operations may fail
+    /// with errors like `EINTR` or `EAGAIN`. With this option such errors
+    /// (and only them) are retried up to `num` times per file with
+    /// an exponential backoff, instead of aborting the whole scan.
+    ///
+    /// Default is 0 (no retries).
+    pub fn io_retries(&mut self, num: u32) -> &mut Self {
+        self.io_retries = num;
+        self
+    }
+    /// Re-read files that change while being hashed
+    ///
+    /// After a file is hashed its metadata is read again and if the size
+    /// or the modification time differ from ones when the file was opened,
+    /// the file is hashed again, up to `num` more times. If the file is
+    /// still changing after that, the scan fails with `Error::ReadFile`.
+    ///
+    /// This is a best-effort check: a file modified in place without
+    /// changing its size within the same timestamp granularity is not
+    /// detected. Default is 0, which means metadata is not re-read and the
+    /// index contains whatever was read from a file being written.
+    pub fn stable_read(&mut self, num: u32) -> &mut Self {
+        self.stable_read = num;
+        self
+    }
+    /// Skip files and symlinks modified after the specified time
+    ///
+    /// This is useful to make a snapshot of a directory that ignores
+    /// files being written right now. Note that the resulting index depends
+    /// on modification times, so scanning the same set of files with
+    /// different cutoffs (or after `touch`ing files) may produce a different
+    /// index, even if contents of the files are the same.
+    ///
+    /// Directories are never skipped.
+    pub fn mtime_cutoff(&mut self, time: SystemTime) -> &mut Self {
+        self.mtime_cutoff = Some(time);
+        self
+    }
+    /// Store owner (uid and gid) of files and directories in the index
+    ///
+    /// Ownership is written as `uid=N gid=N` attributes at the end of
+    /// file and directory lines, and the header gets `ownership=yes`
+    /// attribute. This is an extension to the v1 format: older versions of
+    /// this library fail to parse such index files. Also ownership is
+    /// included in the footer hash, so the same directory indexed with and
+    /// without this option produces different image ids.
+    pub fn store_ownership(&mut self) -> &mut Self {
+        self.store_ownership = true;
+        self
+    }
+    /// Set what to do when the same path is found in several directories
+    ///
+    /// When multiple directories are added with the same prefix, their
+    /// contents are overlaid. Directories existing in several sources are
+    /// merged, but a file (or a symlink) can be taken only from one source.
+    /// By default the directory added last wins, use
+    /// `CollisionPolicy::Error` to detect such overlaps.
+    pub fn on_collision(&mut self, policy: CollisionPolicy) -> &mut Self {
+        self.on_collision = policy;
+        self
+    }
+    /// Normalize destinations of symlinks
+    ///
+    /// Absolute destinations are treated relative to the root of the image
+    /// (not the real root of the file system), as if the image was
+    /// mounted or chrooted. With `TargetMode::Relative` a link `/a/b/link`
+    /// pointing to `/a/c` is written as `../c`, with `TargetMode::Absolute`
+    /// a link `/a/b/link` pointing to `../c` is written as `/a/c`. Paths
+    /// are normalized lexically: `.` and `..` components are resolved
+    /// without looking at other symlinks, and `..` never goes above the root.
+    ///
+    /// Note that index reflects the converted destination, so the same
+    /// directory indexed with different modes might have a different image
+    /// id, and verifying such index against the original directory reports
+    /// symlinks as changed.
+    pub fn symlink_targets(&mut self, mode: TargetMode) -> &mut Self {
+        self.symlink_targets = mode;
+        self
+    }
+    /// Choose which permission bits mark a file as executable
+    ///
+    /// By default (`ExeBitMode::OwnerOnly`) only the owner-execute bit is
+    /// checked, so a file with mode `0o654` is written as a regular file.
+    /// With `ExeBitMode::AnyExecute` a file having any execute bit set is
+    /// written as executable.
+    ///
+    /// The executable flag is part of the index, so the same directory
+    /// scanned with different modes may get a different image id. Also
+    /// note that [`verify`](v1/verify/index.html) always checks the
+    /// owner-execute bit.
+    pub fn exe_bit(&mut self, mode: ExeBitMode) -> &mut Self {
+        self.exe_bit = mode;
+        self
+    }
+    /// Record overlay whiteouts instead of regular files
+    ///
+    /// With this option a regular file named `.wh.<name>` is written to
+    /// the index as a whiteout entry for `<name>` (this is the naming
+    /// used by container image layers), which means that `<name>` is
+    /// removed in this layer. Whiteouts are applied when layers are merged
+    /// with [`merge::union`](v1/merge/fn.union.html). Opaque directory
+    /// markers (`.wh..wh..opq`) are not supported and are indexed as
+    /// regular files.
+    ///
+    /// This is an extension to the v1 format: older versions of this
+    /// library fail to parse index files containing whiteouts.
+    pub fn overlay_whiteouts(&mut self) -> &mut Self {
+        self.overlay_whiteouts = true;
+        self
+    }
+    /// Prefix the footer with `=`, so it can't be confused with an entry
+    ///
+    /// By default footer is just a hex hash, so a streaming reader can only
+    /// recognize it as a line that is not an entry. With this option
+    /// header gets `footer=marked` attribute and the footer line starts
+    /// with `=`. The footer hash itself is the same. This is an extension to
+    /// the v1 format: older versions of this library fail to parse such
+    /// index files, while this version parses both kinds of footers.
+    pub fn marked_footer(&mut self) -> &mut Self {
+        self.marked_footer = true;
+        self
+    }
+    /// Collect paths of the files that were not written to the index
+    ///
+    /// These are files of unknown type (fifos, sockets, devices) and
+    /// files skipped because of [`mtime_cutoff`](#method.mtime_cutoff).
+    /// Paths are returned in
+    /// [`ScanStats::skipped`](v1/struct.ScanStats.html#structfield.skipped)
+    /// by [`v1::scan_with_stats`](v1/fn.scan_with_stats.html). Note that
+    /// unreadable files are not skipped, they fail the whole scan.
+    pub fn collect_skipped(&mut self) -> &mut Self {
+        self.collect_skipped = true;
+        self
+    }
+    /// Omit directories that have no files in them
+    ///
+    /// A directory is omitted if it has no files, symlinks or whiteouts,
+    /// recursively, i.e. a tree of nested empty directories is omitted
+    /// entirely. The root directory is always written.
+    ///
+    /// Note that index doesn't have such directories at all, so the image
+    /// id is different from the one of the index without this option, and
+    /// verifying the index against the original directory reports them
+    /// as extra.
+    pub fn prune_empty_dirs(&mut self) -> &mut Self {
+        self.prune_empty_dirs = true;
+        self
+    }
+    /// Don't descend into directories deeper than `depth` levels
+    ///
+    /// Root directory is at the level zero, so with `max_depth(0)` only
+    /// the files of the root directory are indexed, with `max_depth(1)`
+    /// also files in its direct subdirectories, and so on. Deeper
+    /// directories are not written to the index at all.
+    ///
+    /// This changes the image id, and verifying such index against the
+    /// original directory reports deeper directories as extra.
+    pub fn max_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_depth = Some(depth);
+        self
+    }
     /// Add a directory to the index
     ///
     /// `prefix` should either be `/` or a subdirectory where indexed files
@@ -78,4 +310,23 @@ impl ScannerConfig {
         self.print_progress = true;
         self
     }
+    /// Set how progress is printed
+    ///
+    /// By default progress is a single line rewritten in place using
+    /// carriage returns, which looks fine in a terminal but clutters logs
+    /// when stderr is redirected to a file. Use `ProgressFormat::Lines` to
+    /// print every update as a separate line. Only makes sense with
+    /// [`print_progress`](#method.print_progress).
+    pub fn progress_format(&mut self, format: ProgressFormat) -> &mut Self {
+        self.progress_format = format;
+        self
+    }
+    /// Set minimum interval between progress updates
+    ///
+    /// Default is 100 milliseconds. Consider a larger interval with
+    /// `ProgressFormat::Lines`, as every update adds a line to the output.
+    pub fn progress_interval(&mut self, interval: Duration) -> &mut Self {
+        self.progress_interval = interval;
+        self
+    }
 }
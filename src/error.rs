@@ -1,4 +1,7 @@
 use std::io;
+use std::path::PathBuf;
+
+use crate::v1::ParseError;
 
 // TODO(tailhook) should we split it?
 quick_error! {
@@ -29,11 +32,62 @@ quick_error! {
         // TODO(tailhook) lift this restriction
         NoRootDirectory {
             description("no root directory to build index for")
+            display("no root directory to build index for: \
+                     add a directory with `/` prefix, \
+                     i.e. `add_dir(path, \"/\")`")
+        }
+        /// Same path exists in several source directories
+        ///
+        /// Only returned with `CollisionPolicy::Error`
+        PathCollision(path: PathBuf) {
+            description("path exists in several source directories")
+            display("path {:?} exists in several source directories", path)
+        }
+        /// Prefix to split the index by is not a directory in the index
+        NotADirectory(path: PathBuf) {
+            description("prefix is not a directory")
+            display("prefix {:?} is not a directory in the index", path)
         }
         /// Unsupported hash algorithm
         UnsupportedHash {
             description("Unsupported hash algorithm")
         }
+        /// Invalid image id
+        InvalidImageId(msg: String) {
+            description("invalid image id")
+            display("invalid image id: {}", msg)
+        }
+        /// Raw hashes don't match the hash type
+        InvalidHashes(msg: String) {
+            description("invalid hashes")
+            display("invalid hashes: {}", msg)
+        }
+        /// Footer of the index doesn't match its contents
+        ///
+        /// Contains the hash in the footer and the one computed from data
+        FooterMismatch(expected: Vec<u8>, actual: Vec<u8>) {
+            description("footer doesn't match the index")
+            display("footer doesn't match the index, it's either corrupt \
+                     or tampered with")
+        }
+        /// Block size is zero
+        ZeroBlockSize {
+            description("block size must be positive")
+        }
+        /// Average size of content-defined chunks is out of range
+        ///
+        /// See [`v1::cdc::MIN_AVG_SIZE`](v1/cdc/constant.MIN_AVG_SIZE.html)
+        /// and [`v1::cdc::MAX_AVG_SIZE`](v1/cdc/constant.MAX_AVG_SIZE.html)
+        InvalidChunkSize(size: u64) {
+            description("average chunk size is out of range")
+            display("average chunk size {} is out of range", size)
+        }
+        /// Error parsing index
+        Parse(err: ParseError) {
+            description("error parsing index")
+            display("error parsing index: {}", err)
+            from()
+        }
         #[doc(hidden)]
         __Nonexhaustive
     }
@@ -21,6 +21,48 @@ impl HashType {
         HashType(HashTypeEnum::Blake3_256)
     }
 
+    /// All supported hash types
+    pub fn all() -> &'static [HashType] {
+        &[
+            HashType(HashTypeEnum::Sha512_256),
+            HashType(HashTypeEnum::Blake2b_256),
+            HashType(HashTypeEnum::Blake3_256),
+        ]
+    }
+
+    /// Returns a single byte code of the hash type for binary formats
+    ///
+    /// Codes are stable across releases: `1` is `sha512/256`, `2` is
+    /// `blake2b/256` and `3` is `blake3/256`. Zero is never used.
+    pub fn to_code(self) -> u8 {
+        match self.0 {
+            HashTypeEnum::Sha512_256 => 1,
+            HashTypeEnum::Blake2b_256 => 2,
+            HashTypeEnum::Blake3_256 => 3,
+        }
+    }
+
+    /// Returns a hash type by the code returned by [`to_code`](#method.to_code)
+    ///
+    /// Returns `None` for unknown codes.
+    pub fn from_code(code: u8) -> Option<HashType> {
+        match code {
+            1 => Some(HashType(HashTypeEnum::Sha512_256)),
+            2 => Some(HashType(HashTypeEnum::Blake2b_256)),
+            3 => Some(HashType(HashTypeEnum::Blake3_256)),
+            _ => None,
+        }
+    }
+
+    /// Name of the hash type as written in the header
+    pub(crate) fn name(self) -> &'static str {
+        match self.0 {
+            HashTypeEnum::Sha512_256 => "sha512/256",
+            HashTypeEnum::Blake2b_256 => "blake2b/256",
+            HashTypeEnum::Blake3_256 => "blake3/256",
+        }
+    }
+
     /// Get the digest size in bytes
     pub fn output_bytes(self) -> usize {
         match self.0 {
@@ -46,10 +88,23 @@ impl FromStr for HashType {
 
 impl fmt::Display for HashType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.0 {
-            HashTypeEnum::Sha512_256 => "sha512/256",
-            HashTypeEnum::Blake2b_256 => "blake2b/256",
-            HashTypeEnum::Blake3_256 => "blake3/256",
-        }.fmt(f)
+        self.name().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::HashType;
+
+    #[test]
+    fn code_roundtrip() {
+        for &typ in HashType::all() {
+            assert_eq!(HashType::from_code(typ.to_code()), Some(typ));
+        }
+        assert_eq!(HashType::sha512_256().to_code(), 1);
+        assert_eq!(HashType::blake2b_256().to_code(), 2);
+        assert_eq!(HashType::blake3_256().to_code(), 3);
+        assert_eq!(HashType::from_code(0), None);
+        assert_eq!(HashType::from_code(4), None);
     }
 }
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::hint;
+use std::str::{self, FromStr};
+
+use crate::{Error, HashType, ImageId};
+
+
+impl ImageId {
+    /// Create an image id from the raw hash bytes
+    ///
+    /// Fails if length of the `hash` doesn't match the hash type
+    pub fn new(hash_type: HashType, hash: Vec<u8>) -> Result<ImageId, Error> {
+        if hash.len() != hash_type.output_bytes() {
+            return Err(Error::InvalidImageId(format!(
+                "expected {} bytes for {} but got {}",
+                hash_type.output_bytes(), hash_type, hash.len())));
+        }
+        Ok(ImageId(hash_type, hash))
+    }
+
+    /// Hash type that was used to calculate this image id
+    pub fn hash_type(&self) -> HashType {
+        self.0
+    }
+
+    /// Raw bytes of the hash
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.1
+    }
+
+    /// Number of bytes in the hash
+    pub fn len(&self) -> usize {
+        self.1.len()
+    }
+
+    /// Returns `true` if the hash has no bytes (never true for a valid id)
+    pub fn is_empty(&self) -> bool {
+        self.1.is_empty()
+    }
+
+    /// Compares image ids in constant time
+    ///
+    /// Use it instead of `==` when checking a downloaded index against the
+    /// expected id. See [`constant_time_eq`](fn.constant_time_eq.html).
+    pub fn ct_eq(&self, other: &ImageId) -> bool {
+        self.0 == other.0 && constant_time_eq(&self.1, &other.1)
+    }
+}
+
+/// Compares two hashes in time that doesn't depend on their contents
+///
+/// This avoids timing side channels when validating an image id of
+/// a fetched index against an expected one. Only the contents are
+/// protected, hashes of different length are unequal right away.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b)
+        .fold(0u8, |acc, (x, y)| acc | hint::black_box(x ^ y));
+    diff == 0
+}
+
+/// Formats as a bare hex hash, as written in the footer of the index
+impl fmt::LowerHex for ImageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &b in &self.1 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats as `<hash_type>:<hex>`, e.g. `blake3/256:57ba1f...`
+///
+/// Formatting flags (width, precision) are ignored, so the result can
+/// always be parsed back with `FromStr`.
+impl fmt::Display for ImageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.0.name())?;
+        for &b in &self.1 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the image id in the `<hash_type>:<hex>` format produced by
+/// `Display`
+impl FromStr for ImageId {
+    type Err = Error;
+    fn from_str(val: &str) -> Result<ImageId, Error> {
+        let (hash_type, hex) = val.split_once(':').ok_or_else(|| {
+            Error::InvalidImageId(format!(
+                "expected `<hash_type>:<hex>` but there is no `:` in {:?}",
+                val))
+        })?;
+        let hash_type = hash_type.parse::<HashType>()?;
+        if hex.is_empty() {
+            return Err(Error::InvalidImageId(
+                format!("no hash after `:` in {:?}", val)));
+        }
+        if hex.len() % 2 != 0 {
+            return Err(Error::InvalidImageId(
+                format!("odd number of hex digits in {:?}", val)));
+        }
+        let hash = hex.as_bytes().chunks(2)
+            .map(|d| {
+                str::from_utf8(d).ok()
+                .and_then(|d| u8::from_str_radix(d, 16).ok())
+                .ok_or_else(|| Error::InvalidImageId(
+                    format!("invalid hex in {:?}", val)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        ImageId::new(hash_type, hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{HashType, ImageId, constant_time_eq};
+
+    const HEX: &str =
+        "552ca5730ee95727e890a2155c88609d244624034ff70de264cf88220d11d6df";
+
+    #[test]
+    fn display_parse() {
+        let id = format!("blake3/256:{}", HEX).parse::<ImageId>().unwrap();
+        assert_eq!(id.hash_type(), HashType::blake3_256());
+        assert_eq!(id.len(), 32);
+        assert_eq!(&id.as_bytes()[..2], &[0x55, 0x2c]);
+        assert_eq!(format!("{:x}", id), HEX);
+        assert_eq!(id.to_string(), format!("blake3/256:{}", HEX));
+        assert_eq!(id.to_string().parse::<ImageId>().unwrap(), id);
+        assert_eq!(format!("{:>100.3}", id), id.to_string());
+        for hash_type in [HashType::sha512_256(), HashType::blake2b_256(),
+                          HashType::blake3_256()]
+        {
+            let id = ImageId::new(hash_type, vec![0xab; 32]).unwrap();
+            assert_eq!(id.to_string(),
+                       format!("{}:{}", hash_type, "ab".repeat(32)));
+            assert_eq!(id.to_string().parse::<ImageId>().unwrap(), id);
+        }
+    }
+
+    fn error(val: &str) -> String {
+        val.parse::<ImageId>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(error(HEX).contains("there is no `:`"), "{}", error(HEX));
+        assert!(error("sha512/256:").contains("no hash after `:`"));
+        assert!(format!("sha512/256:{}", &HEX[..62])
+            .parse::<ImageId>().is_err());
+        assert!(format!("sha512/256:{}0", HEX).parse::<ImageId>().is_err());
+        assert!(format!("sha512/256:x{}", &HEX[1..])
+            .parse::<ImageId>().is_err());
+        assert!(format!("md5:{}", HEX).parse::<ImageId>().is_err());
+        assert!(ImageId::new(HashType::sha512_256(), vec![0; 31]).is_err());
+    }
+
+    #[test]
+    fn equality() {
+        let a = ImageId::new(HashType::sha512_256(), vec![1; 32]).unwrap();
+        let b = ImageId::new(HashType::blake2b_256(), vec![1; 32]).unwrap();
+        assert_eq!(a, a.clone());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn constant_time() {
+        let a = ImageId::new(HashType::sha512_256(), vec![1; 32]).unwrap();
+        let mut other = vec![1; 32];
+        other[31] = 2;
+        let b = ImageId::new(HashType::sha512_256(), other).unwrap();
+        assert!(a.ct_eq(&a.clone()));
+        assert!(!a.ct_eq(&b));
+        assert!(constant_time_eq(a.as_bytes(), a.as_bytes()));
+        assert!(!constant_time_eq(a.as_bytes(), b.as_bytes()));
+        assert!(!constant_time_eq(a.as_bytes(), &b.as_bytes()[..31]));
+        let c = ImageId::new(HashType::blake3_256(), vec![1; 32]).unwrap();
+        assert!(!a.ct_eq(&c));
+    }
+}
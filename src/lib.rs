@@ -32,31 +32,112 @@
 #[cfg(test)] #[macro_use] extern crate matches;
 
 pub mod v1;
+#[cfg(feature="test-util")] pub mod testutil;
 mod error;
 mod config;
 mod hash_type;
+mod image_id;
 mod read;
 
 pub use crate::error::Error;
-pub use crate::read::get_hash;
+pub use crate::read::{get_hash, get_hash_short, get_image_id};
+pub use crate::image_id::constant_time_eq;
 
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Block size used by `ScannerConfig` by default
+///
+/// This is also the only block size every implementation of the v1 format
+/// is required to support.
+pub const DEFAULT_BLOCK_SIZE: u64 = 32768;
 
 /// Scanner config contains a list of directories you will scan and other
 /// settings that influence filesystem scanning
+#[derive(Clone)]
 pub struct ScannerConfig {
     threads: usize,
     queue_size: Option<usize>,
     hash: HashType,
     block_size: u64,
+    auto_block_size: bool,
+    content_defined_chunks: bool,
+    io_retries: u32,
+    stable_read: u32,
+    mtime_cutoff: Option<SystemTime>,
+    store_ownership: bool,
+    on_collision: CollisionPolicy,
+    symlink_targets: TargetMode,
+    exe_bit: ExeBitMode,
+    overlay_whiteouts: bool,
+    marked_footer: bool,
+    collect_skipped: bool,
+    prune_empty_dirs: bool,
+    max_depth: Option<usize>,
     dirs: Vec<(PathBuf, PathBuf)>,
     print_progress: bool,
+    progress_format: ProgressFormat,
+    progress_interval: Duration,
+}
+
+/// What to do when several source directories contain the same path
+///
+/// See [`ScannerConfig::on_collision`](struct.ScannerConfig.html#method.on_collision)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CollisionPolicy {
+    /// Entry from the directory added last overrides others (default)
+    LastWins,
+    /// Entry from the directory added first overrides others
+    FirstWins,
+    /// Fail the scan with `Error::PathCollision`
+    Error,
+}
+
+/// How destinations of symlinks are written to the index
+///
+/// See [`ScannerConfig::symlink_targets`](struct.ScannerConfig.html#method.symlink_targets)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TargetMode {
+    /// Write destination as is (default)
+    Verbatim,
+    /// Convert absolute destinations to relative ones
+    Relative,
+    /// Convert relative destinations to absolute ones
+    Absolute,
+}
+
+/// Which permission bits make a file executable in the index
+///
+/// See [`ScannerConfig::exe_bit`](struct.ScannerConfig.html#method.exe_bit)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExeBitMode {
+    /// Only owner-execute bit (`0o100`) is checked (default)
+    OwnerOnly,
+    /// Any of the execute bits (`0o111`) makes file executable
+    AnyExecute,
+}
+
+/// How progress is printed to stderr
+///
+/// See [`ScannerConfig::progress_format`](struct.ScannerConfig.html#method.progress_format)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProgressFormat {
+    /// Single line updated in place using `\r` (default)
+    Overwrite,
+    /// Every update is a separate line, suitable for log files
+    Lines,
 }
 
 /// A type of hash supported by the library
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct HashType(HashTypeEnum);
 
+/// Hash of the whole index file (the last line of the file)
+///
+/// It serves as an identifier of the image/directory.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(HashType, Vec<u8>);
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 enum HashTypeEnum {
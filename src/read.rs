@@ -1,7 +1,7 @@
 use std::str::{from_utf8, FromStr};
 use std::io::{self, Read, Seek, SeekFrom};
 
-use crate::{HashType};
+use crate::{HashType, ImageId};
 
 
 fn hex_to_digit(v: u8) -> Option<u8> {
@@ -17,41 +17,86 @@ fn hex_to_digit(v: u8) -> Option<u8> {
 /// That hash is a last line of the index file. It may serve either as a
 /// checksum of the file or as identifier if this image/directory
 pub fn get_hash<F: Read+Seek>(f: &mut F) -> Result<Vec<u8>, io::Error> {
+    read_hash(f).map(|(_, hash)| hash)
+}
+
+/// Get a short identifier of an index file
+///
+/// Returns the first `hex_chars` lowercase hex digits of the hash returned by
+/// [`get_hash`](fn.get_hash.html) (the whole hash if it's shorter), like
+/// short commit ids in git.
+///
+/// Note that short ids are much more likely to collide: `hex_chars` digits
+/// hold `4 * hex_chars` bits, so a collision becomes likely after about
+/// `2 ^ (2 * hex_chars)` images, i.e. tens of thousands of images for
+/// 8 digits. Use them for display only, not for identifying images.
+pub fn get_hash_short<F: Read+Seek>(f: &mut F, hex_chars: usize)
+    -> Result<String, io::Error>
+{
+    let hash = get_hash(f)?;
+    let mut result = String::with_capacity(hex_chars);
+    for b in hash.iter().take(hex_chars.div_ceil(2)) {
+        result.push_str(&format!("{:02x}", b));
+    }
+    result.truncate(hex_chars);
+    Ok(result)
+}
+
+/// Get an image id from an index file
+///
+/// This is the same as `get_hash` but also returns the hash type read from
+/// the header of the file.
+pub fn get_image_id<F: Read+Seek>(f: &mut F) -> Result<ImageId, io::Error> {
+    let (hash_type, hash) = read_hash(f)?;
+    ImageId::new(hash_type, hash)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_hash<F: Read+Seek>(f: &mut F)
+    -> Result<(HashType, Vec<u8>), io::Error>
+{
     let einval = io::ErrorKind::InvalidData;
     let mut signature = [0u8; 32];
     f.read(&mut signature)?;
     if &signature[..16] != b"DIRSIGNATURE.v1 " {
         return Err(einval.into());
     }
-    let hash = signature[16..].iter().position(|&x| x == b' ')
+    let hash_type = signature[16..].iter().position(|&x| x == b' ')
         .and_then(|e| from_utf8(&signature[16..16+e]).ok())
         .and_then(|s| HashType::from_str(s).ok())
         .ok_or(einval)?;
 
-    let nbytes = hash.output_bytes()*2+2;
+    // newline, optional `=` marker, hash and a newline
+    let nbytes = hash_type.output_bytes()*2+3;
     f.seek(SeekFrom::End(- (nbytes as i64)))?;
     let mut buf = [0u8; 100];
     assert!(buf.len() >= nbytes);
     if f.read(&mut buf)? != nbytes {
         return Err(io::ErrorKind::UnexpectedEof.into());
     }
-    if buf[0] != b'\n' || buf[nbytes-1] != b'\n' {
+    if buf[nbytes-1] != b'\n' {
         return Err(einval.into());
     }
-    let mut hash = Vec::with_capacity(hash.output_bytes());
-    for d in buf[1..nbytes-1].chunks(2) {
+    let start = match (buf[0], buf[1]) {
+        (b'\n', b'=') => 2,
+        (_, b'\n') => 2,
+        _ => return Err(einval.into()),
+    };
+    let mut hash = Vec::with_capacity(hash_type.output_bytes());
+    for d in buf[start..nbytes-1].chunks(2) {
         hash.push(
             (hex_to_digit(d[0]).ok_or(einval)? << 4)
             | hex_to_digit(d[1]).ok_or(einval)?);
     }
 
-    return Ok(hash);
+    Ok((hash_type, hash))
 }
 
 #[cfg(test)]
 mod test {
-    use super::get_hash;
+    use super::{get_hash, get_hash_short, get_image_id};
     use std::io::Cursor;
+    use crate::HashType;
 
     const DATA: &'static [u8] = b"\
 DIRSIGNATURE.v1 sha512/256 block_size=32768
@@ -73,4 +118,32 @@ DIRSIGNATURE.v1 sha512/256 block_size=32768
                  0x26, 0x05, 0x29, 0x97, 0xf7, 0x03, 0x28, 0xd7, 0xb0,
                  0x7a, 0xe4, 0xdd, 0x6e, 0xac]);
     }
+
+    #[test]
+    fn read_short() {
+        assert_eq!(get_hash_short(&mut Cursor::new(DATA), 8).unwrap(),
+                   "11928917");
+        assert_eq!(get_hash_short(&mut Cursor::new(DATA), 7).unwrap(),
+                   "1192891");
+        assert_eq!(get_hash_short(&mut Cursor::new(DATA), 100).unwrap(),
+            "11928917e3e44838af46bad1c7a43a8c16eb26052997f70328d7b07ae4dd6eac");
+    }
+
+    #[test]
+    fn read_marked() {
+        let mut data = DATA.to_vec();
+        let pos = data[..data.len()-1].iter().rposition(|&b| b == b'\n')
+            .unwrap();
+        data.insert(pos + 1, b'=');
+        assert_eq!(get_hash(&mut Cursor::new(&data)).unwrap(),
+                   get_hash(&mut Cursor::new(DATA)).unwrap());
+    }
+
+    #[test]
+    fn read_image_id() {
+        let id = get_image_id(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(id.hash_type(), HashType::sha512_256());
+        assert_eq!(format!("{:x}", id),
+            "11928917e3e44838af46bad1c7a43a8c16eb26052997f70328d7b07ae4dd6eac");
+    }
 }
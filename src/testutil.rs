@@ -0,0 +1,109 @@
+//! Helpers for building directory trees in tests
+//!
+//! Requires `test-util` feature. Trees are created in a fresh temporary
+//! directory, so tests don't depend on the checked-in fixtures and on the
+//! permissions they got when checked out.
+//!
+//! ```rust
+//! # use dir_signature::{ScannerConfig, v1};
+//! use dir_signature::testutil::TreeBuilder;
+//!
+//! let tree = TreeBuilder::new()
+//!     .file("hello.txt", b"hello\n")
+//!     .exe("bin/run", b"#!/bin/sh\n")
+//!     .symlink("bin/hello", "../hello.txt")
+//!     .build().unwrap();
+//! let mut cfg = ScannerConfig::new();
+//! cfg.add_dir(tree.path(), "/");
+//! let (index, image_id) = v1::scan_to_vec(&cfg).unwrap();
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+
+/// A kind of the entry to create
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File { contents: Vec<u8>, exe: bool },
+    Symlink(PathBuf),
+}
+
+/// Declarative spec of a directory tree
+///
+/// Paths are relative to the root of the tree, parent directories are
+/// created automatically. Entries are created in the order they are added.
+#[derive(Debug, Clone, Default)]
+pub struct TreeBuilder {
+    nodes: Vec<(PathBuf, Node)>,
+}
+
+impl TreeBuilder {
+    /// Create an empty tree spec
+    pub fn new() -> TreeBuilder {
+        TreeBuilder::default()
+    }
+    /// Add a (possibly empty) directory
+    pub fn dir<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.nodes.push((path.as_ref().to_path_buf(), Node::Dir));
+        self
+    }
+    /// Add a regular file with mode `0o644`
+    pub fn file<P: AsRef<Path>>(&mut self, path: P, contents: &[u8])
+        -> &mut Self
+    {
+        self.nodes.push((path.as_ref().to_path_buf(),
+            Node::File { contents: contents.to_vec(), exe: false }));
+        self
+    }
+    /// Add an executable file with mode `0o755`
+    pub fn exe<P: AsRef<Path>>(&mut self, path: P, contents: &[u8])
+        -> &mut Self
+    {
+        self.nodes.push((path.as_ref().to_path_buf(),
+            Node::File { contents: contents.to_vec(), exe: true }));
+        self
+    }
+    /// Add a symlink pointing to `dest`
+    pub fn symlink<P, D>(&mut self, path: P, dest: D) -> &mut Self
+        where P: AsRef<Path>, D: AsRef<Path>,
+    {
+        self.nodes.push((path.as_ref().to_path_buf(),
+            Node::Symlink(dest.as_ref().to_path_buf())));
+        self
+    }
+    /// Create the tree in a new temporary directory
+    ///
+    /// The directory is removed when returned `TempDir` is dropped, use
+    /// `TempDir::path` to get its path.
+    pub fn build(&self) -> io::Result<TempDir> {
+        let dir = tempfile::tempdir()?;
+        self.build_in(dir.path())?;
+        Ok(dir)
+    }
+    /// Create the tree in an existing directory
+    pub fn build_in(&self, root: &Path) -> io::Result<()> {
+        for (path, node) in &self.nodes {
+            let path = root.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match *node {
+                Node::Dir => fs::create_dir_all(&path)?,
+                Node::File { ref contents, exe } => {
+                    let mut f = File::create(&path)?;
+                    f.write_all(contents)?;
+                    let mode = if exe { 0o755 } else { 0o644 };
+                    f.set_permissions(fs::Permissions::from_mode(mode))?;
+                }
+                Node::Symlink(ref dest) => symlink(dest, &path)?,
+            }
+        }
+        Ok(())
+    }
+}
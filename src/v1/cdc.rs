@@ -0,0 +1,141 @@
+//! Content-defined chunking of files
+//!
+//! Signature files store hashes of fixed-size blocks, so inserting a single
+//! byte into the file changes hashes of all subsequent blocks. With
+//! content-defined chunking boundaries of chunks are chosen by the data
+//! itself (using a rolling "gear" hash like in FastCDC), so after an edit
+//! only chunks around the edited place change.
+//!
+//! Signature files use these chunks instead of fixed-size blocks when
+//! created with
+//! [`ScannerConfig::content_defined_chunks`](../../struct.ScannerConfig.html#method.content_defined_chunks)
+//! (see "Content-Defined Chunking Extension" in `FORMAT.v1.rst`). This
+//! module can also be used directly by tools that want to deduplicate data.
+
+use std::io::{self, Read};
+
+use crate::{Error, HashType};
+use crate::v1::hash::hash_bytes;
+
+/// Minimum average chunk size
+pub const MIN_AVG_SIZE: u64 = 64;
+/// Maximum average chunk size (chunks are at most four times larger)
+pub const MAX_AVG_SIZE: u64 = 64 << 20;
+
+
+/// A single chunk of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Offset of the chunk in the file
+    pub offset: u64,
+    /// Length of the chunk in bytes
+    pub length: u64,
+    /// Hash of the chunk data
+    pub hash: Vec<u8>,
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, the table must never change as it defines boundaries
+    let mut table = [0u64; 256];
+    let mut state = 0x6469727369676e61u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Returns the length of the first chunk of `data`
+///
+/// `data` must contain at least `max` bytes unless it's the end of file.
+fn cut_point(data: &[u8], min: usize, avg: usize, max: usize) -> usize {
+    if data.len() <= min {
+        return data.len();
+    }
+    let end = data.len().min(max);
+    let normal = end.min(avg);
+    let bits = 63 - (avg as u64).leading_zeros();
+    // normalized chunking: harder to cut before average size, easier after
+    let mask_hard = !0u64 << (64 - (bits + 1));
+    let mask_easy = !0u64 << (64 - (bits - 1));
+    let mut hash = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(normal).skip(min) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if hash & mask_hard == 0 {
+            return i + 1;
+        }
+    }
+    for (i, &byte) in data.iter().enumerate().take(end).skip(normal) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if hash & mask_easy == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Splits the data into content-defined chunks and hashes them
+///
+/// `avg_size` is the desired average size of the chunk, it's rounded down
+/// to a power of two. Chunks are at least a quarter and at most four times
+/// the average size (except the last one which may be smaller). Each chunk
+/// is hashed separately (with [`hash_bytes`](../fn.hash_bytes.html)), so
+/// the same data has the same hash wherever it is.
+///
+/// Returns `Error::InvalidChunkSize` if `avg_size` is not in the
+/// `MIN_AVG_SIZE..=MAX_AVG_SIZE` range.
+pub fn chunks<R: Read>(hash_type: HashType, avg_size: u32, r: R)
+    -> Result<Vec<Chunk>, Error>
+{
+    let avg_size = check_avg_size(avg_size.into())?;
+    read_chunks(hash_type, avg_size, r).map_err(Error::ReadFile)
+}
+
+/// Checks that the average chunk size is supported
+pub(crate) fn check_avg_size(avg_size: u64) -> Result<u32, Error> {
+    if !(MIN_AVG_SIZE..=MAX_AVG_SIZE).contains(&avg_size) {
+        return Err(Error::InvalidChunkSize(avg_size));
+    }
+    Ok(avg_size as u32)
+}
+
+/// Same as `chunks`, but `avg_size` must be already checked
+pub(crate) fn read_chunks<R: Read>(hash_type: HashType, avg_size: u32,
+    mut r: R)
+    -> io::Result<Vec<Chunk>>
+{
+    let avg = 1usize << (31 - avg_size.leading_zeros());
+    let (min, max) = (avg / 4, avg * 4);
+    let mut result = Vec::new();
+    let mut buf = Vec::with_capacity(max);
+    let mut offset = 0;
+    let mut eof = false;
+    loop {
+        while !eof && buf.len() < max {
+            let len = buf.len();
+            buf.resize(max, 0);
+            let n = r.read(&mut buf[len..])?;
+            buf.truncate(len + n);
+            eof = n == 0;
+        }
+        if buf.is_empty() {
+            break;
+        }
+        let len = cut_point(&buf, min, avg, max);
+        result.push(Chunk {
+            offset,
+            length: len as u64,
+            hash: hash_bytes(hash_type, &buf[..len]),
+        });
+        offset += len as u64;
+        buf.drain(..len);
+    }
+    Ok(result)
+}
@@ -0,0 +1,60 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+use crate::Error::{self, ReadFile as ERead, WriteError as EWrite};
+use crate::v1::hash::digest;
+use crate::v1::parser::{parse_header, parse_footer, strip_newline};
+use crate::v1::parser::{row_error, extra_lines_error, ParseRowError};
+
+
+/// Copies the index from `src` to `dst` checking the footer on the way
+///
+/// The data is hashed while being copied and the hash is compared to the
+/// footer at the end of the stream, so the index downloaded over the
+/// network can be validated in a single pass. Returns the hash (i.e. the
+/// image id) if it matches and `Error::FooterMismatch` otherwise.
+///
+/// Only the header line and the footer are parsed, entries are not
+/// checked. Note that all the data, including the footer, is already
+/// written to `dst` when the error is returned, so `dst` should be
+/// discarded on error.
+pub fn copy_verified<R: Read, W: Write>(src: R, mut dst: W)
+    -> Result<Vec<u8>, Error>
+{
+    let mut src = BufReader::new(src);
+    let mut line = Vec::new();
+    src.read_until(b'\n', &mut line).map_err(ERead)?;
+    dst.write_all(&line).map_err(EWrite)?;
+    strip_newline(&mut line).map_err(|e| row_error(1, e))?;
+    let hash_type = parse_header(&line, false)?.get_hash_type();
+
+    let mut row_num = 1;
+    let mut footer = None;
+    let actual = digest(hash_type, |hasher| -> Result<(), Error> {
+        loop {
+            row_num += 1;
+            line.clear();
+            if src.read_until(b'\n', &mut line).map_err(ERead)? == 0 {
+                return Ok(());
+            }
+            dst.write_all(&line).map_err(EWrite)?;
+            if footer.is_some() {
+                return Err(extra_lines_error(row_num).into());
+            }
+            if line.starts_with(b"/") || line.starts_with(b"  ") {
+                hasher.write_all(&line)
+                    .expect("writing to hasher never fails");
+            } else {
+                strip_newline(&mut line)
+                    .map_err(|e| row_error(row_num, e))?;
+                footer = Some(parse_footer(&line, hash_type)
+                    .map_err(|e| row_error(row_num, e))?);
+            }
+        }
+    })?;
+    let expected = footer.ok_or_else(|| row_error(row_num,
+        ParseRowError::InvalidLine("Missing footer".into())))?;
+    if expected != actual {
+        return Err(Error::FooterMismatch(expected, actual));
+    }
+    Ok(actual)
+}
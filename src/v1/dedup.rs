@@ -0,0 +1,57 @@
+//! A module for estimating block-level deduplication of an index
+//!
+//!
+//! Entry points:
+//!
+//! * [`unique_blocks`](fn.unique_blocks.html) for getting a set of distinct
+//!   block hashes
+//! * [`block_refcounts`](fn.block_refcounts.html) for counting how many times
+//!   each block is referenced
+
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+use super::{Entry, Parser, ParseError};
+
+
+/// Returns the set of distinct block hashes of all files in the index
+///
+/// This is the set of blocks a content-addressable store has to keep to
+/// be able to reconstruct every file of the index.
+pub fn unique_blocks<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<HashSet<Vec<u8>>, ParseError>
+{
+    let mut blocks = HashSet::new();
+    for entry in parser.iter() {
+        if let Entry::File { hashes, .. } = entry? {
+            for hash in hashes.iter() {
+                if !blocks.contains(hash) {
+                    blocks.insert(hash.to_vec());
+                }
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Returns number of references to each distinct block hash in the index
+///
+/// Sum of the values is the total number of blocks in the index, and the
+/// number of keys is the number of unique blocks.
+pub fn block_refcounts<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<HashMap<Vec<u8>, usize>, ParseError>
+{
+    let mut counts = HashMap::new();
+    for entry in parser.iter() {
+        if let Entry::File { hashes, .. } = entry? {
+            for hash in hashes.iter() {
+                if let Some(count) = counts.get_mut(hash) {
+                    *count += 1;
+                    continue;
+                }
+                counts.insert(hash.to_vec(), 1);
+            }
+        }
+    }
+    Ok(counts)
+}
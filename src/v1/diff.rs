@@ -0,0 +1,336 @@
+//! A module for comparing two signature files
+//!
+//!
+//! Entry points:
+//!
+//! * [`Diff`](struct.Diff.html) iterator over differences
+//! * [`BlockDiffs`](struct.BlockDiffs.html) iterator over changed blocks
+//!   of files
+//! * [`diff_stream`](fn.diff_stream.html) for writing differences as text
+//! * [`size_delta`](fn.size_delta.html) for a change of the total size
+//! * [`append_only_changes`](fn.append_only_changes.html) for files that
+//!   were only appended to
+//! * [`scan_patch`](fn.scan_patch.html) for comparing a directory with
+//!   a prior signature file
+//! * [`detect_renames`](fn.detect_renames.html) for files that were moved
+//!
+//! Both signature files are read sequentially at the same time, so memory
+//! usage doesn't depend on the size of the indexes.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::path::PathBuf;
+
+use crate::ScannerConfig;
+use crate::Error::{self, WriteError as EWrite};
+use crate::v1::{Entry, EntryIterator, Hashes, Parser, ParseError};
+use crate::v1::writer::Name;
+
+
+/// A single difference between two signature files
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffEntry {
+    /// Entry exists only in the new signature file
+    Added(Entry),
+    /// Entry exists only in the old signature file
+    Removed(Entry),
+    /// Entry exists in both files but has a different type or contents
+    ///
+    /// Contains the old and the new entry.
+    Changed(Entry, Entry),
+}
+
+/// Iterator over differences between two signature files
+///
+/// Entries are yielded in the order of the signature file. Note that files
+/// are compared by hashes, so signature files with different hash type or
+/// block size report all non-empty files as changed.
+pub struct Diff<'a, R1: BufRead, R2: BufRead> {
+    old: EntryIterator<'a, R1>,
+    new: EntryIterator<'a, R2>,
+}
+
+/// Blocks changed in a file that exists in both signature files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDiff {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Indexes of changed blocks, sorted
+    ///
+    /// Includes blocks that were added or removed when size of the file
+    /// has changed.
+    pub changed: Vec<usize>,
+}
+
+/// Differences between a prior signature file and the current directory
+///
+/// Returned by [`scan_patch`](fn.scan_patch.html).
+#[derive(Debug, PartialEq, Eq)]
+pub struct Patch {
+    /// Added, removed and changed entries in the order of signature file
+    pub entries: Vec<DiffEntry>,
+    /// Changed blocks of the files that exist in both signatures
+    ///
+    /// Same as yielded by [`BlockDiffs`](struct.BlockDiffs.html).
+    pub blocks: Vec<BlockDiff>,
+    /// Hash of the new index (i.e. the image id of the current directory)
+    pub image_id: Vec<u8>,
+}
+
+/// Iterator over changed blocks of files between two signature files
+///
+/// Yields a [`BlockDiff`](struct.BlockDiff.html) for every file that
+/// exists in both signature files and has different contents. Added and
+/// removed files, directories and symlinks are skipped. Files that changed
+/// only by the executable bit have empty list of changed blocks.
+pub struct BlockDiffs<'a, R1: BufRead, R2: BufRead>(Diff<'a, R1, R2>);
+
+impl DiffEntry {
+    /// Returns the (new) entry of this difference
+    ///
+    /// For `Removed` it's the removed entry
+    pub fn entry(&self) -> &Entry {
+        match *self {
+            DiffEntry::Added(ref entry) | DiffEntry::Removed(ref entry) |
+            DiffEntry::Changed(_, ref entry) => entry,
+        }
+    }
+}
+
+impl<'a, R1: BufRead, R2: BufRead> Diff<'a, R1, R2> {
+    /// Creates an iterator over differences from `old` to `new`
+    pub fn new(old: &'a mut Parser<R1>, new: &'a mut Parser<R2>)
+        -> Diff<'a, R1, R2>
+    {
+        Diff { old: old.iter(), new: new.iter() }
+    }
+}
+
+impl<'a, R1: BufRead, R2: BufRead> BlockDiffs<'a, R1, R2> {
+    /// Creates an iterator over changed blocks from `old` to `new`
+    pub fn new(old: &'a mut Parser<R1>, new: &'a mut Parser<R2>)
+        -> BlockDiffs<'a, R1, R2>
+    {
+        BlockDiffs(Diff::new(old, new))
+    }
+}
+
+impl<'a, R1: BufRead, R2: BufRead> Iterator for BlockDiffs<'a, R1, R2> {
+    type Item = Result<BlockDiff, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next()? {
+                Ok(DiffEntry::Changed(
+                    Entry::File { hashes: old, .. },
+                    Entry::File { path, hashes: new, .. },
+                )) => {
+                    let changed = old.diff_blocks(&new);
+                    return Some(Ok(BlockDiff { path, changed }));
+                }
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Consumes an entry that is already peeked successfully
+fn take<R: BufRead>(iter: &mut EntryIterator<'_, R>) -> Entry {
+    match iter.next() {
+        Some(Ok(entry)) => entry,
+        _ => unreachable!("entry is peeked"),
+    }
+}
+
+/// Consumes an error that is already peeked
+fn take_err<R: BufRead>(iter: &mut EntryIterator<'_, R>) -> ParseError {
+    match iter.next() {
+        Some(Err(e)) => e,
+        _ => unreachable!("error is peeked"),
+    }
+}
+
+impl<'a, R1: BufRead, R2: BufRead> Iterator for Diff<'a, R1, R2> {
+    type Item = Result<DiffEntry, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.old.peek(), self.new.peek()) {
+                (None, None) => return None,
+                (Some(Err(_)), _) => return Some(Err(take_err(&mut self.old))),
+                (_, Some(Err(_))) => return Some(Err(take_err(&mut self.new))),
+                (Some(Ok(_)), None) => Ordering::Less,
+                (None, Some(Ok(_))) => Ordering::Greater,
+                (Some(Ok(old)), Some(Ok(new))) => old.kind().cmp(&new.kind()),
+            };
+            let entry = match order {
+                Ordering::Less => DiffEntry::Removed(take(&mut self.old)),
+                Ordering::Greater => DiffEntry::Added(take(&mut self.new)),
+                Ordering::Equal => {
+                    let old = take(&mut self.old);
+                    let new = take(&mut self.new);
+                    if old.content_eq(&new) {
+                        continue;
+                    }
+                    DiffEntry::Changed(old, new)
+                }
+            };
+            return Some(Ok(entry));
+        }
+    }
+}
+
+/// Writes differences between two signature files as text
+///
+/// Every line is `+ path` for added entries, `- path` for removed ones and
+/// `~ path` for changed ones. Paths are escaped the same way as in the
+/// index. Nothing except a line buffer of each file is kept in memory.
+pub fn diff_stream<R1, R2, W>(old: &mut Parser<R1>, new: &mut Parser<R2>,
+    mut out: W)
+    -> Result<(), Error>
+    where R1: BufRead, R2: BufRead, W: Write,
+{
+    for item in Diff::new(old, new) {
+        let item = item?;
+        let sign = match item {
+            DiffEntry::Added(..) => '+',
+            DiffEntry::Removed(..) => '-',
+            DiffEntry::Changed(..) => '~',
+        };
+        writeln!(out, "{} {}", sign, Name(item.entry().path()))
+            .map_err(EWrite)?;
+    }
+    Ok(())
+}
+
+/// Returns net change of the total size of files between two signatures
+///
+/// This is the total size of the files in `new` minus the total size of
+/// the files in `old`. Only differences are taken into account, so it's
+/// as cheap as iterating over the [`Diff`](struct.Diff.html).
+pub fn size_delta<R1, R2>(old: &mut Parser<R1>, new: &mut Parser<R2>)
+    -> Result<i64, ParseError>
+    where R1: BufRead, R2: BufRead,
+{
+    fn size(entry: &Entry) -> i64 {
+        match *entry {
+            Entry::File { size, .. } => size as i64,
+            Entry::Dir(..) | Entry::Link(..) | Entry::Whiteout(..) => 0,
+        }
+    }
+    let mut delta = 0;
+    for item in Diff::new(old, new) {
+        delta += match item? {
+            DiffEntry::Added(ref entry) => size(entry),
+            DiffEntry::Removed(ref entry) => -size(entry),
+            DiffEntry::Changed(ref old, ref new) => size(new) - size(old),
+        };
+    }
+    Ok(delta)
+}
+
+/// Returns files that were changed only by appending whole blocks
+///
+/// These are files that are larger in `new` and hashes of the old file are
+/// the first hashes of the new one (see
+/// [`Hashes::is_prefix_of`](../struct.Hashes.html#method.is_prefix_of)),
+/// so only the new blocks need to be transferred. Files changed in any
+/// other way are not returned.
+pub fn append_only_changes<R1, R2>(old: &mut Parser<R1>, new: &mut Parser<R2>)
+    -> Result<Vec<PathBuf>, ParseError>
+    where R1: BufRead, R2: BufRead,
+{
+    let mut result = Vec::new();
+    for item in Diff::new(old, new) {
+        if let DiffEntry::Changed(
+            Entry::File { exe: old_exe, size: old_size, hashes: old_hashes, ..},
+            Entry::File { path, exe, size, hashes },
+        ) = item? {
+            if exe == old_exe && size > old_size &&
+                old_hashes.is_prefix_of(&hashes)
+            {
+                result.push(path);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Returns files that were probably renamed, as `(old_path, new_path)`
+///
+/// A file removed from `old` is paired with a file added in `new` if they
+/// have the same size, executable bit and hashes (see
+/// [`Entry::content_eq`](../enum.Entry.html#method.content_eq)). When
+/// several removed files have the same contents, they are paired with added
+/// files of the same contents in the order of the signature files, i.e.
+/// the first removed one with the first added one and so on; extra files
+/// are not reported. Empty files are never paired, as all of them have the
+/// same contents. Pairs are returned in the order of new paths.
+///
+/// Removed and added files are kept in memory until the end of comparison.
+pub fn detect_renames<R1, R2>(old: &mut Parser<R1>, new: &mut Parser<R2>)
+    -> Result<Vec<(PathBuf, PathBuf)>, ParseError>
+    where R1: BufRead, R2: BufRead,
+{
+    let mut removed = HashMap::<(bool, u64, Hashes), VecDeque<PathBuf>>::new();
+    let mut added = Vec::new();
+    for item in Diff::new(old, new) {
+        match item? {
+            DiffEntry::Removed(Entry::File { path, exe, size, hashes })
+            if size > 0 => {
+                removed.entry((exe, size, hashes)).or_default()
+                    .push_back(path);
+            }
+            DiffEntry::Added(Entry::File { path, exe, size, hashes })
+            if size > 0 => {
+                added.push((path, (exe, size, hashes)));
+            }
+            _ => {}
+        }
+    }
+    let mut result = Vec::new();
+    for (path, key) in added {
+        if let Some(old_path) = removed.get_mut(&key)
+            .and_then(|paths| paths.pop_front())
+        {
+            result.push((old_path, path));
+        }
+    }
+    Ok(result)
+}
+
+/// Scans directories of the `config` and compares them to `prior` index
+///
+/// Hash type, block size and chunking are taken from the header of `prior`,
+/// so unchanged files are never reported as changed. The new index is only
+/// kept in memory while comparing, it's not written anywhere.
+pub fn scan_patch<R: BufRead>(config: &ScannerConfig, prior: &mut Parser<R>)
+    -> Result<Patch, Error>
+{
+    let header = prior.get_header();
+    let mut config = config.clone();
+    config.hash = header.get_hash_type();
+    config.block_size = header.get_block_size();
+    config.auto_block_size = false;
+    config.content_defined_chunks = header.row_format().chunked;
+    let (buf, image_id) = super::scan_to_vec(&config)?;
+    let mut current = Parser::new(BufReader::new(Cursor::new(&buf[..])))?;
+    let mut entries = Vec::new();
+    let mut blocks = Vec::new();
+    for item in Diff::new(prior, &mut current) {
+        let item = item?;
+        if let DiffEntry::Changed(
+            Entry::File { hashes: ref old, .. },
+            Entry::File { ref path, hashes: ref new, .. },
+        ) = item {
+            blocks.push(BlockDiff {
+                path: path.clone(),
+                changed: old.diff_blocks(new),
+            });
+        }
+        entries.push(item);
+    }
+    Ok(Patch { entries, blocks, image_id })
+}
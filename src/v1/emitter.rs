@@ -9,7 +9,7 @@ use blake2::VarBlake2b;
 
 use crate::{HashType, HashTypeEnum};
 use crate::v1::writer::{MAGIC, VERSION, Name};
-use crate::v1::parser::{Hashes, Hexlified};
+use crate::v1::parser::{Hashes, HashTokens, Header, Hexlified};
 
 /// A non-validating emitter of v1 index files
 ///
@@ -20,9 +20,24 @@ pub struct Emitter<'a> {
     out: HashWriter<'a>
 }
 
+/// An emitter that records offsets of the entries
+///
+/// Works the same as [`Emitter`](struct.Emitter.html), but also records
+/// the byte offset at which each line of directory, file or symlink starts
+/// (counting from the start of the output, header included). The offsets
+/// are returned by [`finish`](#method.finish) in the same order as entries
+/// were added, so caller can build a random-access index of the output
+/// without parsing it again.
+pub struct OffsetEmitter<'a> {
+    emitter: Emitter<'a>,
+    offsets: Vec<u64>,
+}
+
 pub(crate) struct HashWriter<'a> {
     out: &'a mut dyn Write,
     hash: Box<dyn HashTrait>,
+    /// Number of bytes written so far, including header
+    pos: u64,
 }
 
 /// Object-safe version of hash trait
@@ -37,6 +52,25 @@ impl<'a> Emitter<'a> {
     /// The Write implementation here should preferably be a buffered writer.
     pub fn new<'x>(hash_type: HashType, block_size: u64, dest: &'x mut dyn Write)
         -> io::Result<Emitter<'x>>
+    {
+        Emitter::_new(hash_type, block_size, false, dest)
+    }
+
+    /// Create a new emitter for entries parsed with the `header`
+    ///
+    /// Works like [`new`](#method.new), but also keeps the
+    /// `chunking=cdc` attribute of the header, so files with content-defined
+    /// chunks can be written. Other attributes are not copied.
+    pub fn with_header<'x>(header: &Header, dest: &'x mut dyn Write)
+        -> io::Result<Emitter<'x>>
+    {
+        Emitter::_new(header.get_hash_type(), header.get_block_size(),
+            header.row_format().chunked, dest)
+    }
+
+    fn _new<'x>(hash_type: HashType, block_size: u64, chunked: bool,
+        dest: &'x mut dyn Write)
+        -> io::Result<Emitter<'x>>
     {
         let hash = match hash_type.0 {
             HashTypeEnum::Sha512_256 => {
@@ -53,14 +87,15 @@ impl<'a> Emitter<'a> {
                 as Box<dyn HashTrait>
             }
         };
-        writeln!(dest,
-            "{}.{} {} block_size={}",
+        let header = format!("{}.{} {} block_size={}{}\n",
             MAGIC, VERSION, hash_type, block_size,
-        )?;
+            if chunked { " chunking=cdc" } else { "" });
+        dest.write_all(header.as_bytes())?;
         Ok(Emitter {
             out: HashWriter {
                 out: dest,
                 hash,
+                pos: header.len() as u64,
             },
         })
     }
@@ -98,8 +133,32 @@ impl<'a> Emitter<'a> {
             if executable { "x" } else { "f" },
             size,
         )?;
-        for item in hashes.hex_iter() {
-            write!(self.out, " {:x}", item)?;
+        writeln!(self.out, "{}", HashTokens(hashes))?;
+        Ok(())
+    }
+
+    /// Add a file with hashes of blocks in binary form
+    ///
+    /// Works the same as [`add_file`](#method.add_file) but doesn't require
+    /// building `Hashes` first. Only fixed-size blocks can be written this
+    /// way, not content-defined chunks.
+    ///
+    /// # Panics
+    ///
+    /// If length of any block hash doesn't match `hash_type`
+    pub fn add_file_blocks(&mut self, name: &OsStr, executable: bool,
+        size: u64, hash_type: HashType, blocks: &[&[u8]])
+        -> io::Result<()>
+    {
+        write!(self.out, "  {} {} {}",
+            Name(Path::new(name)),
+            if executable { "x" } else { "f" },
+            size,
+        )?;
+        for block in blocks {
+            assert_eq!(block.len(), hash_type.output_bytes(),
+                "invalid length of block hash");
+            write!(self.out, " {:x}", Hexlified(block))?;
         }
         self.out.write_all(b"\n")?;
         Ok(())
@@ -121,6 +180,17 @@ impl<'a> Emitter<'a> {
         Ok(())
     }
 
+    /// Add an overlay whiteout
+    ///
+    /// Note: whiteouts are sorted together with files by the name of the
+    /// removed entry (without `.wh.` prefix).
+    ///
+    /// The only reason this method may fail is when it failed to write to the
+    /// underlying buffer.
+    pub fn add_whiteout(&mut self, name: &OsStr) -> io::Result<()> {
+        writeln!(self.out, "  {} w", Name(Path::new(name)))
+    }
+
     /// Write the final line of the image
     ///
     /// It's the expected that nothing will be called after this method
@@ -130,6 +200,75 @@ impl<'a> Emitter<'a> {
     }
 }
 
+impl<'a> OffsetEmitter<'a> {
+    /// Create a new emitter and write a header
+    ///
+    /// See [`Emitter::new`](struct.Emitter.html#method.new)
+    pub fn new<'x>(hash_type: HashType, block_size: u64, dest: &'x mut dyn Write)
+        -> io::Result<OffsetEmitter<'x>>
+    {
+        Ok(OffsetEmitter {
+            emitter: Emitter::new(hash_type, block_size, dest)?,
+            offsets: Vec::new(),
+        })
+    }
+
+    /// Start a directory
+    ///
+    /// See [`Emitter::start_dir`](struct.Emitter.html#method.start_dir)
+    pub fn start_dir(&mut self, path: &Path) -> io::Result<()> {
+        self.offsets.push(self.emitter.out.pos);
+        self.emitter.start_dir(path)
+    }
+
+    /// Add a file
+    ///
+    /// See [`Emitter::add_file`](struct.Emitter.html#method.add_file)
+    pub fn add_file(&mut self, name: &OsStr, executable: bool, size: u64,
+        hashes: &Hashes)
+        -> io::Result<()>
+    {
+        self.offsets.push(self.emitter.out.pos);
+        self.emitter.add_file(name, executable, size, hashes)
+    }
+
+    /// Add a file with hashes of blocks in binary form
+    ///
+    /// See [`Emitter::add_file_blocks`](
+    /// struct.Emitter.html#method.add_file_blocks)
+    pub fn add_file_blocks(&mut self, name: &OsStr, executable: bool,
+        size: u64, hash_type: HashType, blocks: &[&[u8]])
+        -> io::Result<()>
+    {
+        self.offsets.push(self.emitter.out.pos);
+        self.emitter.add_file_blocks(name, executable, size, hash_type, blocks)
+    }
+
+    /// Add a symlink
+    ///
+    /// See [`Emitter::add_symlink`](struct.Emitter.html#method.add_symlink)
+    pub fn add_symlink(&mut self, name: &OsStr, dest: &Path)
+        -> io::Result<()>
+    {
+        self.offsets.push(self.emitter.out.pos);
+        self.emitter.add_symlink(name, dest)
+    }
+
+    /// Add an overlay whiteout
+    ///
+    /// See [`Emitter::add_whiteout`](struct.Emitter.html#method.add_whiteout)
+    pub fn add_whiteout(&mut self, name: &OsStr) -> io::Result<()> {
+        self.offsets.push(self.emitter.out.pos);
+        self.emitter.add_whiteout(name)
+    }
+
+    /// Write the final line of the image and return offsets of the entries
+    pub fn finish(mut self) -> io::Result<Vec<u64>> {
+        self.emitter.finish()?;
+        Ok(self.offsets)
+    }
+}
+
 impl HashTrait for sha2::Sha512Trunc256 {
     fn input(&mut self, data: &[u8]) {
         self.update(data);
@@ -165,6 +304,7 @@ impl<'a> io::Write for HashWriter<'a> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let n = self.out.write(buf)?;
         self.hash.input(&buf[..n]);
+        self.pos += n as u64;
         Ok(n)
     }
     fn flush(&mut self) -> io::Result<()> {
@@ -174,9 +314,10 @@ impl<'a> io::Write for HashWriter<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::v1::emitter::Emitter;
+    use crate::v1::emitter::{Emitter, OffsetEmitter};
     use crate::v1::parser::Hashes;
     use crate::HashType;
+    use std::ffi::OsStr;
     use std::path::Path;
 
     #[test]
@@ -218,4 +359,72 @@ DIRSIGNATURE.v1 sha512/256 block_size=32768
 552ca5730ee95727e890a2155c88609d244624034ff70de264cf88220d11d6df
 ");
     }
+
+    #[test]
+    fn test_add_file_blocks() {
+        let hashes = Hashes::from_hex(
+            "24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 \
+             9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9",
+            HashType::sha512_256(), 2, 32768);
+        let mut expected = Vec::new();
+        {
+            let mut e = Emitter::new(HashType::sha512_256(), 32768,
+                &mut expected).unwrap();
+            e.start_dir(Path::new("/")).unwrap();
+            e.add_file(OsStr::new("a.bin"), true, 58394, &hashes).unwrap();
+            e.add_file(OsStr::new("b.txt"), false, 0,
+                &Hashes::from_hex("", HashType::sha512_256(), 0, 32768)
+            ).unwrap();
+            e.finish().unwrap();
+        }
+        let blocks = hashes.iter().collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        {
+            let mut e = Emitter::new(HashType::sha512_256(), 32768, &mut buf)
+                .unwrap();
+            e.start_dir(Path::new("/")).unwrap();
+            e.add_file_blocks(OsStr::new("a.bin"), true, 58394,
+                HashType::sha512_256(), &blocks).unwrap();
+            e.add_file_blocks(OsStr::new("b.txt"), false, 0,
+                HashType::sha512_256(), &[]).unwrap();
+            e.finish().unwrap();
+        }
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   String::from_utf8(expected).unwrap());
+    }
+
+    #[test]
+    fn test_offsets() {
+        let mut buf = Vec::new();
+        let offsets = {
+            let mut e = OffsetEmitter::new(HashType::sha512_256(), 32768,
+                &mut buf).unwrap();
+            e.start_dir(Path::new("/")).unwrap();
+            e.add_file(OsStr::new("hello.txt"), false, 6,
+                &Hashes::from_hex(
+                "a79eef66019bfb9a41f798f2cff2d2d36ed294cc3f96bf53bbfc5192ebe60192",
+                HashType::sha512_256(), 1, 32768)
+            ).unwrap();
+            e.add_symlink(OsStr::new("link"), Path::new("hello.txt"))
+                .unwrap();
+            e.start_dir(Path::new("/subdir")).unwrap();
+            e.add_file_blocks(OsStr::new("empty.txt"), false, 0,
+                HashType::sha512_256(), &[]).unwrap();
+            e.finish().unwrap()
+        };
+        let lines = offsets.iter()
+            .map(|&off| {
+                let rest = &buf[off as usize..];
+                let end = rest.iter().position(|&b| b == b'\n').unwrap();
+                String::from_utf8(rest[..end].to_vec()).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![
+            "/",
+            "  hello.txt f 6 a79eef66019bfb9a41f798f2cff2d2d36ed294cc3f96bf53bbfc5192ebe60192",
+            "  link s hello.txt",
+            "/subdir",
+            "  empty.txt f 0",
+        ]);
+    }
 }
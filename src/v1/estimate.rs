@@ -0,0 +1,178 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use crate::{ScannerConfig, Error, DEFAULT_BLOCK_SIZE};
+use crate::Error::{ListDir as EList, ReadFile as ERead};
+use super::scan::find_roots;
+use super::source::{DirSource, EntryType};
+use super::writer::{MAGIC, VERSION, FOOTER_MARKER};
+
+
+/// Maximum number of hashes for the whole index with auto block size
+const MAX_TOTAL_HASHES: u64 = 1 << 20;
+/// Maximum number of hashes for a single file with auto block size
+const MAX_FILE_HASHES: u64 = 4096;
+/// Upper limit for the auto block size
+const MAX_AUTO_BLOCK_SIZE: u64 = 64 << 20;
+
+/// Length of the name as written to the index (with escapes)
+fn escaped_len(name: &OsStr) -> u64 {
+    name.as_bytes().iter()
+        .map(|&b| if b <= 0x20 || b >= 0x7F || b == b'\\' { 4 } else { 1 })
+        .sum()
+}
+
+/// Number of decimal digits in the number
+fn digits(mut n: u64) -> u64 {
+    let mut result = 1;
+    while n >= 10 {
+        n /= 10;
+        result += 1;
+    }
+    result
+}
+
+fn owner_len(config: &ScannerConfig, uid: u32, gid: u32) -> u64 {
+    if config.store_ownership {
+        // ` uid=N gid=N`
+        10 + digits(uid.into()) + digits(gid.into())
+    } else {
+        0
+    }
+}
+
+/// Estimates the size of the index for the config
+///
+/// Only metadata is read, files are not hashed. For a single root
+/// directory without concurrent modifications the estimate is exact
+/// (unless whiteouts or some other options are used). Entries that are
+/// overlaid from several directories are counted several times, so
+/// the result is larger than the actual index in that case.
+///
+/// This can be used to pre-allocate a buffer for an in-memory index:
+///
+/// ```rust,no_run
+/// # use dir_signature::{ScannerConfig, v1};
+/// let mut cfg = ScannerConfig::new();
+/// cfg.add_dir("/some/dir", "/");
+/// let size = v1::estimate_size(&cfg).unwrap();
+/// let mut buf = Vec::with_capacity(size as usize);
+/// v1::scan(&cfg, &mut buf).unwrap();
+/// ```
+pub fn estimate_size(config: &ScannerConfig) -> Result<u64, Error> {
+    estimate(config, find_roots(config)?)
+}
+
+/// Returns block size that will be used by the scanner
+///
+/// This is the one set in config unless
+/// [`ScannerConfig::auto_block_size`](
+/// ../struct.ScannerConfig.html#method.auto_block_size) is enabled. In the
+/// latter case a pass over the directories is made to choose the size, so
+/// the result may be different at scan time if directories are changed.
+pub fn choose_block_size(config: &ScannerConfig) -> Result<u64, Error> {
+    if config.auto_block_size {
+        auto_block_size(&find_roots(config)?)
+    } else {
+        Ok(config.block_size)
+    }
+}
+
+pub(crate) fn auto_block_size<S: DirSource>(roots: &[S])
+    -> Result<u64, Error>
+{
+    fn walk<S: DirSource>(dir: &S, total: &mut u64, largest: &mut u64)
+        -> Result<(), Error>
+    {
+        for (name, typ) in dir.list_dir().map_err(EList)? {
+            match typ {
+                EntryType::Dir => {
+                    let sub = dir.sub_dir(&name).map_err(EList)?;
+                    walk(&sub, total, largest)?;
+                }
+                EntryType::File => {
+                    let size = dir.metadata(&name).map_err(ERead)?.size;
+                    *total = total.saturating_add(size);
+                    *largest = (*largest).max(size);
+                }
+                EntryType::Symlink | EntryType::Other => {}
+            }
+        }
+        Ok(())
+    }
+    let mut total = 0;
+    let mut largest = 0;
+    for root in roots {
+        walk(root, &mut total, &mut largest)?;
+    }
+    let size = total.div_ceil(MAX_TOTAL_HASHES)
+        .max(largest.div_ceil(MAX_FILE_HASHES));
+    Ok(size.checked_next_power_of_two().unwrap_or(MAX_AUTO_BLOCK_SIZE)
+        .clamp(DEFAULT_BLOCK_SIZE, MAX_AUTO_BLOCK_SIZE))
+}
+
+fn estimate<S: DirSource>(config: &ScannerConfig, roots: Vec<S>)
+    -> Result<u64, Error>
+{
+    let hex_len = (config.hash.output_bytes() * 2) as u64;
+    let block_size = if config.auto_block_size {
+        auto_block_size(&roots)?
+    } else {
+        config.block_size
+    };
+    let header = format!("{}.{} {} block_size={}{}{}{}\n",
+        MAGIC, VERSION, config.hash, block_size,
+        if config.store_ownership { " ownership=yes" } else { "" },
+        if config.content_defined_chunks { " chunking=cdc" } else { "" },
+        if config.marked_footer { " footer=marked" } else { "" });
+    // content-defined chunks are prefixed by length, which is about the
+    // same number of digits as the average size
+    let hash_len = if config.content_defined_chunks {
+        hex_len + 1 + digits(block_size)
+    } else {
+        hex_len
+    };
+    // header and footer
+    let mut total = header.len() as u64 + hex_len + 1;
+    if config.marked_footer {
+        total += FOOTER_MARKER.len() as u64;
+    }
+    let mut stack = roots.into_iter()
+        .map(|dir| (PathBuf::from("/"), dir))
+        .collect::<Vec<_>>();
+    while let Some((path, dir)) = stack.pop() {
+        total += escaped_len(path.as_os_str()) + 1;
+        if config.store_ownership {
+            let meta = dir.self_metadata().map_err(ERead)?;
+            total += owner_len(config, meta.uid, meta.gid);
+        }
+        for (name, typ) in dir.list_dir().map_err(EList)? {
+            // two spaces, name, space, type char and the newline
+            let line = 5 + escaped_len(&name);
+            match typ {
+                EntryType::Dir => {
+                    let sub = dir.sub_dir(&name).map_err(EList)?;
+                    stack.push((path.join(&name), sub));
+                }
+                EntryType::File => {
+                    let meta = dir.metadata(&name).map_err(ERead)?;
+                    let blocks = if block_size > 0 {
+                        meta.size.div_ceil(block_size)
+                    } else {
+                        0
+                    };
+                    total += line + 1 + digits(meta.size)
+                        + blocks * (hash_len + 1)
+                        + owner_len(config, meta.uid, meta.gid);
+                }
+                EntryType::Symlink => {
+                    let dest = dir.read_link(&name).map_err(ERead)?;
+                    total += line + 1 + escaped_len(dest.as_os_str());
+                }
+                EntryType::Other => {}
+            }
+        }
+    }
+    Ok(total)
+}
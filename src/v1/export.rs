@@ -0,0 +1,101 @@
+//! A module for exporting an index into other text formats
+//!
+//!
+//! Entry points:
+//!
+//! * [`manifest`](fn.manifest.html) for a compact list of paths and sizes
+//! * [`symlinks`](fn.symlinks.html) for a list of symlinks and their targets
+//! * [`flat_sorted`](fn.flat_sorted.html) for a list of all entries sorted
+//!   by full path, for comparing indexes with `diff`
+
+use std::io::{BufRead, Write};
+
+use crate::Error::{self, WriteError as EWrite};
+use crate::v1::{Entry, Parser};
+use crate::v1::parser::HashTokens;
+use crate::v1::writer::Name;
+
+
+/// Writes a manifest of directories and files of the index
+///
+/// Every line is either `size\tpath` for a file or `dir\tpath` for a
+/// directory, in the same order as in the index. Symlinks and whiteouts
+/// are skipped. Paths are escaped the same way as in the index.
+pub fn manifest<R, W>(parser: &mut Parser<R>, mut out: W)
+    -> Result<(), Error>
+    where R: BufRead, W: Write,
+{
+    for entry in parser.iter() {
+        match entry? {
+            Entry::Dir(ref path) => {
+                writeln!(out, "dir\t{}", Name(path)).map_err(EWrite)?;
+            }
+            Entry::File { ref path, size, .. } => {
+                writeln!(out, "{}\t{}", size, Name(path)).map_err(EWrite)?;
+            }
+            Entry::Link(..) | Entry::Whiteout(..) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Writes a list of symlinks of the index
+///
+/// Every line is `path -> target`, lines are sorted by path. Both path and
+/// target are escaped the same way as in the index. Unlike other exports,
+/// this collects all symlinks into memory for sorting.
+pub fn symlinks<R, W>(parser: &mut Parser<R>, mut out: W)
+    -> Result<(), Error>
+    where R: BufRead, W: Write,
+{
+    let mut links = Vec::new();
+    for entry in parser.iter() {
+        if let Entry::Link(path, dest) = entry? {
+            links.push((path, dest));
+        }
+    }
+    links.sort();
+    for (path, dest) in &links {
+        writeln!(out, "{} -> {}", Name(path), Name(dest)).map_err(EWrite)?;
+    }
+    Ok(())
+}
+
+/// Writes all entries of the index sorted by full path
+///
+/// Unlike the index, where files of a directory go before its
+/// subdirectories, here directories and files are interleaved and sorted
+/// by path as binary strings. So adding a directory doesn't move other
+/// lines around, which makes output of `diff` of two such listings easier
+/// to read. Every line is a full path followed by the same attributes as
+/// in the index:
+///
+/// * `path` for a directory
+/// * `path f size hashes..` for a file, or `path x size hashes..` if it's
+///   executable
+/// * `path s target` for a symlink
+/// * `path w` for a whiteout
+///
+/// This is for humans only, the output is not a valid signature file.
+/// All entries are collected into memory for sorting.
+pub fn flat_sorted<R, W>(parser: &mut Parser<R>, mut out: W)
+    -> Result<(), Error>
+    where R: BufRead, W: Write,
+{
+    let mut entries = parser.iter().collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by(|a, b| a.path_bytes().cmp(b.path_bytes()));
+    for entry in &entries {
+        match *entry {
+            Entry::Dir(ref path) => writeln!(out, "{}", Name(path)),
+            Entry::File { ref path, exe, size, ref hashes } => {
+                writeln!(out, "{} {} {}{}", Name(path),
+                    if exe { "x" } else { "f" }, size, HashTokens(hashes))
+            }
+            Entry::Link(ref path, ref dest) => {
+                writeln!(out, "{} s {}", Name(path), Name(dest))
+            }
+            Entry::Whiteout(ref path) => writeln!(out, "{} w", Name(path)),
+        }.map_err(EWrite)?;
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use std::io;
 use std::fmt;
+use std::fs::File;
+use std::path::Path;
 use std::str;
 
 use digest::{FixedOutputDirty, Reset, Update, VariableOutput};
@@ -7,6 +9,9 @@ use sha2::Sha512Trunc256;
 use blake2::VarBlake2b;
 use generic_array::GenericArray;
 
+use crate::{Error, HashType, HashTypeEnum};
+use crate::v1::Hashes;
+
 pub(crate) static LOWER_CHARS: &'static[u8] = b"0123456789abcdef";
 
 pub trait Hash: Clone + Send + Sync + io::Write + 'static {
@@ -33,6 +38,134 @@ pub trait Hash: Clone + Send + Sync + io::Write + 'static {
     }
 }
 
+/// Hashes the data with the specified hash type
+///
+/// This is the same hash that is used for the footer of the index. Note that
+/// the footer covers all the lines after the header line, up to (but not
+/// including) the footer itself. So to validate the index downloaded into
+/// memory, compare the footer to the `hash_bytes` of the bytes between the
+/// header line and the footer line.
+pub fn hash_bytes(hash_type: HashType, data: &[u8]) -> Vec<u8> {
+    digest(hash_type, |out| out.write_all(data))
+        .expect("writing to hasher never fails")
+}
+
+/// Computes block hashes of a single file
+///
+/// Returns size of the file and its hashes, exactly as they would be
+/// written to the index for this file by [`scan`](fn.scan.html). Empty
+/// file has no hashes. Returns `Error::ZeroBlockSize` if `block_size` is
+/// zero.
+pub fn hash_file<P: AsRef<Path>>(path: P, hash_type: HashType,
+    block_size: u64)
+    -> Result<(u64, Hashes), Error>
+{
+    fn run<H: Hash>(mut hash: H, mut f: File, size: u64, block_size: u64)
+        -> io::Result<Vec<u8>>
+    {
+        let mut data = Vec::new();
+        let mut n = size;
+        while n > 0 {
+            data.extend(hash.hash_file(&mut f, block_size)?.result());
+            n = n.saturating_sub(block_size);
+        }
+        Ok(data)
+    }
+    if block_size == 0 {
+        return Err(Error::ZeroBlockSize);
+    }
+    let f = File::open(path).map_err(Error::ReadFile)?;
+    let size = f.metadata().map_err(Error::ReadFile)?.len();
+    let data = match hash_type.0 {
+        HashTypeEnum::Sha512_256 => {
+            run(Sha512_256::new(), f, size, block_size)
+        }
+        HashTypeEnum::Blake2b_256 => {
+            run(Blake2b_256::new(), f, size, block_size)
+        }
+        HashTypeEnum::Blake3_256 => {
+            run(Blake3_256::new(), f, size, block_size)
+        }
+    }.map_err(Error::ReadFile)?;
+    Ok((size, Hashes::new(data, hash_type, block_size)))
+}
+
+/// Computes block hashes of the data read from `reader` until EOF
+///
+/// This is the same as [`hash_file`](fn.hash_file.html) but works for
+/// any stream, e.g. a pipe or a decompressor, so size doesn't have to be
+/// known in advance. Data is hashed as it's read, only a small fixed-size
+/// buffer is used and the hasher is reused for every block, so memory
+/// usage doesn't depend on the block size, only the resulting hashes are
+/// kept. Returns number of bytes read and the hashes.
+pub fn hash_reader<R: io::Read>(reader: R, hash_type: HashType,
+    block_size: u64)
+    -> Result<(u64, Hashes), Error>
+{
+    fn run<H: Hash, R: io::Read>(mut hash: H, mut f: R, block_size: u64)
+        -> io::Result<(u64, Vec<u8>)>
+    {
+        let mut data = Vec::new();
+        let mut size = 0;
+        loop {
+            let (bytes, h) = hash.hash_and_size(&mut f, block_size)?;
+            if bytes == 0 {
+                break;
+            }
+            data.extend(h.result());
+            size += bytes;
+            if bytes < block_size {
+                break;
+            }
+        }
+        Ok((size, data))
+    }
+    if block_size == 0 {
+        return Err(Error::ZeroBlockSize);
+    }
+    let (size, data) = match hash_type.0 {
+        HashTypeEnum::Sha512_256 => {
+            run(Sha512_256::new(), reader, block_size)
+        }
+        HashTypeEnum::Blake2b_256 => {
+            run(Blake2b_256::new(), reader, block_size)
+        }
+        HashTypeEnum::Blake3_256 => {
+            run(Blake3_256::new(), reader, block_size)
+        }
+    }.map_err(Error::ReadFile)?;
+    Ok((size, Hashes::new(data, hash_type, block_size)))
+}
+
+/// Returns number of blocks (and hashes) of a file of `size` bytes
+///
+/// This is `ceil(size / block_size)`, computed without overflowing for
+/// any size. Empty file has no blocks. Returns `Error::ZeroBlockSize` if
+/// `block_size` is zero.
+pub fn block_count(size: u64, block_size: u64) -> Result<u64, Error> {
+    if block_size == 0 {
+        return Err(Error::ZeroBlockSize);
+    }
+    Ok(size.div_ceil(block_size))
+}
+
+/// Computes a digest of the data written by `f` using the specified hash
+pub(crate) fn digest<F, E>(hash_type: HashType, f: F) -> Result<Vec<u8>, E>
+    where F: FnOnce(&mut dyn io::Write) -> Result<(), E>,
+{
+    fn run<H: Hash, F, E>(mut hash: H, f: F) -> Result<Vec<u8>, E>
+        where F: FnOnce(&mut dyn io::Write) -> Result<(), E>,
+    {
+        f(&mut hash)?;
+        Ok(hash.total_hash().result().to_vec())
+    }
+    match hash_type.0 {
+        HashTypeEnum::Sha512_256 => run(Sha512_256::new(), f),
+        HashTypeEnum::Blake2b_256 => run(Blake2b_256::new(), f),
+        HashTypeEnum::Blake3_256 => run(Blake3_256::new(), f),
+    }
+}
+
 pub trait HashOutput {
     fn result(&self) -> &[u8];
 }
@@ -154,6 +287,7 @@ impl Hash for Blake3_256 {
     fn total_hash(&mut self) -> Self::Output {
         let mut h: [u8; 32] = Default::default();
         h.copy_from_slice(self.0.finalize().as_bytes());
+        self.0.reset();
         Blake_Res(h)
     }
 }
@@ -203,3 +337,21 @@ impl fmt::LowerHex for Blake_Res {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::block_count;
+
+    #[test]
+    fn test_block_count() {
+        assert_eq!(block_count(0, 32768).unwrap(), 0);
+        assert_eq!(block_count(1, 32768).unwrap(), 1);
+        assert_eq!(block_count(32768, 32768).unwrap(), 1);
+        assert_eq!(block_count(32769, 32768).unwrap(), 2);
+        assert_eq!(block_count(u64::MAX, 1).unwrap(), u64::MAX);
+        assert_eq!(block_count(u64::MAX, 32768).unwrap(),
+                   u64::MAX / 32768 + 1);
+        assert_eq!(block_count(u64::MAX, u64::MAX).unwrap(), 1);
+        assert!(block_count(10, 0).is_err());
+    }
+}
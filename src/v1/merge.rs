@@ -7,13 +7,18 @@
 //!   for opening files and building `MergedSignatures`
 //! * [`MergedSignatures::new`](struct.MergedSignatures.html#method.new)
 //!   for iterating over entries from multiple signature files
+//! * [`union`](fn.union.html) for writing overlay of multiple signature
+//!   files as a single one
+//! * [`union_unique_blocks`](fn.union_unique_blocks.html) for counting
+//!   distinct blocks of multiple signature files
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 
 use crate::HashType;
-use super::{Entry, EntryKind, Parser, ParseError};
+use super::{Emitter, Entry, EntryKind, Parser, ParseError};
 use super::parser::EntryIterator;
 
 quick_error! {
@@ -41,6 +46,12 @@ quick_error! {
             description("Block sizes mismatch")
             display("Block sizes mismatch: {:?}", block_sizes)
         }
+        /// Some signature files have content-defined chunks and some don't
+        ChunkingMismatch {
+            description("Chunking mismatch")
+            display("Chunking mismatch: only some files have \
+                     content-defined chunks")
+        }
     }
 }
 
@@ -94,7 +105,7 @@ pub struct MergedSignatures<K, R: BufRead> {
 impl<K, R: BufRead> MergedSignatures<K, R> {
     /// Creates merged signatures struct over `parsers`
     ///
-    /// All hash types and block size should be the same
+    /// All hash types and block size (and chunking) should be the same
     pub fn new<I>(parsers: I)
         -> Result<MergedSignatures<K, R>, MergeError>
         where I: IntoIterator<Item=(K, Parser<R>)>
@@ -112,6 +123,12 @@ impl<K, R: BufRead> MergedSignatures<K, R> {
         if !check_same(&block_sizes) {
             return Err(MergeError::BlockSizesMismatch(block_sizes));
         }
+        let chunked = parsers.iter()
+            .map(|p| p.1.get_header().row_format().chunked)
+            .collect::<Vec<_>>();
+        if !check_same(&chunked) {
+            return Err(MergeError::ChunkingMismatch);
+        }
         Ok(MergedSignatures {
             parsers: parsers,
         })
@@ -156,6 +173,20 @@ impl<'a, K, R: BufRead> MergedEntriesIterator<'a, K, R> {
         }
         entries
     }
+
+    /// Advances only the parser with the specified index and returns its
+    /// matching entry
+    ///
+    /// Unlike `advance` this doesn't touch other parsers, so it can be used
+    /// to pull entries from a single source at a time. Returns `None` if
+    /// there is no such entry in the source or index is out of range.
+    pub fn advance_for<P: AsRef<Path>>(&mut self, index: usize,
+        to: &EntryKind<P>)
+        -> Option<(&'a K, Result<Entry, ParseError>)>
+    {
+        let &mut (key, ref mut iterator) = self.iterators.get_mut(index)?;
+        iterator.advance(to).map(|entry| (key, entry))
+    }
 }
 
 struct PeekableEntryIterator<'a, R: BufRead> {
@@ -214,6 +245,18 @@ impl<'a, K, R: BufRead> Iterator for MergedEntriesIterator<'a, K, R> {
     type Item = Vec<(&'a K, Result<Entry, ParseError>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let group = self.next_indexed()?;
+        Some(group.into_iter()
+            .map(|(ix, entry)| (self.iterators[ix].0, entry))
+            .collect())
+    }
+}
+
+impl<'a, K, R: BufRead> MergedEntriesIterator<'a, K, R> {
+    /// Same as `next` but returns indexes of the parsers instead of keys
+    fn next_indexed(&mut self)
+        -> Option<Vec<(usize, Result<Entry, ParseError>)>>
+    {
         use std::cmp::Ordering::*;
 
         let mut res = Vec::with_capacity(self.iterators.len());
@@ -256,11 +299,8 @@ impl<'a, K, R: BufRead> Iterator for MergedEntriesIterator<'a, K, R> {
         }
 
         for &ix in &self.iterator_ixs {
-            let ref mut elem = self.iterators[ix];
-            let key = elem.0;
-            let ref mut iterator = elem.1;
-            if let Some(entry) = iterator.next() {
-                res.push((key, entry));
+            if let Some(entry) = self.iterators[ix].1.next() {
+                res.push((ix, entry));
             }
         }
 
@@ -272,6 +312,111 @@ impl<'a, K, R: BufRead> Iterator for MergedEntriesIterator<'a, K, R> {
     }
 }
 
+/// Writes union of several signature files (layers) as a single one
+///
+/// Layers are overlaid in order: when the same path exists in several
+/// layers the entry from the last one wins (directories are merged).
+/// Whiteout entries (see [`ScannerConfig::overlay_whiteouts`](
+/// ../../struct.ScannerConfig.html#method.overlay_whiteouts)) remove the
+/// entry with the same path, including the whole subtree if it's a
+/// directory, from all the previous layers. Whiteouts themselves are not
+/// written to the output.
+///
+/// Memory usage is proportional to the number of whiteouts.
+///
+/// # Panics
+///
+/// If `layers` is empty
+pub fn union<R, W>(layers: Vec<Parser<R>>, out: &mut W)
+    -> Result<(), MergeError>
+    where R: BufRead, W: Write,
+{
+    let mut merged = MergedSignatures::new(layers.into_iter().enumerate())?;
+    write_union(&mut merged, out)
+}
+
+/// Writes union of the merged signatures, parsers are layers in order
+///
+/// # Panics
+///
+/// If there are no parsers in `merged`
+pub(crate) fn write_union<K, R, W>(merged: &mut MergedSignatures<K, R>,
+    out: &mut W)
+    -> Result<(), MergeError>
+    where R: BufRead, W: Write,
+{
+    let header = merged.parsers.first().expect("at least one layer")
+        .1.get_header();
+    let write_err = |e| MergeError::Io("cannot write union".into(), e);
+    let mut emitter = Emitter::with_header(&header, out)
+        .map_err(write_err)?;
+    // path of the whiteout -> the last layer that contains it
+    let mut whiteouts = HashMap::<PathBuf, usize>::new();
+    let mut iter = merged.iter();
+    while let Some(group) = iter.next_indexed() {
+        let group = group.into_iter()
+            .map(|(layer, entry)| entry.map(|entry| (layer, entry)))
+            .collect::<Result<Vec<_>, _>>()?;
+        // whiteout may be in the same group as the entries it removes
+        for (layer, entry) in &group {
+            if let Entry::Whiteout(ref path) = *entry {
+                whiteouts.insert(path.clone(), *layer);
+            }
+        }
+        let mut winner = None;
+        for (layer, entry) in group {
+            if let Entry::Whiteout(..) = entry {
+                continue;
+            }
+            let removed = entry.path().ancestors().any(|p| {
+                matches!(whiteouts.get(p), Some(&wlayer) if wlayer > layer)
+            });
+            if !removed {
+                winner = Some(entry);
+            }
+        }
+        let name = |path: &Path| {
+            path.file_name().expect("file has a name").to_os_string()
+        };
+        match winner {
+            None | Some(Entry::Whiteout(..)) => Ok(()),
+            Some(Entry::Dir(ref path)) => emitter.start_dir(path),
+            Some(Entry::File { ref path, exe, size, ref hashes }) => {
+                emitter.add_file(&name(path), exe, size, hashes)
+            }
+            Some(Entry::Link(ref path, ref dest)) => {
+                emitter.add_symlink(&name(path), dest)
+            }
+        }.map_err(write_err)?;
+    }
+    emitter.finish().map_err(write_err)?;
+    Ok(())
+}
+
+/// Returns number of distinct block hashes across all merged signatures
+///
+/// This is the number of blocks a content-addressable store needs to keep
+/// all the images, so multiplied by the block size it's an upper estimate
+/// of the storage needed (last blocks of files are usually shorter).
+/// Memory usage is proportional to the number of distinct blocks.
+pub fn union_unique_blocks<K, R: BufRead>(merged: &mut MergedSignatures<K, R>)
+    -> Result<usize, MergeError>
+{
+    let mut blocks = HashSet::new();
+    for group in merged.iter() {
+        for (_, entry) in group {
+            if let Entry::File { hashes, .. } = entry? {
+                for hash in hashes.iter() {
+                    if !blocks.contains(hash) {
+                        blocks.insert(hash.to_vec());
+                    }
+                }
+            }
+        }
+    }
+    Ok(blocks.len())
+}
+
 fn check_same<I, V>(values: I) -> bool
     where I: IntoIterator<Item=V>, V: PartialEq
 {
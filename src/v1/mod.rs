@@ -4,7 +4,18 @@
 //! Entry points:
 //!
 //! * [`scan`](fn.scan.html) function for creating index file
+//! * [`scan_to_vec`](fn.scan_to_vec.html) for creating index in memory
+//! * [`scan_combined_hash`](fn.scan_combined_hash.html) for getting just
+//!   the image id without storing the index
+//! * [`scan_many`](fn.scan_many.html) for writing several indexes into
+//!   a single file, and [`Sections`](struct.Sections.html) for reading them
+//! * [`scan_source`](fn.scan_source.html) for indexing a custom
+//!   [`DirSource`](trait.DirSource.html)
+//! * [`scan_patch`](fn.scan_patch.html) for comparing a directory with
+//!   a prior index without writing a new one
 //! * [`Parser::new`](struct.Parser.html#method.new) for reading index file
+//! * [`tokio::AsyncParser`](tokio/struct.AsyncParser.html) for reading index
+//!   file asynchronously (requires `tokio` feature)
 //!
 //! There is also global [`get_hash`](../fn.get_hash.html) for getting just
 //! checksum of an index file.
@@ -13,18 +24,52 @@ mod writer;
 mod progress;
 mod hash;
 mod scan;
+mod source;
 mod parser;
 mod emitter;
+mod split;
+mod structural;
+mod estimate;
+mod sections;
+mod copy;
 pub mod merge;
-#[cfg(feature="threads")] mod threaded_writer;
+pub mod dedup;
+pub mod verify;
+pub mod export;
+pub mod validate;
+pub mod diff;
+pub mod cdc;
+pub mod tree;
+pub mod stats;
+#[cfg(any(feature="threads", feature="std-threads"))]
+mod threaded_writer;
+#[cfg(all(feature="std-threads", not(feature="threads")))]
+mod std_threaded_writer;
+#[cfg(feature="tokio")] pub mod tokio;
 
 use std::io;
+use std::path::PathBuf;
 
 pub use crate::error::Error;
 pub use self::parser::{Hashes, HashesIter};
 pub use self::parser::{Header, Entry, EntryKind, Parser, EntryIterator};
-pub use self::parser::{ParseError};
-pub use crate::v1::emitter::Emitter;
+pub use self::parser::{SignatureIndex, OwnershipIterator, FilteredEntries};
+pub use self::parser::{ResolvedLinks};
+pub use self::parser::{BorrowedEntries, EntryRef, HashesRef};
+pub use self::parser::{ParseError, is_signature, unescape_name};
+pub use self::parser::{Footer, read_footer};
+pub use self::writer::escape_name;
+pub use crate::v1::emitter::{Emitter, OffsetEmitter};
+pub use crate::v1::split::split_by_prefix;
+pub use crate::v1::hash::{hash_bytes, hash_file, hash_reader, block_count};
+pub use crate::v1::copy::copy_verified;
+pub use crate::v1::structural::structural_hash;
+pub use crate::v1::estimate::{estimate_size, choose_block_size};
+pub use crate::v1::sections::{scan_many, Sections};
+pub use crate::v1::diff::{diff_stream, size_delta, append_only_changes};
+pub use crate::v1::diff::{scan_patch, Patch, detect_renames};
+pub use crate::v1::verify::changed_heuristic;
+pub use crate::v1::source::{DirSource, EntryType, Metadata};
 
 use self::progress::Progress;
 use self::writer::{Writer, SyncWriter};
@@ -34,58 +79,187 @@ use crate::{ScannerConfig, HashTypeEnum};
 /// Create an index using specified config
 ///
 /// It's better to use some buffered output file here.
+///
+/// At least one directory must be added with `/` prefix, otherwise
+/// `Error::NoRootDirectory` is returned before anything is written to `out`.
 pub fn scan<F: io::Write>(config: &ScannerConfig, out: &mut F)
     -> Result<(), Error>
 {
-    add_hash(config, out)
+    add_hash(config, scan::find_roots(config)?, out)?;
+    Ok(())
+}
+
+/// Statistics of the scan returned by
+/// [`scan_with_stats`](fn.scan_with_stats.html)
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    /// Paths that were not written to the index, in order of scanning
+    ///
+    /// Only filled if [`ScannerConfig::collect_skipped`](
+    /// ../struct.ScannerConfig.html#method.collect_skipped) is enabled.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Create an index like [`scan`](fn.scan.html) and return scan statistics
+pub fn scan_with_stats<F: io::Write>(config: &ScannerConfig, out: &mut F)
+    -> Result<ScanStats, Error>
+{
+    let skipped = add_hash(config, scan::find_roots(config)?, out)?;
+    Ok(ScanStats { skipped })
+}
+
+/// Create an index in memory, returning it together with its hash
+///
+/// The hash is the same as returned by [`get_hash`](../fn.get_hash.html) for
+/// the index, i.e. it's decoded last line of the index which can be used as
+/// the image id.
+pub fn scan_to_vec(config: &ScannerConfig) -> Result<(Vec<u8>, Vec<u8>), Error>
+{
+    let mut buf = Vec::new();
+    scan(config, &mut buf)?;
+    let footer = buf.strip_suffix(b"\n").expect("footer ends with newline");
+    let start = footer.iter().rposition(|&b| b == b'\n').map_or(0, |x| x+1);
+    let hash = parser::parse_footer(&footer[start..], config.hash)
+        .expect("footer is valid");
+    Ok((buf, hash))
 }
 
-fn add_progress<W: Writer>(config: &ScannerConfig, out: W)
+/// Scan directories and return just the hash of the index
+///
+/// The hash is the same as returned by [`scan_to_vec`](fn.scan_to_vec.html)
+/// (i.e. the image id), but the index itself is discarded while scanning,
+/// so memory usage doesn't depend on the size of the index.
+pub fn scan_combined_hash(config: &ScannerConfig) -> Result<Vec<u8>, Error> {
+    let mut out = LastLine { inner: io::sink(), last: Vec::new(),
+                             current: Vec::new() };
+    scan(config, &mut out)?;
+    let hash = parser::parse_footer(&out.last, config.hash)
+        .expect("footer is valid");
+    Ok(hash)
+}
+
+/// Writer that remembers the last complete line written
+struct LastLine<W> {
+    inner: W,
+    last: Vec<u8>,
+    current: Vec<u8>,
+}
+
+impl<W: io::Write> io::Write for LastLine<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = self.inner.write(buf)?;
+        for line in buf[..bytes].split_inclusive(|&b| b == b'\n') {
+            match line.split_last() {
+                Some((b'\n', line)) => {
+                    self.current.extend_from_slice(line);
+                    std::mem::swap(&mut self.last, &mut self.current);
+                    self.current.clear();
+                }
+                _ => self.current.extend_from_slice(line),
+            }
+        }
+        Ok(bytes)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Scans directory twice and checks that both indexes are identical
+///
+/// Index must only depend on the contents of the directory, so `false`
+/// means either the directory was changed during the scan or there is a
+/// nondeterminism bug (e.g. depending on the order of directory listing or
+/// thread scheduling). Useful as a self-test in CI.
+pub fn scan_twice_identical(config: &ScannerConfig) -> Result<bool, Error> {
+    let mut first = Vec::new();
+    scan(config, &mut first)?;
+    let mut second = Vec::with_capacity(first.len());
+    scan(config, &mut second)?;
+    Ok(first == second)
+}
+
+/// Create an index of a custom directory source
+///
+/// Works like [`scan`](fn.scan.html) but indexes `root` instead of the
+/// directories added to the config.
+pub fn scan_source<S, F>(config: &ScannerConfig, root: S, out: &mut F)
     -> Result<(), Error>
+    where S: DirSource, F: io::Write,
+{
+    add_hash(config, vec![root], out)?;
+    Ok(())
+}
+
+fn add_progress<S: DirSource, W: Writer>(config: &ScannerConfig,
+    roots: Vec<S>, out: W)
+    -> Result<Vec<PathBuf>, Error>
     where W::TotalHash: ::std::fmt::LowerHex,
 {
     if config.print_progress {
-        scan::scan(config, Progress::new(io::stderr(), out))
+        scan::scan(config, roots, Progress::new(io::stderr(), out, config))
     } else {
-        scan::scan(config, out)
+        scan::scan(config, roots, out)
     }
 }
 
-#[cfg(not(feature="threads"))]
-fn add_threads<O, H: Hash>(config: &ScannerConfig, hash: H, out: &mut O)
-    -> Result<(), Error>
-    where O: io::Write,
+#[cfg(not(any(feature="threads", feature="std-threads")))]
+fn add_threads<S, O, H>(config: &ScannerConfig, roots: Vec<S>, hash: H,
+    out: &mut O)
+    -> Result<Vec<PathBuf>, Error>
+    where S: DirSource, O: io::Write, H: Hash,
 {
-    add_progress(config, SyncWriter::new(out, hash, config.block_size)?)
+    add_progress(config, roots, SyncWriter::new(out, hash, config)?)
 }
 
 #[cfg(feature="threads")]
-fn add_threads<O, H: Hash>(config: &ScannerConfig, hash: H, out: &mut O)
-    -> Result<(), Error>
-    where O: io::Write,
+fn add_threads<S, O, H>(config: &ScannerConfig, roots: Vec<S>, hash: H,
+    out: &mut O)
+    -> Result<Vec<PathBuf>, Error>
+    where S: DirSource, O: io::Write, H: Hash,
 {
     if config.threads > 1 {
-        add_progress(config, threaded_writer::ThreadedWriter::new(
-            config.threads,
-            out, hash, config.block_size)?)
+        add_progress(config, roots, threaded_writer::ThreadedWriter::new(
+            config.threads, out, hash, config)?)
     } else {
-        add_progress(config, SyncWriter::new(out, hash, config.block_size)?)
+        add_progress(config, roots, SyncWriter::new(out, hash, config)?)
     }
 }
 
-fn add_hash<O>(config: &ScannerConfig, out: &mut O)
-    -> Result<(), Error>
-    where O: io::Write,
+#[cfg(all(feature="std-threads", not(feature="threads")))]
+fn add_threads<S, O, H>(config: &ScannerConfig, roots: Vec<S>, hash: H,
+    out: &mut O)
+    -> Result<Vec<PathBuf>, Error>
+    where S: DirSource, O: io::Write, H: Hash,
 {
+    if config.threads > 1 {
+        add_progress(config, roots,
+            threaded_writer::ThreadedWriter::new_std(
+                config.threads, out, hash, config)?)
+    } else {
+        add_progress(config, roots, SyncWriter::new(out, hash, config)?)
+    }
+}
+
+fn add_hash<S, O>(config: &ScannerConfig, roots: Vec<S>, out: &mut O)
+    -> Result<Vec<PathBuf>, Error>
+    where S: DirSource, O: io::Write,
+{
+    if config.auto_block_size {
+        let mut config = config.clone();
+        config.block_size = estimate::auto_block_size(&roots)?;
+        config.auto_block_size = false;
+        return add_hash(&config, roots, out);
+    }
     match config.hash.0 {
         HashTypeEnum::Sha512_256 => {
-            add_threads(config, hash::Sha512_256::new(), out)
+            add_threads(config, roots, hash::Sha512_256::new(), out)
         }
         HashTypeEnum::Blake2b_256 => {
-            add_threads(config, hash::Blake2b_256::new(), out)
+            add_threads(config, roots, hash::Blake2b_256::new(), out)
         }
         HashTypeEnum::Blake3_256 => {
-            add_threads(config, hash::Blake3_256::new(), out)
+            add_threads(config, roots, hash::Blake3_256::new(), out)
         }
     }
 }
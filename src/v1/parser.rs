@@ -2,19 +2,23 @@ use std;
 use std::fmt;
 use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
 use std::ffi::{OsStr, OsString};
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
-use std::io::{self, BufRead};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::slice::Chunks;
 use std::str::{self, FromStr};
 
 use quick_error::ResultExt;
+#[cfg(feature="gzip")] use flate2::read::MultiGzDecoder;
 
-use crate::HashType;
-use super::writer::{MAGIC, VERSION};
-use super::hash::{self, HashOutput, LOWER_CHARS};
+use crate::{Error, HashType};
+use super::writer::{MAGIC, VERSION, FOOTER_MARKER};
+use super::hash::{self, block_count, HashOutput, LOWER_CHARS};
+use super::scan::normalize;
 
 quick_error! {
     /// The error type that represents errors which can happen when parsing
@@ -52,6 +56,16 @@ quick_error! {
             display("Invalid version: expected {:?} but was {:?}",
                 VERSION, version)
         }
+        /// File version is newer than supported by this library
+        ///
+        /// Only returned by [`Parser::with_version_detection`](
+        /// struct.Parser.html#method.with_version_detection)
+        UnsupportedVersion(version: String) {
+            description("Unsupported version")
+            display("Unsupported version: file has version {:?} but only \
+                     {:?} is supported, upgrade the library to read it",
+                version, VERSION)
+        }
         /// Missing hash type
         MissingHashType {
             description("Missing hash type")
@@ -59,7 +73,9 @@ quick_error! {
         /// Invalid hash type
         UnsupportedHashType(hash_type: String) {
             description("Unsupported hash type")
-            display("Unsupported hash type: {}", hash_type)
+            display("Unsupported hash type: {} (supported: {})", hash_type,
+                HashType::all().iter().map(|h| h.to_string())
+                    .collect::<Vec<_>>().join(", "))
         }
         /// Missing block size
         MissingBlockSize {
@@ -68,7 +84,8 @@ quick_error! {
         /// Invalid block size
         InvalidBlockSize(block_size: String) {
             description("Invalid block size")
-            display("Invalid block size: {}", block_size)
+            display("Invalid block size: {} (must be a positive integer)",
+                block_size)
         }
         /// Invalid hash
         InvalidHash(msg: String) {
@@ -79,6 +96,23 @@ quick_error! {
             description("Invalid file type")
             display("Invalid file type: {}", file_type)
         }
+        /// Symlink line has data after the destination
+        ///
+        /// Usually means that destination contains an unescaped space
+        InvalidSymlink(tail: String) {
+            description("Invalid symlink")
+            display("Invalid symlink: unexpected {:?} after destination \
+                     (spaces in destination must be escaped as \\x20)",
+                    tail)
+        }
+        /// Footer hash has wrong length for the hash type of the file
+        InvalidFooterLength(hash_type: HashType, expected: usize,
+                            actual: usize)
+        {
+            description("Invalid footer length")
+            display("Invalid footer: {} hash must have {} hex digits \
+                     but footer has {}", hash_type, expected, actual)
+        }
         /// General parsing error
         InvalidLine(msg: String) {
             description("Invalid line")
@@ -92,7 +126,7 @@ quick_error! {
         /// Utf-8 convertion error
         InvalidUtf8(err: std::str::Utf8Error) {
             description("Invalid utf-8")
-            display("Invalid utf-8: {}", err)
+            display("Invalid utf-8 at byte {}", err.valid_up_to())
             from()
         }
         /// Integer parsing error
@@ -125,6 +159,23 @@ quick_error! {
     }
 }
 
+impl ParseError {
+    /// Returns the version of the file if it's a newer version of the format
+    ///
+    /// This is only detected by the parser created with
+    /// [`Parser::with_version_detection`](
+    /// struct.Parser.html#method.with_version_detection). Tools can use it to
+    /// ask the user to upgrade instead of reporting a corrupt file.
+    pub fn unsupported_version(&self) -> Option<&str> {
+        match self.0 {
+            ErrorEnum::Parse(ParseRowError::UnsupportedVersion(ref v), _) => {
+                Some(v)
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Represents a type of the entry inside a signature file.
 ///
 /// Entry kinds are ordered in a way they appear in a signature file.
@@ -250,12 +301,14 @@ pub struct Header {
     version: String,
     hash_type: HashType,
     block_size: u64,
+    attrs: HashMap<String, String>,
 }
 
 impl Header {
     fn parse(row: &[u8]) -> Result<Header, ParseRowError> {
         let line = std::str::from_utf8(row).map_err(|e|
-            ParseRowError::InvalidHeader(format!("{}", e)))?;
+            ParseRowError::InvalidHeader(
+                format!("invalid utf-8 at byte {}", e.valid_up_to())))?;
         let mut parts = line.split_whitespace();
         let version = if let Some(signature) = parts.next() {
             let mut sig_parts = signature.splitn(2, '.');
@@ -295,9 +348,15 @@ impl Header {
                 },
                 Some(_) => {
                     match block_size_kv.next() {
-                        Some(v) => u64::from_str_radix(v, 10)
-                            .map_err(|_| ParseRowError::InvalidBlockSize(
-                                v.to_string()))?,
+                        // zero block size would mean infinite number of
+                        // hashes, so it's rejected rather than panicking
+                        Some(v) => match v.parse::<u64>() {
+                            Ok(0) | Err(_) => {
+                                return Err(ParseRowError::InvalidBlockSize(
+                                    v.to_string()));
+                            }
+                            Ok(size) => size,
+                        },
                         None => return Err(ParseRowError::MissingBlockSize),
                     }
                 },
@@ -305,10 +364,29 @@ impl Header {
         } else {
             return Err(ParseRowError::MissingBlockSize);
         };
+        let mut attrs = HashMap::new();
+        for attr in parts {
+            let mut kv = attr.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if !k.is_empty() => {
+                    attrs.insert(k.to_string(), v.to_string());
+                }
+                // tokens that are not `key=value` might be added by newer
+                // writers, they are skipped like unknown attributes
+                _ => {}
+            }
+        }
+        match attrs.get("chunking").map(|v| &v[..]) {
+            None | Some("cdc") => {}
+            // lines of such file can't be parsed
+            Some(v) => return Err(ParseRowError::InvalidHeader(
+                format!("unsupported chunking {:?}", v))),
+        }
         Ok(Header {
             version: version.to_string(),
             hash_type: hash_type,
             block_size: block_size,
+            attrs,
         })
     }
 
@@ -326,15 +404,65 @@ impl Header {
     pub fn get_block_size(&self) -> u64 {
         self.block_size
     }
+
+    /// Returns value of the additional `key=value` attribute of the header
+    ///
+    /// Every `key=value` pair that follows `block_size` is stored here,
+    /// both the ones this parser knows (`ownership`, `footer`, `chunking`)
+    /// and unknown ones, so that files written by newer versions of the
+    /// library can carry metadata that is ignored by older ones. Block size
+    /// itself is returned by `get_block_size` only. Tokens that are not in
+    /// `key=value` form are skipped.
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs.get(key).map(|v| &v[..])
+    }
+
+    pub(crate) fn row_format(&self) -> RowFormat {
+        RowFormat {
+            hash_type: self.hash_type,
+            block_size: self.block_size,
+            ownership: self.attr("ownership") == Some("yes"),
+            chunked: self.attr("chunking") == Some("cdc"),
+        }
+    }
 }
 
-#[derive(Debug)]
+/// Parameters of the header needed to parse entry rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RowFormat {
+    pub hash_type: HashType,
+    pub block_size: u64,
+    /// Header has `ownership=yes`, so dirs and files may have `uid`/`gid`
+    pub ownership: bool,
+    /// Header has `chunking=cdc`, so files have `length:hash` chunks
+    pub chunked: bool,
+}
+
+/// Footer of the signature file, i.e. hash of the whole index
+///
+/// See [`read_footer`](fn.read_footer.html)
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Footer(Vec<u8>);
 
 impl Footer {
+    /// Returns the hash stored in the footer
+    pub fn hash(&self) -> &[u8] {
+        &self.0
+    }
+    /// Returns the hash stored in the footer, consuming the footer
+    pub fn into_hash(self) -> Vec<u8> {
+        self.0
+    }
     fn parse(row: &[u8], hash_type: HashType)
         -> Result<Footer, ParseRowError>
     {
+        let row = row.strip_prefix(FOOTER_MARKER.as_bytes()).unwrap_or(row);
+        let (field, _) = parse_field(row)?;
+        let expected = hash_type.output_bytes() * 2;
+        if !field.is_empty() && field.len() != expected {
+            return Err(ParseRowError::InvalidFooterLength(
+                hash_type, expected, field.len()));
+        }
         let (data, tail) = parse_hashes(row, hash_type, 1)?;
         if !tail.is_empty() {
             return Err(ParseRowError::InvalidLine(
@@ -351,23 +479,58 @@ pub struct Hashes {
     data: Vec<u8>,
     hash_type: HashType,
     block_size: u64,
+    /// Lengths of content-defined chunks, `None` for fixed-size blocks
+    lengths: Option<Vec<u64>>,
 }
 
+/// Hashes formatted as in the entry line, each one with a leading space
+pub(crate) struct HashTokens<'a>(pub(crate) &'a Hashes);
+
 /// Entry hashes iterator
 #[derive(Debug)]
 pub struct HashesIter<'a>(Chunks<'a, u8>);
 
-/// Hexlified hashes interator
-#[derive(Debug)]
-pub(crate) struct HexHashesIter<'a>(Chunks<'a, u8>);
-
 impl Hashes {
-    fn new(data: Vec<u8>, hash_type: HashType, block_size: u64) -> Hashes {
+    pub(crate) fn new(data: Vec<u8>, hash_type: HashType, block_size: u64)
+        -> Hashes
+    {
         Hashes {
             data: data,
             hash_type: hash_type,
             block_size: block_size,
+            lengths: None,
+        }
+    }
+
+    pub(crate) fn with_lengths(data: Vec<u8>, lengths: Option<Vec<u64>>,
+        hash_type: HashType, block_size: u64)
+        -> Hashes
+    {
+        Hashes { lengths, ..Hashes::new(data, hash_type, block_size) }
+    }
+
+    /// Creates hashes from concatenated raw digests
+    ///
+    /// This is an inverse of [`as_bytes`](#method.as_bytes). Returns
+    /// `Error::InvalidHashes` if the length of `data` is not a multiple of
+    /// the digest size of `hash_type`.
+    pub fn from_bytes(data: Vec<u8>, hash_type: HashType, block_size: u64)
+        -> Result<Hashes, Error>
+    {
+        let bytes = hash_type.output_bytes();
+        if !data.len().is_multiple_of(bytes) {
+            return Err(Error::InvalidHashes(format!(
+                "{} bytes is not a multiple of {} hash size {}",
+                data.len(), hash_type, bytes)));
         }
+        Ok(Hashes::new(data, hash_type, block_size))
+    }
+
+    /// Returns raw digests of all blocks concatenated
+    ///
+    /// Useful for storing or transferring hashes without hex encoding.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
     }
 
     /// Number of hashes
@@ -387,11 +550,125 @@ impl Hashes {
         }
     }
 
+    /// Returns `true` if these hashes are the first hashes of `other`
+    ///
+    /// I.e. the file with `other` hashes may be created by appending whole
+    /// blocks to this file (if the last block of this file is shorter than
+    /// the block size, its hash changes when anything is appended). Hashes
+    /// of different hash types or block sizes never match.
+    pub fn is_prefix_of(&self, other: &Hashes) -> bool {
+        let lengths_match = match (&self.lengths, &other.lengths) {
+            (None, None) => true,
+            (Some(mine), Some(other)) => other.starts_with(mine),
+            _ => false,
+        };
+        self.hash_type == other.hash_type &&
+            self.block_size == other.block_size &&
+            lengths_match &&
+            other.data.starts_with(&self.data)
+    }
+
+    /// Returns indexes of blocks that differ from `other`
+    ///
+    /// Blocks that exist only in one of the files (because they have
+    /// different size) are also included. If hash type, block size or
+    /// chunking differ, all blocks are reported.
+    pub fn diff_blocks(&self, other: &Hashes) -> Vec<usize> {
+        let len = self.len().max(other.len());
+        if self.hash_type != other.hash_type ||
+            self.block_size != other.block_size ||
+            self.lengths.is_some() != other.lengths.is_some()
+        {
+            return (0..len).collect();
+        }
+        (0..len).filter(|&i| self.get(i) != other.get(i)).collect()
+    }
+
+    /// Returns indexes of blocks whose hashes are not in `available`
+    ///
+    /// This is a list of blocks that must be fetched to reconstruct the
+    /// file from a block store (see also [`dedup`](dedup/index.html)).
+    /// Every index is reported, even if the same block is repeated in the
+    /// file several times.
+    pub fn missing_blocks(&self, available: &HashSet<&[u8]>) -> Vec<usize> {
+        self.iter().enumerate()
+            .filter(|(_, hash)| !available.contains(hash))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Estimates number of bytes in blocks that differ from `other`
+    ///
+    /// `old_size` is the size of the file with these hashes and `new_size`
+    /// is the size of the file with `other` hashes. Every block reported
+    /// by [`diff_blocks`](#method.diff_blocks) is counted by its length
+    /// in the larger of the two files, so the final partial block is only
+    /// counted partially. This is an estimate of how much data needs to be
+    /// transferred to sync the file.
+    ///
+    /// For content-defined chunks, length of the chunk in `other` is
+    /// counted (or in this file, if `other` has less chunks).
+    pub fn changed_bytes(&self, other: &Hashes, old_size: u64, new_size: u64)
+        -> u64
+    {
+        if let (Some(old), Some(new)) = (&self.lengths, &other.lengths) {
+            return self.diff_blocks(other).into_iter()
+                .filter_map(|i| new.get(i).or_else(|| old.get(i)))
+                .sum();
+        }
+        let block_size = self.block_size.max(other.block_size);
+        let size = old_size.max(new_size);
+        self.diff_blocks(other).into_iter()
+            .map(|i| {
+                let start = (i as u64).saturating_mul(block_size);
+                size.saturating_sub(start).min(block_size)
+            })
+            .sum()
+    }
+
+    /// Number of bytes covered by hashes, i.e. `len() * block_size()`
+    ///
+    /// The last block of a file is usually shorter than block size, so for
+    /// a valid entry this is not less than the file size but less than
+    /// a block larger. Use `covered_len().min(size)` to get the number of
+    /// bytes actually hashed. For content-defined chunks this is the sum of
+    /// their lengths.
+    pub fn covered_len(&self) -> u64 {
+        match self.lengths {
+            Some(ref lengths) => lengths.iter().sum(),
+            None => (self.len() as u64).saturating_mul(self.block_size),
+        }
+    }
+
+    /// Checks that number of hashes matches the file size
+    ///
+    /// A file of `size` bytes must have exactly `ceil(size / block_size)`
+    /// hashes (none for an empty file). For content-defined chunks, lengths
+    /// of the chunks must sum up to the `size`.
+    pub fn matches_size(&self, size: u64) -> bool {
+        if self.lengths.is_some() {
+            return self.covered_len() == size;
+        }
+        self.block_size > 0 &&
+            self.len() as u64 == size.div_ceil(self.block_size)
+    }
+
     /// Original block size of file (size that is represented by a single hash)
+    ///
+    /// For content-defined chunks it's an average chunk size.
     pub fn block_size(&self) -> u64 {
         self.block_size
     }
 
+    /// Lengths of content-defined chunks
+    ///
+    /// Returns `None` for fixed-size blocks, i.e. unless the index has
+    /// `chunking=cdc` in the header (see
+    /// [`ScannerConfig::content_defined_chunks`](../struct.ScannerConfig.html#method.content_defined_chunks)).
+    pub fn chunk_lengths(&self) -> Option<&[u64]> {
+        self.lengths.as_deref()
+    }
+
     /// Original hash type of the index
     pub fn hash_type(&self) -> HashType {
         self.hash_type
@@ -402,9 +679,15 @@ impl Hashes {
         HashesIter(self.data.chunks(self.hash_type.output_bytes()))
     }
 
-    /// Returns iterator over hexlified hashes
-    pub(crate) fn hex_iter<'a>(&'a self) -> HexHashesIter<'a> {
-        HexHashesIter(self.data.chunks(self.hash_type.output_bytes()))
+    /// Returns iterator over hashes that owns the data
+    ///
+    /// Unlike [`iter`](#method.iter) it doesn't borrow `Hashes`, so it can
+    /// be returned from a function. Each hash is a separate allocation.
+    pub fn into_iter_owned(self) -> impl Iterator<Item=Vec<u8>> {
+        let bytes = self.hash_type.output_bytes();
+        let data = self.data;
+        (0..data.len() / bytes)
+            .map(move |i| data[i*bytes..(i+1)*bytes].to_vec())
     }
 
     /// Creates and instance by hashing a file
@@ -457,6 +740,16 @@ impl Hashes {
     fn _check_file<R: io::Read, H: hash::Hash>(&self, mut f: R, mut h: H)
         -> io::Result<bool>
     {
+        if let Some(ref lengths) = self.lengths {
+            for (orig_hash, &len) in self.iter().zip(lengths) {
+                let (bytes, hash) = h.hash_and_size(&mut f, len)?;
+                if bytes != len || orig_hash != hash.result() {
+                    return Ok(false);
+                }
+            }
+            let mut test_buf = [0; 1];
+            return Ok(f.read(&mut test_buf)? == 0);
+        }
         for orig_hash in self.iter() {
             let hash = h.hash_file(&mut f, self.block_size)?;
             if orig_hash != hash.result() {
@@ -469,6 +762,44 @@ impl Hashes {
         }
         Ok(true)
     }
+
+    /// Checks whether a single block of the file has the same hash
+    ///
+    /// Seeks to the block number `idx` and reads up to `block_size` bytes
+    /// from there (the last block of the file may be shorter). Returns
+    /// `false` if there is no such block in the hashes or in the file.
+    pub fn verify_block<R: io::Read + Seek>(&self, idx: usize, file: &mut R)
+        -> io::Result<bool>
+    {
+        use crate::HashTypeEnum::*;
+        let expected = match self.get(idx) {
+            Some(hash) => hash,
+            None => return Ok(false),
+        };
+        // offset to seek and the block length
+        let (offset, len) = match self.lengths {
+            Some(ref lengths) => (lengths[..idx].iter().sum(), lengths[idx]),
+            None => (idx as u64 * self.block_size, self.block_size),
+        };
+        match self.hash_type.0 {
+            Sha512_256 => self._verify_block(expected, offset, len,
+                file, hash::Sha512_256::new()),
+            Blake2b_256 => self._verify_block(expected, offset, len,
+                file, hash::Blake2b_256::new()),
+            Blake3_256 => self._verify_block(expected, offset, len,
+                file, hash::Blake3_256::new()),
+        }
+    }
+
+    fn _verify_block<R, H>(&self, expected: &[u8], offset: u64, len: u64,
+        file: &mut R, mut h: H)
+        -> io::Result<bool>
+        where R: io::Read + Seek, H: hash::Hash,
+    {
+        file.seek(SeekFrom::Start(offset))?;
+        let (bytes, hash) = h.hash_and_size(file, len)?;
+        Ok(bytes > 0 && hash.result() == expected)
+    }
 }
 
 impl<'a> Iterator for HashesIter<'a> {
@@ -480,15 +811,32 @@ impl<'a> Iterator for HashesIter<'a> {
 
 pub(crate) struct Hexlified<'a>(pub(crate) &'a [u8]);
 
-impl<'a> Iterator for HexHashesIter<'a> {
-    type Item = Hexlified<'a>;
-    fn next(&mut self) -> Option<Hexlified<'a>> {
-        self.0.next().map(Hexlified)
+impl<'a> fmt::Display for HashTokens<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hashes = self.0;
+        match hashes.lengths {
+            Some(ref lengths) => {
+                for (len, hash) in lengths.iter().zip(hashes.iter()) {
+                    write!(f, " {}:{:x}", len, Hexlified(hash))?;
+                }
+            }
+            None => {
+                for hash in hashes.iter() {
+                    write!(f, " {:x}", Hexlified(hash))?;
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 /// Represents an entry from dir signature file
+///
+/// New kinds of entries may be added along with format extensions (like
+/// `Whiteout` was added in 0.3.0), so the enum is non-exhaustive and
+/// matching on it requires a wildcard arm.
 #[derive(Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Entry {
     /// Direcory
     Dir(PathBuf),
@@ -505,47 +853,47 @@ pub enum Entry {
     },
     /// Link
     Link(PathBuf, PathBuf),
+    /// Overlay whiteout, i.e. the entry is removed in this layer
+    ///
+    /// See [`ScannerConfig::overlay_whiteouts`](
+    /// ../struct.ScannerConfig.html#method.overlay_whiteouts)
+    Whiteout(PathBuf),
 }
 
 impl Entry {
-    fn parse(row: &[u8], current_dir: &Path, hash_type: HashType, block_size: u64)
+    fn parse(row: &[u8], current_dir: &Path, format: RowFormat)
         -> Result<Option<Entry>, ParseRowError>
     {
-        let (entry, tail) = if row.starts_with(b"/") {
-            let (path, row) = parse_path_buf(row)?;
-            (Entry::Dir(path), row)
-        } else if row.starts_with(b"  ") {
-            let row = &row[2..];
-            let (path, row) = parse_path(row)?;
-            let path = current_dir.join(&path);
-            let (file_type, row) = parse_os_str(row)?;
-            if file_type == "f" || file_type == "x" {
-                let (file_size, row) = parse_u64(row)?;
-                let hashes_num = ((file_size + block_size - 1) / block_size) as usize;
-                let (hashes_data, row) = parse_hashes(row, hash_type, hashes_num)?;
-                let hashes = Hashes::new(hashes_data, hash_type, block_size);
-                (Entry::File {
-                    path: path,
-                    exe: file_type == "x",
-                    size: file_size,
-                    hashes: hashes },
-                 row)
-            } else if file_type == "s" {
-                let (dest, row) = parse_path_buf(row)?;
-                (Entry::Link(path, dest), row)
-            } else {
-                return Err(ParseRowError::InvalidFileType(
-                    format!("{}", String::from_utf8_lossy(file_type.as_bytes()))));
+        Ok(Entry::parse_with_owner(row, current_dir, format)?
+            .map(|(entry, _)| entry))
+    }
+
+    fn parse_with_owner(row: &[u8], current_dir: &Path, format: RowFormat)
+        -> Result<Option<(Entry, Ownership)>, ParseRowError>
+    {
+        let mut hashes = Vec::new();
+        let mut lengths = Vec::new();
+        let parsed = parse_row_ref(row, format, &mut hashes, &mut lengths)?;
+        let (row, owner) = match parsed {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let entry = match row {
+            RowRef::Dir(path) => Entry::Dir(path.into_owned()),
+            RowRef::File { name, exe, size } => Entry::File {
+                path: current_dir.join(name),
+                exe,
+                size,
+                hashes: Hashes::with_lengths(hashes,
+                    if format.chunked { Some(lengths) } else { None },
+                    format.hash_type, format.block_size),
+            },
+            RowRef::Link(name, dest) => {
+                Entry::Link(current_dir.join(name), dest.into_owned())
             }
-        } else {
-            return Ok(None);
+            RowRef::Whiteout(name) => Entry::Whiteout(current_dir.join(name)),
         };
-        if !tail.is_empty() {
-            return Err(ParseRowError::InvalidLine(
-                format!("Entry is not fully consumed: {:?}",
-                    String::from_utf8_lossy(tail))));
-        }
-        Ok(Some(entry))
+        Ok(Some((entry, owner)))
     }
 
     /// Get path of the entry
@@ -553,7 +901,38 @@ impl Entry {
         match *self {
             Entry::Dir(ref path) |
             Entry::File{ref path, ..} |
-            Entry::Link(ref path, _) => path
+            Entry::Link(ref path, _) |
+            Entry::Whiteout(ref path) => path
+        }
+    }
+
+    /// Get raw bytes of the path of the entry
+    ///
+    /// Unlike converting path to a string this is lossless for file names
+    /// that are not valid utf-8.
+    pub fn path_bytes(&self) -> &[u8] {
+        self.path().as_os_str().as_bytes()
+    }
+
+    /// Compares entries ignoring their paths
+    ///
+    /// Files are equal if they have the same size, executable bit and hashes,
+    /// symlinks are equal if they point to the same destination. Any two
+    /// directories (and any two whiteouts) are equal. This can be used to
+    /// find entries that were moved but are otherwise identical.
+    pub fn content_eq(&self, other: &Entry) -> bool {
+        match (self, other) {
+            (Entry::Dir(_), Entry::Dir(_)) => true,
+            (Entry::File { exe, size, hashes, .. },
+             Entry::File { exe: o_exe, size: o_size, hashes: o_hashes, .. })
+            => {
+                exe == o_exe && size == o_size && hashes == o_hashes
+            }
+            (Entry::Link(_, dest), Entry::Link(_, o_dest)) => {
+                dest == o_dest
+            }
+            (Entry::Whiteout(_), Entry::Whiteout(_)) => true,
+            _ => false,
         }
     }
 
@@ -564,7 +943,18 @@ impl Entry {
         match *self {
             Entry::Dir(ref path) => EntryKind::Dir(path.as_ref()),
             Entry::File{ref path, ..} |
-            Entry::Link(ref path, _) => EntryKind::File(path.as_ref()),
+            Entry::Link(ref path, _) |
+            Entry::Whiteout(ref path) => EntryKind::File(path.as_ref()),
+        }
+    }
+
+    /// Converts the entry into its kind, keeping the path
+    pub fn into_kind(self) -> EntryKind<PathBuf> {
+        match self {
+            Entry::Dir(path) => EntryKind::Dir(path),
+            Entry::File { path, .. } |
+            Entry::Link(path, _) |
+            Entry::Whiteout(path) => EntryKind::File(path),
         }
     }
 }
@@ -573,20 +963,68 @@ impl Entry {
 pub struct Parser<R: BufRead> {
     header: Header,
     reader: R,
+    max_line: Option<usize>,
+}
+
+/// Clones the header and the reader
+///
+/// The clone starts at the current position of the reader, so cloning a
+/// freshly created parser allows iterating over entries twice. Note that
+/// `BufReader` is not `Clone`, use it with cheaply cloneable readers like
+/// `&[u8]` or `Cursor<&[u8]>`.
+impl<R: BufRead + Clone> Clone for Parser<R> {
+    fn clone(&self) -> Parser<R> {
+        Parser {
+            header: self.header.clone(),
+            reader: self.reader.clone(),
+            max_line: self.max_line,
+        }
+    }
 }
 
 impl<R: BufRead> Parser<R> {
     /// Creates a directory signature parser (format v1)
     /// Tries to parse header
-    pub fn new(mut reader: R) -> Result<Parser<R>, ParseError> {
+    pub fn new(reader: R) -> Result<Parser<R>, ParseError> {
+        Parser::_new(reader, None, false)
+    }
+
+    /// Creates a parser that recognizes newer versions of the format
+    ///
+    /// Works like [`new`](#method.new), but a header like
+    /// `DIRSIGNATURE.v2` is reported as an unsupported version (see
+    /// [`ParseError::unsupported_version`](
+    /// struct.ParseError.html#method.unsupported_version)) rather than an
+    /// invalid one. Such files still can't be parsed.
+    pub fn with_version_detection(reader: R)
+        -> Result<Parser<R>, ParseError>
+    {
+        Parser::_new(reader, None, true)
+    }
+
+    /// Creates a parser that fails on lines longer than `max_line` bytes
+    ///
+    /// By default lines are not limited, so malformed input without newlines
+    /// is buffered into memory entirely before the error is reported. Note
+    /// that each block of a file adds 65 bytes to the line (for 256 bit
+    /// hashes), so the limit must be large enough for the largest file.
+    pub fn with_max_line_length(reader: R, max_line: usize)
+        -> Result<Parser<R>, ParseError>
+    {
+        Parser::_new(reader, Some(max_line), false)
+    }
+
+    fn _new(mut reader: R, max_line: Option<usize>, detect_versions: bool)
+        -> Result<Parser<R>, ParseError>
+    {
         let mut header_line = vec!();
-        read_line(&mut reader, &mut header_line)
-            .map_err(|e| ErrorEnum::Parse(e, 1))?;
-        let header = Header::parse(&header_line)
+        read_line(&mut reader, &mut header_line, max_line)
             .map_err(|e| ErrorEnum::Parse(e, 1))?;
+        let header = parse_header(&header_line, detect_versions)?;
         Ok(Parser {
             header: header,
             reader: reader,
+            max_line,
         })
     }
 
@@ -598,7 +1036,56 @@ impl<R: BufRead> Parser<R> {
     /// Creates iterator over directory signature entries
     pub fn iter(&mut self) -> EntryIterator<'_, R> {
         EntryIterator::new(&mut self.reader,
-            self.header.hash_type, self.header.block_size)
+            self.header.row_format(), self.max_line)
+    }
+
+    /// Creates iterator over entries that doesn't allocate per entry
+    ///
+    /// See [`BorrowedEntries`](struct.BorrowedEntries.html) for details.
+    pub fn iter_borrowed(&mut self) -> BorrowedEntries<'_, R> {
+        BorrowedEntries {
+            reader: &mut self.reader,
+            format: self.header.row_format(),
+            max_line: self.max_line,
+            row: Vec::new(),
+            row_num: 1,
+            dir: PathBuf::new(),
+            path: PathBuf::new(),
+            dest: PathBuf::new(),
+            hashes: Vec::new(),
+            lengths: Vec::new(),
+            exhausted: false,
+        }
+    }
+
+    /// Creates iterator over entries along with their ownership
+    ///
+    /// Ownership is `Some((uid, gid))` for entries that have `uid` and `gid`
+    /// attributes, i.e. if the signature file was created with
+    /// [`ScannerConfig::store_ownership`](../struct.ScannerConfig.html#method.store_ownership).
+    pub fn iter_with_ownership(&mut self) -> OwnershipIterator<'_, R> {
+        OwnershipIterator(self.iter())
+    }
+
+    /// Creates iterator over the entries of the directory subtree
+    ///
+    /// Skips entries up to the `dir` directory and then yields the
+    /// directory itself and all the entries inside it (including nested
+    /// directories). Yields nothing if there is no such directory in the
+    /// signature file.
+    pub fn subtree<P: AsRef<Path>>(&mut self, dir: P)
+        -> impl Iterator<Item=Result<Entry, ParseError>> + '_
+    {
+        let dir = dir.as_ref().to_path_buf();
+        let mut iter = self.iter();
+        let first = iter.advance(&EntryKind::Dir(&dir));
+        let found = first.is_some();
+        first.into_iter().chain(iter.take_while(move |entry| {
+            found && match *entry {
+                Ok(ref entry) => entry.path().starts_with(&dir),
+                Err(_) => true,
+            }
+        }))
     }
 
     /// Consumes the parser returning ownership of the underlying reader
@@ -624,29 +1111,305 @@ impl<R: BufRead> Parser<R> {
     }
 }
 
+/// Signature file with a table of entry offsets
+///
+/// Allows to read any entry by its position with a single seek, which is
+/// useful for paginating over large signature files. The table is built
+/// in a single pass over the file.
+pub struct SignatureIndex<R: BufRead + Seek> {
+    header: Header,
+    reader: R,
+    max_line: Option<usize>,
+    dirs: Vec<PathBuf>,
+    /// Offset of the line and index of the directory in `dirs`
+    entries: Vec<(u64, usize)>,
+}
+
+impl<R: BufRead + Seek> SignatureIndex<R> {
+    /// Reads remaining entries of the parser and builds an offset table
+    pub fn new(parser: Parser<R>) -> Result<SignatureIndex<R>, ParseError> {
+        SignatureIndex::_new(parser).map_err(|e| e.into())
+    }
+    fn _new(parser: Parser<R>) -> Result<SignatureIndex<R>, ErrorEnum> {
+        let Parser { header, mut reader, max_line } = parser;
+        let mut offset = reader.stream_position()?;
+        let mut dirs = vec![PathBuf::new()];
+        let mut entries = Vec::new();
+        let mut row = Vec::new();
+        let mut row_num = 1;
+        loop {
+            row_num += 1;
+            row.clear();
+            let bytes = read_line(&mut reader, &mut row, max_line)
+                .context(row_num)?;
+            let entry = Entry::parse(&row, &dirs[dirs.len()-1],
+                    header.row_format())
+                .context(row_num)?;
+            match entry {
+                None => {
+                    Footer::parse(&row, header.hash_type).context(row_num)?;
+                    let mut test_buf = [0; 1];
+                    if reader.read(&mut test_buf)? != 0 {
+                        return Err(ErrorEnum::Parse(
+                            ParseRowError::InvalidLine(
+                                "Found extra lines after the footer".into()),
+                            row_num));
+                    }
+                    break;
+                }
+                Some(Entry::Dir(path)) => dirs.push(path),
+                Some(_) => {}
+            }
+            entries.push((offset, dirs.len()-1));
+            offset += bytes as u64;
+        }
+        Ok(SignatureIndex { header, reader, max_line, dirs, entries })
+    }
+
+    /// Returns parsed `Header`
+    pub fn get_header(&self) -> Header {
+        self.header.clone()
+    }
+
+    /// Number of entries in the signature file
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no entries in the signature file
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads entry at the specified position
+    ///
+    /// Returns `None` if position is out of range
+    pub fn nth(&mut self, i: usize) -> Option<Result<Entry, ParseError>> {
+        let (offset, dir) = *self.entries.get(i)?;
+        Some(self._nth(offset, dir, i + 2).map_err(|e| e.into()))
+    }
+    fn _nth(&mut self, offset: u64, dir: usize, row_num: usize)
+        -> Result<Entry, ErrorEnum>
+    {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut row = Vec::new();
+        read_line(&mut self.reader, &mut row, self.max_line)
+            .context(row_num)?;
+        let entry = Entry::parse(&row, &self.dirs[dir],
+                self.header.row_format())
+            .context(row_num)?;
+        entry.ok_or_else(|| ErrorEnum::Parse(
+            ParseRowError::InvalidLine(
+                "Expected an entry but found the footer".into()),
+            row_num))
+    }
+}
+
+impl Parser<BufReader<File>> {
+    /// Opens signature file and parses its header
+    pub fn open<P: AsRef<Path>>(path: P)
+        -> Result<Parser<BufReader<File>>, ParseError>
+    {
+        let file = File::open(path).map_err(ErrorEnum::Io)?;
+        Parser::new(BufReader::new(file))
+    }
+
+    /// Opens signature file using a buffer of the specified capacity
+    ///
+    /// Default buffer (used by [`open`](#method.open)) is 8KiB, larger
+    /// buffers may be faster for multi-megabyte signature files.
+    pub fn open_with_capacity<P: AsRef<Path>>(path: P, capacity: usize)
+        -> Result<Parser<BufReader<File>>, ParseError>
+    {
+        let file = File::open(path).map_err(ErrorEnum::Io)?;
+        Parser::new(BufReader::with_capacity(capacity, file))
+    }
+}
+
+#[cfg(feature="gzip")]
+impl<R: io::Read> Parser<BufReader<MultiGzDecoder<R>>> {
+    /// Creates a parser of a gzip-compressed signature file
+    ///
+    /// All gzip members are decoded, so signature files compressed in
+    /// chunks and concatenated (e.g. by appending to a `.gz` file) are
+    /// read entirely rather than truncated after the first member.
+    pub fn from_gzip(reader: R)
+        -> Result<Parser<BufReader<MultiGzDecoder<R>>>, ParseError>
+    {
+        Parser::new(BufReader::new(MultiGzDecoder::new(reader)))
+    }
+}
+
+#[cfg(feature="gzip")]
+impl Parser<BufReader<MultiGzDecoder<File>>> {
+    /// Opens gzip-compressed signature file and parses its header
+    ///
+    /// See [`from_gzip`](#method.from_gzip) for details.
+    pub fn open_gzip<P: AsRef<Path>>(path: P)
+        -> Result<Parser<BufReader<MultiGzDecoder<File>>>, ParseError>
+    {
+        let file = File::open(path).map_err(ErrorEnum::Io)?;
+        Parser::from_gzip(file)
+    }
+}
+
+/// Parses a signature file held in a string
+///
+/// The string is copied into the parser, so it doesn't borrow the original
+/// one. This is mostly useful for tests and small tools.
+///
+/// # Example
+///
+/// ```rust
+/// use dir_signature::v1::{Entry, Parser};
+///
+/// let mut parser: Parser<_> = "\
+/// DIRSIGNATURE.v1 sha512/256 block_size=32768
+/// /
+///   hello.txt f 6 a79eef66019bfb9a41f798f2cff2d2d36ed294cc3f96bf53bbfc5192ebe60192
+/// acc410d912119e546044987f6485c44bb2285651c43c2a27ec69ec9ea0eb593c
+/// ".parse().unwrap();
+/// let entries = parser.iter().collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(entries.len(), 2);
+/// assert!(matches!(entries[1], Entry::File { size: 6, .. }));
+/// ```
+impl FromStr for Parser<io::Cursor<Vec<u8>>> {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Parser<io::Cursor<Vec<u8>>>, ParseError> {
+        Parser::new(io::Cursor::new(s.as_bytes().to_vec()))
+    }
+}
+
+/// Checks whether the reader starts with a v1 signature file header
+///
+/// Only looks at the buffered data (using `fill_buf`), so nothing is
+/// consumed and the reader can be passed to [`Parser::new`] afterwards.
+/// Returns `false` if the reader's buffer is too small to contain the magic.
+///
+/// [`Parser::new`]: struct.Parser.html#method.new
+pub fn is_signature<R: BufRead>(r: &mut R) -> io::Result<bool> {
+    let buf = r.fill_buf()?;
+    let magic = MAGIC.as_bytes();
+    let version = VERSION.as_bytes();
+    let len = magic.len() + 1 + version.len();
+    Ok(buf.len() > len &&
+        buf.starts_with(magic) &&
+        buf[magic.len()] == b'.' &&
+        &buf[magic.len()+1..len] == version &&
+        buf[len] == b' ')
+}
+
 /// Iterator over the entries of the signature file
 pub struct EntryIterator<'a, R: BufRead> {
     reader: &'a mut R,
-    hash_type: HashType,
-    block_size: u64,
+    format: RowFormat,
     current_row: Vec<u8>,
     current_row_num: usize,
     current_dir: PathBuf,
     exhausted: bool,
+    max_line: Option<usize>,
+    owner: Option<(u32, u32)>,
+    peeked: Option<Option<Result<Entry, ParseError>>>,
+}
+
+/// Iterator over the entries that match a predicate
+///
+/// Unlike `Iterator::filter` it keeps the `advance` method.
+/// Created by [`EntryIterator::filter_entries`](struct.EntryIterator.html#method.filter_entries)
+pub struct FilteredEntries<'a, R: BufRead, F> {
+    iter: EntryIterator<'a, R>,
+    predicate: F,
+}
+
+/// Iterator over the entries along with resolved symlink targets
+///
+/// Created by [`EntryIterator::resolve_links`](struct.EntryIterator.html#method.resolve_links)
+pub struct ResolvedLinks<'a, R: BufRead>(EntryIterator<'a, R>);
+
+/// Entry of the signature file borrowed from
+/// [`BorrowedEntries`](struct.BorrowedEntries.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryRef<'a> {
+    /// Directory
+    Dir(&'a Path),
+    /// File
+    File {
+        /// File path (joined with current directory)
+        path: &'a Path,
+        /// Is executable
+        exe: bool,
+        /// File size
+        size: u64,
+        /// Blocks hashes
+        hashes: HashesRef<'a>,
+    },
+    /// Link
+    Link(&'a Path, &'a Path),
+    /// Overlay whiteout
+    Whiteout(&'a Path),
+}
+
+/// List of hashes borrowed from the buffer of the iterator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashesRef<'a> {
+    data: &'a [u8],
+    hash_type: HashType,
+    block_size: u64,
+    lengths: Option<&'a [u64]>,
+}
+
+/// Iterator over entries that reuses buffers for paths and hashes
+///
+/// This is not an `Iterator`, because an entry borrows the iterator and is
+/// only valid until the next call to [`next_entry`](#method.next_entry). In exchange
+/// no memory is allocated per entry (except for paths containing escapes),
+/// which is faster for streaming consumers over huge signature files.
+///
+/// Created by [`Parser::iter_borrowed`](struct.Parser.html#method.iter_borrowed)
+pub struct BorrowedEntries<'a, R: BufRead> {
+    reader: &'a mut R,
+    format: RowFormat,
+    max_line: Option<usize>,
+    row: Vec<u8>,
+    row_num: usize,
+    dir: PathBuf,
+    path: PathBuf,
+    dest: PathBuf,
+    hashes: Vec<u8>,
+    lengths: Vec<u64>,
+    exhausted: bool,
 }
 
+/// Kind of the last row parsed by `BorrowedEntries`, data is in buffers
+enum RowKind {
+    Dir,
+    File { exe: bool, size: u64 },
+    Link,
+    Whiteout,
+}
+
+/// Owner `(uid, gid)` of the entry if stored in the signature file
+pub(crate) type Ownership = Option<(u32, u32)>;
+
+/// Iterator over the entries of the signature file and their ownership
+///
+/// Created by [`Parser::iter_with_ownership`](struct.Parser.html#method.iter_with_ownership)
+pub struct OwnershipIterator<'a, R: BufRead>(EntryIterator<'a, R>);
+
 impl<'a, R: BufRead> EntryIterator<'a, R> {
-    fn new(reader: &'a mut R, hash_type: HashType, block_size: u64)
+    fn new(reader: &'a mut R, format: RowFormat, max_line: Option<usize>)
         -> EntryIterator<'_, R>
     {
         EntryIterator {
             reader: reader.by_ref(),
-            hash_type: hash_type,
-            block_size: block_size,
+            format,
             current_row: vec!(),
             current_row_num: 1,
             current_dir: PathBuf::new(),
             exhausted: false,
+            max_line,
+            owner: None,
+            peeked: None,
         }
     }
 
@@ -659,31 +1422,24 @@ impl<'a, R: BufRead> EntryIterator<'a, R> {
         }
         self.current_row_num += 1;
         if self.current_row.is_empty() {
-            read_line(self.reader.by_ref(), &mut self.current_row)
+            read_line(self.reader.by_ref(), &mut self.current_row,
+                    self.max_line)
                 .context(self.current_row_num)?;
         }
-        let row = &self.current_row[..];
-        let entry = Entry::parse(
-                row, &self.current_dir, self.hash_type, self.block_size)
-            .context(self.current_row_num)?;
+        let entry = parse_row(&self.current_row, self.current_row_num,
+            &mut self.current_dir, self.format)
+            .map_err(|e| e.0)?;
         match entry {
             None => {
-                let _footer = Footer::parse(row, self.hash_type)
-                    .context(self.current_row_num)?;
                 let mut test_buf = [0; 1];
                 if self.reader.read(&mut test_buf)? != 0 {
-                    return Err(ErrorEnum::Parse(
-                        ParseRowError::InvalidLine(
-                            format!("Found extra lines after the footer")),
-                        self.current_row_num));
+                    return Err(extra_lines_error(self.current_row_num).0);
                 }
                 self.exhausted = true;
                 Ok(None)
             },
-            Some(entry) => {
-                if let Entry::Dir(ref dir_path) = entry {
-                    self.current_dir = dir_path.clone();
-                }
+            Some((entry, owner)) => {
+                self.owner = owner;
                 Ok(Some(entry))
             },
         }
@@ -699,6 +1455,21 @@ impl<'a, R: BufRead> EntryIterator<'a, R> {
     {
         use std::cmp::Ordering::*;
 
+        match self.peeked.take() {
+            Some(Some(Ok(entry))) => {
+                match entry.kind().cmp(&kind.as_ref()) {
+                    Less => {}
+                    Greater => {
+                        self.peeked = Some(Some(Ok(entry)));
+                        return None;
+                    }
+                    Equal => return Some(Ok(entry)),
+                }
+            }
+            Some(Some(Err(e))) => return Some(Err(e)),
+            Some(None) => return None,
+            None => {}
+        }
         loop {
             match self.parse_entry() {
                 Ok(Some(entry)) => {
@@ -721,12 +1492,91 @@ impl<'a, R: BufRead> EntryIterator<'a, R> {
             }
         }
     }
+
+    /// Returns the next entry without consuming it
+    ///
+    /// Subsequent `next` or `advance` call returns (or skips) this entry.
+    pub fn peek(&mut self) -> Option<&Result<Entry, ParseError>> {
+        if self.peeked.is_none() {
+            let entry = self.next();
+            self.peeked = Some(entry);
+        }
+        self.peeked.as_ref().and_then(|entry| entry.as_ref())
+    }
+
+    /// Returns iterator over entries and resolved targets of symlinks
+    ///
+    /// For `Entry::Link` the second element is an absolute path of the
+    /// target, with `..` and `.` resolved lexically against the directory
+    /// of the link. Absolute targets are relative to the root of the index,
+    /// and `..` never goes above the root. Other links (symlinks in the
+    /// target path) are not followed. For other entries it's `None`.
+    pub fn resolve_links(self) -> ResolvedLinks<'a, R> {
+        ResolvedLinks(self)
+    }
+
+    /// Returns iterator over entries for which `predicate` returns `true`
+    ///
+    /// Errors are always yielded. Order of entries is preserved, so
+    /// `advance` can be used on the resulting iterator too.
+    pub fn filter_entries<F>(self, predicate: F) -> FilteredEntries<'a, R, F>
+        where F: FnMut(&Entry) -> bool,
+    {
+        FilteredEntries { iter: self, predicate }
+    }
+
+    /// Returns iterator over files and symlinks only
+    ///
+    /// Directories (and whiteouts) are skipped, paths of the entries are
+    /// full paths as usual. Useful for making a list of checksums.
+    pub fn files_only(self)
+        -> FilteredEntries<'a, R, fn(&Entry) -> bool>
+    {
+        fn is_file(entry: &Entry) -> bool {
+            matches!(*entry, Entry::File { .. } | Entry::Link(..))
+        }
+        self.filter_entries(is_file)
+    }
+}
+
+impl<'a, R: BufRead, F: FnMut(&Entry) -> bool> FilteredEntries<'a, R, F> {
+    /// Advances to the entry with the wanted path
+    ///
+    /// Works like [`EntryIterator::advance`](struct.EntryIterator.html#method.advance)
+    /// but also returns `None` if the entry is found and doesn't match
+    /// the predicate.
+    pub fn advance<P: AsRef<Path>>(&mut self, kind: &EntryKind<P>)
+        -> Option<Result<Entry, ParseError>>
+    {
+        match self.iter.advance(kind) {
+            Some(Ok(ref entry)) if !(self.predicate)(entry) => None,
+            res => res,
+        }
+    }
+}
+
+impl<'a, R, F> Iterator for FilteredEntries<'a, R, F>
+    where R: BufRead, F: FnMut(&Entry) -> bool,
+{
+    type Item = Result<Entry, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.iter.next()? {
+                Ok(ref entry) if !(self.predicate)(entry) => continue,
+                res => return Some(res),
+            }
+        }
+    }
 }
 
 impl<'a, R: BufRead> Iterator for EntryIterator<'a, R> {
     type Item = Result<Entry, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.peeked.take() {
+            return entry;
+        }
         let res = match self.parse_entry() {
             Ok(Some(entry)) => Some(Ok(entry)),
             Ok(None) => None,
@@ -737,10 +1587,234 @@ impl<'a, R: BufRead> Iterator for EntryIterator<'a, R> {
     }
 }
 
-fn read_line<R: BufRead>(reader: &mut R, mut buf: &mut Vec<u8>)
-    -> Result<(), ParseRowError>
+impl<'a> EntryRef<'a> {
+    /// Get path of the entry
+    pub fn path(&self) -> &'a Path {
+        match *self {
+            EntryRef::Dir(path) |
+            EntryRef::File { path, .. } |
+            EntryRef::Link(path, _) |
+            EntryRef::Whiteout(path) => path,
+        }
+    }
+
+    /// Copies the entry into an owned one
+    pub fn to_entry(&self) -> Entry {
+        match *self {
+            EntryRef::Dir(path) => Entry::Dir(path.to_path_buf()),
+            EntryRef::File { path, exe, size, hashes } => Entry::File {
+                path: path.to_path_buf(),
+                exe,
+                size,
+                hashes: hashes.to_hashes(),
+            },
+            EntryRef::Link(path, dest) => {
+                Entry::Link(path.to_path_buf(), dest.to_path_buf())
+            }
+            EntryRef::Whiteout(path) => Entry::Whiteout(path.to_path_buf()),
+        }
+    }
+}
+
+impl<'a> HashesRef<'a> {
+    /// Number of hashes
+    pub fn len(&self) -> usize {
+        self.data.len() / self.hash_type.output_bytes()
+    }
+
+    /// Returns `true` if there are no hashes (i.e. file is empty)
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Get hash by index
+    pub fn get(&self, idx: usize) -> Option<&'a [u8]> {
+        let bytes = self.hash_type.output_bytes();
+        let off = bytes.checked_mul(idx)?;
+        let end = off.checked_add(bytes)?;
+        self.data.get(off..end)
+    }
+
+    /// Returns raw digests of all blocks concatenated
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Original block size of file
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Lengths of content-defined chunks, `None` for fixed-size blocks
+    pub fn chunk_lengths(&self) -> Option<&'a [u64]> {
+        self.lengths
+    }
+
+    /// Original hash type of the index
+    pub fn hash_type(&self) -> HashType {
+        self.hash_type
+    }
+
+    /// Returns iterator over hashes
+    pub fn iter(&self) -> HashesIter<'a> {
+        HashesIter(self.data.chunks(self.hash_type.output_bytes()))
+    }
+
+    /// Copies hashes into an owned `Hashes`
+    pub fn to_hashes(&self) -> Hashes {
+        Hashes::with_lengths(self.data.to_vec(),
+            self.lengths.map(|l| l.to_vec()),
+            self.hash_type, self.block_size)
+    }
+}
+
+impl<'a, R: BufRead> BorrowedEntries<'a, R> {
+    /// Returns the next entry, valid until the next call
+    ///
+    /// It's named like this rather than `next` because it borrows the
+    /// iterator, so `BorrowedEntries` can't implement `Iterator`.
+    pub fn next_entry(&mut self) -> Option<Result<EntryRef<'_>, ParseError>> {
+        if self.exhausted {
+            return None;
+        }
+        self.row_num += 1;
+        self.row.clear();
+        let kind = match self.parse_next() {
+            Ok(Some(kind)) => kind,
+            Ok(None) => return None,
+            Err(e) => {
+                self.exhausted = true;
+                return Some(Err(e.into()));
+            }
+        };
+        Some(Ok(match kind {
+            RowKind::Dir => EntryRef::Dir(&self.dir),
+            RowKind::File { exe, size } => EntryRef::File {
+                path: &self.path,
+                exe,
+                size,
+                hashes: HashesRef {
+                    data: &self.hashes,
+                    hash_type: self.format.hash_type,
+                    block_size: self.format.block_size,
+                    lengths: if self.format.chunked {
+                        Some(&self.lengths)
+                    } else {
+                        None
+                    },
+                },
+            },
+            RowKind::Link => EntryRef::Link(&self.path, &self.dest),
+            RowKind::Whiteout => EntryRef::Whiteout(&self.path),
+        }))
+    }
+
+    fn parse_next(&mut self) -> Result<Option<RowKind>, ErrorEnum> {
+        read_line(self.reader.by_ref(), &mut self.row, self.max_line)
+            .context(self.row_num)?;
+        match self.parse_row().context(self.row_num)? {
+            Some(kind) => Ok(Some(kind)),
+            None => {
+                let mut test_buf = [0; 1];
+                if self.reader.read(&mut test_buf)? != 0 {
+                    return Err(extra_lines_error(self.row_num).0);
+                }
+                self.exhausted = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parses the row with `parse_row_ref` and fills buffers
+    fn parse_row(&mut self) -> Result<Option<RowKind>, ParseRowError> {
+        self.hashes.clear();
+        self.lengths.clear();
+        let row = match parse_row_ref(&self.row, self.format,
+                                      &mut self.hashes, &mut self.lengths)?
+        {
+            Some((row, _owner)) => row,
+            None => {
+                Footer::parse(&self.row, self.format.hash_type)?;
+                return Ok(None);
+            }
+        };
+        let (kind, name) = match row {
+            RowRef::Dir(path) => {
+                self.dir.clear();
+                self.dir.push(path);
+                return Ok(Some(RowKind::Dir));
+            }
+            RowRef::File { name, exe, size } => {
+                (RowKind::File { exe, size }, name)
+            }
+            RowRef::Link(name, dest) => {
+                self.dest.clear();
+                self.dest.push(dest);
+                (RowKind::Link, name)
+            }
+            RowRef::Whiteout(name) => (RowKind::Whiteout, name),
+        };
+        self.path.clear();
+        self.path.push(&self.dir);
+        self.path.push(name);
+        Ok(Some(kind))
+    }
+}
+
+impl<'a, R: BufRead> Iterator for ResolvedLinks<'a, R> {
+    type Item = Result<(Entry, Option<PathBuf>), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.0.next()? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        let target = match entry {
+            Entry::Link(ref path, ref dest) => {
+                let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+                Some(normalize(&dir.join(dest)))
+            }
+            _ => None,
+        };
+        Some(Ok((entry, target)))
+    }
+}
+
+impl<'a, R: BufRead> Iterator for OwnershipIterator<'a, R> {
+    type Item = Result<(Entry, Option<(u32, u32)>), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.0.next()?;
+        Some(entry.map(|entry| (entry, self.0.owner)))
+    }
+}
+
+/// Reads a line into `buf` (without the newline)
+///
+/// Returns number of bytes consumed from the reader, including the newline.
+fn read_line<R: BufRead>(reader: &mut R, buf: &mut Vec<u8>,
+    max_line: Option<usize>)
+    -> Result<usize, ParseRowError>
 {
-    let _ = reader.read_until(b'\n', &mut buf)?;
+    let bytes = if let Some(max_line) = max_line {
+        // one more byte for the newline
+        let limit = max_line as u64 + 1;
+        let mut reader = io::Read::take(reader, limit);
+        let bytes = reader.read_until(b'\n', buf)?;
+        if !buf.ends_with(b"\n") && bytes as u64 == limit {
+            return Err(ParseRowError::InvalidLine(
+                format!("Line is longer than {} bytes", max_line)));
+        }
+        bytes
+    } else {
+        reader.read_until(b'\n', buf)?
+    };
+    strip_newline(buf)?;
+    Ok(bytes)
+}
+
+/// Removes trailing newline from the line read by `read_until`
+pub(crate) fn strip_newline(buf: &mut Vec<u8>) -> Result<(), ParseRowError> {
     if !buf.ends_with(b"\n") {
         return Err(ParseRowError::InvalidLine(
             format!("Every line must end with a newline")));
@@ -749,6 +1823,174 @@ fn read_line<R: BufRead>(reader: &mut R, mut buf: &mut Vec<u8>)
     Ok(())
 }
 
+/// Parses a footer line (without the newline) returning the hash
+pub(crate) fn parse_footer(row: &[u8], hash_type: HashType)
+    -> Result<Vec<u8>, ParseRowError>
+{
+    Footer::parse(row, hash_type).map(|Footer(hash)| hash)
+}
+
+/// Reads the footer of the signature file without parsing the entries
+///
+/// Seeks to the end of the file and parses the last line, which must be
+/// terminated by a newline. `hash_type` must be taken from the header of
+/// the file (use [`get_image_id`](../fn.get_image_id.html) if you only need
+/// the hash and don't want to parse the header yourself). Only the footer
+/// line is validated, so the result doesn't prove that the rest of the file
+/// is correct.
+///
+/// Line number of the footer is not known here, so parse errors are
+/// reported at line zero.
+pub fn read_footer<R: Read + Seek>(r: &mut R, hash_type: HashType)
+    -> Result<Footer, ParseError>
+{
+    // preceding newline, optional marker, hash and a newline
+    let max_len = hash_type.output_bytes() * 2 + FOOTER_MARKER.len() + 2;
+    let file_len = r.seek(SeekFrom::End(0)).map_err(ErrorEnum::Io)?;
+    let start = file_len.saturating_sub(max_len as u64);
+    r.seek(SeekFrom::Start(start)).map_err(ErrorEnum::Io)?;
+    let mut buf = Vec::with_capacity(max_len);
+    r.take(max_len as u64).read_to_end(&mut buf).map_err(ErrorEnum::Io)?;
+    let row = match buf.split_last() {
+        Some((b'\n', row)) => row,
+        _ => return Err(row_error(0, ParseRowError::InvalidLine(
+            "Footer is not terminated by a newline".into()))),
+    };
+    let row = match row.iter().rposition(|&b| b == b'\n') {
+        Some(pos) => &row[pos+1..],
+        None if start == 0 => row,
+        None => return Err(row_error(0, ParseRowError::InvalidLine(
+            "Footer line is too long".into()))),
+    };
+    Footer::parse(row, hash_type).map_err(|e| row_error(0, e))
+}
+
+/// Returns `true` for versions like `v2`, i.e. newer than the `v1`
+fn is_future_version(version: &str) -> bool {
+    version.strip_prefix('v')
+        .filter(|num| num.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|num| u32::from_str(num).ok())
+        .is_some_and(|num| num > 1)
+}
+
+/// Parses a header line (without the newline)
+///
+/// `detect_versions` enables `UnsupportedVersion` error for newer versions
+pub(crate) fn parse_header(row: &[u8], detect_versions: bool)
+    -> Result<Header, ParseError>
+{
+    let err = match Header::parse(row) {
+        Ok(header) => return Ok(header),
+        Err(ParseRowError::InvalidVersion(ref v))
+            if detect_versions && is_future_version(v)
+        => ParseRowError::UnsupportedVersion(v.clone()),
+        Err(e) => e,
+    };
+    Err(ErrorEnum::Parse(err, 1).into())
+}
+
+/// Entry row with paths borrowed from the line
+///
+/// Names of files, links and whiteouts are not joined with the directory.
+enum RowRef<'a> {
+    Dir(Cow<'a, Path>),
+    File { name: Cow<'a, Path>, exe: bool, size: u64 },
+    Link(Cow<'a, Path>, Cow<'a, Path>),
+    Whiteout(Cow<'a, Path>),
+}
+
+/// Parses an entry row, hashes of the file are appended to `hashes`
+///
+/// With content-defined chunks, their lengths are appended to `lengths`.
+/// Returns `None` if the row is not an entry, i.e. it should be a footer.
+/// This is the only entry parser, both owned and borrowed iterators use it.
+fn parse_row_ref<'a>(row: &'a [u8], format: RowFormat, hashes: &mut Vec<u8>,
+    lengths: &mut Vec<u64>)
+    -> Result<Option<(RowRef<'a>, Ownership)>, ParseRowError>
+{
+    let (entry, tail) = if row.starts_with(b"/") {
+        let (path, row) = parse_path(row)?;
+        (RowRef::Dir(path), row)
+    } else if row.starts_with(b"  ") {
+        let (name, row) = parse_path(&row[2..])?;
+        let (file_type, row) = parse_os_str(row)?;
+        if file_type == "f" || file_type == "x" {
+            let (size, row) = parse_u64(row)?;
+            let row = if format.chunked {
+                parse_chunks_into(row, format.hash_type, size,
+                    hashes, lengths)?
+            } else {
+                let hashes_num = block_count(size, format.block_size)
+                    .map_err(|_| ParseRowError::InvalidBlockSize(
+                        format.block_size.to_string()))? as usize;
+                parse_hashes_into(row, format.hash_type, hashes_num, hashes)?
+            };
+            (RowRef::File { name, exe: file_type == "x", size }, row)
+        } else if file_type == "s" {
+            let (dest, row) = parse_path(row)?;
+            // anything after the destination is most likely an unescaped
+            // space, only ownership may follow it if header enables one
+            let unexpected = row.split(|&c| c == b' ').any(|field| {
+                !format.ownership ||
+                !(field.starts_with(b"uid=") || field.starts_with(b"gid="))
+            });
+            if !row.is_empty() && unexpected {
+                return Err(ParseRowError::InvalidSymlink(
+                    String::from_utf8_lossy(row).into_owned()));
+            }
+            (RowRef::Link(name, dest), row)
+        } else if file_type == "w" {
+            (RowRef::Whiteout(name), row)
+        } else {
+            return Err(ParseRowError::InvalidFileType(
+                String::from_utf8_lossy(file_type.as_bytes()).into_owned()));
+        }
+    } else {
+        return Ok(None);
+    };
+    let owner = match entry {
+        RowRef::Dir(..) | RowRef::File { .. } => {
+            parse_owner(tail, format.ownership)?
+        }
+        RowRef::Link(..) | RowRef::Whiteout(..) => parse_owner(tail, false)?,
+    };
+    Ok(Some((entry, owner)))
+}
+
+/// Parses an entry or a footer row (without the newline)
+///
+/// Returns `None` for a valid footer and updates `current_dir` when
+/// a directory entry is encountered.
+pub(crate) fn parse_row(row: &[u8], row_num: usize, current_dir: &mut PathBuf,
+    format: RowFormat)
+    -> Result<Option<(Entry, Ownership)>, ParseError>
+{
+    let entry = Entry::parse_with_owner(row, current_dir, format)
+        .context(row_num).map_err(ErrorEnum::from)?;
+    match entry {
+        None => {
+            let _footer = Footer::parse(row, format.hash_type)
+                .context(row_num).map_err(ErrorEnum::from)?;
+            Ok(None)
+        }
+        Some((entry, owner)) => {
+            if let Entry::Dir(ref dir_path) = entry {
+                *current_dir = dir_path.clone();
+            }
+            Ok(Some((entry, owner)))
+        }
+    }
+}
+
+pub(crate) fn row_error(row_num: usize, err: ParseRowError) -> ParseError {
+    ErrorEnum::Parse(err, row_num).into()
+}
+
+pub(crate) fn extra_lines_error(row_num: usize) -> ParseError {
+    row_error(row_num, ParseRowError::InvalidLine(
+        format!("Found extra lines after the footer")))
+}
+
 fn parse_path<'a>(data: &'a [u8])
     -> Result<(Cow<'_, Path>, &'a [u8]), ParseRowError>
 {
@@ -760,14 +2002,6 @@ fn parse_path<'a>(data: &'a [u8])
     Ok((unescaped_path, tail))
  }
 
-fn parse_path_buf<'a>(data: &'a [u8])
-    -> Result<(PathBuf, &'a [u8]), ParseRowError>
-{
-    let (path, tail) = parse_os_str(data)?;
-    let unescaped_path = unescape_hex(path);
-    Ok((PathBuf::from(&unescaped_path), tail))
-}
-
 fn parse_os_str<'a>(data: &'a [u8])
     -> Result<(&OsStr, &'a [u8]), ParseRowError>
 {
@@ -811,16 +2045,75 @@ impl Hashes {
     }
 }
 
+/// Parses `key=value` attributes at the end of the entry line
+///
+/// Returns `uid` and `gid` if present, other attributes are ignored
+/// for forward compatibility. Ownership is an error unless `allowed`,
+/// i.e. unless it's a directory or a file line of the `ownership=yes` index.
+fn parse_owner(data: &[u8], allowed: bool)
+    -> Result<Option<(u32, u32)>, ParseRowError>
+{
+    let mut uid = None;
+    let mut gid = None;
+    let mut tail = data;
+    while !tail.is_empty() {
+        let (field, rest) = parse_field(tail)?;
+        let mut pair = field.splitn(2, |c| *c == b'=');
+        match (pair.next(), pair.next()) {
+            (Some(key @ b"uid"), Some(_)) | (Some(key @ b"gid"), Some(_))
+                if !allowed
+            => {
+                return Err(ParseRowError::InvalidLine(
+                    format!("Unexpected {:?} attribute, ownership is only \
+                        allowed for directories and files if header has \
+                        `ownership=yes`", String::from_utf8_lossy(key))));
+            }
+            (Some(b"uid"), Some(value)) => {
+                uid = Some(str::from_utf8(value)?.parse()?);
+            }
+            (Some(b"gid"), Some(value)) => {
+                gid = Some(str::from_utf8(value)?.parse()?);
+            }
+            (Some(_), Some(_)) => {}
+            _ => {
+                return Err(ParseRowError::InvalidLine(
+                    format!("Entry is not fully consumed: {:?}",
+                        String::from_utf8_lossy(tail))));
+            }
+        }
+        tail = rest;
+    }
+    match (uid, gid) {
+        (Some(uid), Some(gid)) => Ok(Some((uid, gid))),
+        (None, None) => Ok(None),
+        _ => Err(ParseRowError::InvalidLine(
+            "Both uid and gid must be specified".into())),
+    }
+}
+
 fn parse_hashes<'a>(data: &'a [u8], hash_type: HashType, hashes_num: usize)
     -> Result<(Vec<u8>, &'a [u8]), ParseRowError>
+{
+    let mut buf = Vec::new();
+    let tail = parse_hashes_into(data, hash_type, hashes_num, &mut buf)?;
+    Ok((buf, tail))
+}
+
+/// Decodes hashes appending them to `buf`, returns the rest of the line
+fn parse_hashes_into<'a>(data: &'a [u8], hash_type: HashType,
+    hashes_num: usize, buf: &mut Vec<u8>)
+    -> Result<&'a [u8], ParseRowError>
 {
     let mut data = data;
 
     let digest_len = hash_type.output_bytes();
     let hash_len = digest_len * 2;
+    // number of hashes comes from the file, so only allocate for as many
+    // hashes as the line may contain
+    let max_num = data.len() / (hash_len + 1) + 1;
+    buf.reserve(hashes_num.min(max_num) * digest_len);
 
     let mut i = 0;
-    let mut buf = Vec::with_capacity(hashes_num * digest_len);
     loop {
         if i == hashes_num {
             break;
@@ -843,14 +2136,57 @@ fn parse_hashes<'a>(data: &'a [u8], hash_type: HashType, hashes_num: usize)
 
     if i != hashes_num {
         return Err(ParseRowError::InvalidHash(
-            format!("Expected {} hashes but found {}",
-                hashes_num, buf.len() / digest_len)));
+            format!("Expected {} hashes but found {}", hashes_num, i)));
+    }
+
+    Ok(data)
+}
+
+/// Decodes `length:hash` chunks appending them to buffers
+///
+/// Chunks end at the first field that has no `:`, lengths of the chunks
+/// must sum up to the `size`. Returns the rest of the line.
+fn parse_chunks_into<'a>(data: &'a [u8], hash_type: HashType, size: u64,
+    hashes: &mut Vec<u8>, lengths: &mut Vec<u64>)
+    -> Result<&'a [u8], ParseRowError>
+{
+    let mut data = data;
+    let mut total = 0u64;
+    while !data.is_empty() {
+        let (field, tail) = parse_field(data)?;
+        let mut pair = field.splitn(2, |c| *c == b':');
+        let (len, hash) = match (pair.next(), pair.next()) {
+            (Some(len), Some(hash)) => (len, hash),
+            _ => break,
+        };
+        let (len, _) = parse_u64(len)?;
+        if len == 0 {
+            return Err(ParseRowError::InvalidHash(
+                format!("Chunk length must be positive: {:?}",
+                    String::from_utf8_lossy(field))));
+        }
+        let _ = parse_hashes_into(hash, hash_type, 1, hashes)?;
+        lengths.push(len);
+        total = total.saturating_add(len);
+        data = tail;
+    }
+    if total != size {
+        return Err(ParseRowError::InvalidHash(
+            format!("Chunks sum up to {} bytes but file size is {}",
+                total, size)));
     }
+    Ok(data)
+}
 
-    Ok((buf, data))
+/// Unescapes a file name (or a path) as it's written in the index
+///
+/// This is an inverse of [`escape_name`](fn.escape_name.html). Backslashes
+/// that are not followed by `x` and two hex digits are kept as is.
+pub fn unescape_name(name: &str) -> OsString {
+    unescape_hex(OsStr::new(name)).into_owned()
 }
 
-fn unescape_hex(s: &OsStr) -> Cow<'_, OsStr> {
+pub(crate) fn unescape_hex(s: &OsStr) -> Cow<'_, OsStr> {
     let (mut i, has_escapes) = {
         let bytes = s.as_bytes();
         let mut i = 0;
@@ -944,8 +2280,9 @@ mod test {
     use rustc_hex::FromHex;
 
     use crate::HashType;
-    use super::{Entry, Footer, Hashes, Header, ParseRowError};
+    use super::{Entry, Footer, Hashes, Header, ParseRowError, RowFormat};
     use super::{parse_hashes, parse_hex, is_hex, is_hex_encoding, unescape_hex};
+    use super::read_line;
 
     #[test]
     fn test_entry_kind_ord() {
@@ -969,6 +2306,28 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_entry_into_kind() {
+        use super::EntryKind;
+
+        let hashes = Hashes::new(vec![], HashType::sha512_256(), 32768);
+        let entries = vec![
+            Entry::Dir("/a".into()),
+            Entry::File { path: "/a/f".into(), exe: false, size: 0, hashes },
+            Entry::Link("/a/l".into(), "f".into()),
+            Entry::Whiteout("/a/w".into()),
+        ];
+        let kinds = entries.into_iter()
+            .map(|e| e.into_kind()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            EntryKind::Dir("/a".into()),
+            EntryKind::File("/a/f".into()),
+            EntryKind::File("/a/l".into()),
+            EntryKind::File("/a/w".into()),
+        ]);
+        assert_eq!(kinds[1].as_ref(), EntryKind::File(Path::new("/a/f")));
+    }
+
     #[test]
     fn test_header_parse() {
         let res = Header::parse(b"");
@@ -979,7 +2338,7 @@ mod test {
         let res = Header::parse(b"\xff");
         assert!(matches!(res,
                 Err(ParseRowError::InvalidHeader(ref msg))
-                if msg.starts_with("invalid utf-8")),
+                if msg == "invalid utf-8 at byte 0"),
             "Result was: {:?}", res);
 
         let res = Header::parse(b"DIRSIGNATURE");
@@ -1003,6 +2362,9 @@ mod test {
                 Err(ParseRowError::UnsupportedHashType(ref h))
                 if h == "sha512/25"),
             "Result was: {:?}", res);
+        assert_eq!(res.unwrap_err().to_string(),
+            "Unsupported hash type: sha512/25 \
+             (supported: sha512/256, blake2b/256, blake3/256)");
 
         let res = Header::parse(b"DIRSIGNATURE.v1 sha512/256 size=2");
         assert!(matches!(res,
@@ -1015,34 +2377,68 @@ mod test {
                 if b == "dead"),
             "Result was: {:?}", res);
 
+        let res = Header::parse(b"DIRSIGNATURE.v1 sha512/256 block_size=0");
+        assert!(matches!(res,
+                Err(ParseRowError::InvalidBlockSize(ref b))
+                if b == "0"),
+            "Result was: {:?}", res);
+
         let res = Header::parse(b"DIRSIGNATURE.v1 sha512/256 block_size=1234");
         let header = res.unwrap();
         assert_eq!(header.get_version(), "v1");
         assert!(header.get_hash_type() == HashType::sha512_256());
         assert_eq!(header.get_block_size(), 1234);
+        assert_eq!(header.attr("block_size"), None);
+    }
+
+    #[test]
+    fn test_header_attrs() {
+        let res = Header::parse(
+            b"DIRSIGNATURE.v1 sha512/256 block_size=1234 foo=bar empty=");
+        let header = res.unwrap();
+        assert_eq!(header.get_block_size(), 1234);
+        assert_eq!(header.attr("foo"), Some("bar"));
+        assert_eq!(header.attr("empty"), Some(""));
+        assert_eq!(header.attr("bar"), None);
+        assert_eq!(header.attr("block_size"), None);
+
+        let res = Header::parse(
+            b"DIRSIGNATURE.v1 sha512/256 block_size=1234 ownership=yes");
+        assert_eq!(res.unwrap().attr("ownership"), Some("yes"));
+
+        let res = Header::parse(
+            b"DIRSIGNATURE.v1 sha512/256 block_size=1234 foo =x bar=baz");
+        let header = res.unwrap();
+        assert_eq!(header.attr("foo"), None);
+        assert_eq!(header.attr(""), None);
+        assert_eq!(header.attr("bar"), Some("baz"));
     }
 
     #[test]
     fn test_entry_parse() {
-        let t = HashType::sha512_256();
-        let b = 32768;
+        let f = RowFormat {
+            hash_type: HashType::sha512_256(),
+            block_size: 32768,
+            ownership: false,
+            chunked: false,
+        };
 
-        let res = Entry::parse(b"", Path::new(""), t, b);
+        let res = Entry::parse(b"", Path::new(""), f);
         assert!(matches!(res, Ok(None)));
 
-        let res = Entry::parse(b"/test", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"/test", Path::new("/dir"), f);
         assert!(matches!(res,
                 Ok(Some(Entry::Dir(ref dir_path)))
                 if dir_path == Path::new("/test")),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"/test\\x20escaped\\x5cx20", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"/test\\x20escaped\\x5cx20", Path::new("/dir"), f);
         assert!(matches!(res,
                 Ok(Some(Entry::Dir(ref dir_path)))
                 if dir_path == Path::new("/test escaped\\x20")),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"  test f 0", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"  test f 0", Path::new("/dir"), f);
         assert!(matches!(res,
                 Ok(Some(Entry::File { ref path, exe, size, .. }))
                 if path == Path::new("/dir/test") && !exe && size == 0),
@@ -1050,39 +2446,157 @@ mod test {
 
         let res = Entry::parse(
             b"  test x 100 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc",
-            Path::new("/dir"), t, b);
+            Path::new("/dir"), f);
         assert!(matches!(res,
                 Ok(Some(Entry::File { ref path, exe, size, .. }))
                 if path == Path::new("/dir/test") && exe && size == 100),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"  test s ../dest", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"  test s ../dest", Path::new("/dir"), f);
         assert!(matches!(res,
                 Ok(Some(Entry::Link(ref path, ref dest)))
                 if path == Path::new("/dir/test") && dest == Path::new("../dest")),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"  test f x00", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"  test f x00", Path::new("/dir"), f);
         assert!(matches!(res,
                 Err(ParseRowError::InvalidInt(..))),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"  test l ../dest", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"  test l ../dest", Path::new("/dir"), f);
         assert!(matches!(res,
                 Err(ParseRowError::InvalidFileType(ref t))
                 if t == "l"),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"  test s  ../dest", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"  test s  ../dest", Path::new("/dir"), f);
         assert!(matches!(res,
                 Err(ParseRowError::InvalidLine(ref msg))
                 if msg == "Row has multiple spaces"),
             "Result was: {:?}", res);
 
-        let res = Entry::parse(b"  test s ../dest tail", Path::new("/dir"), t, b);
+        let res = Entry::parse(b"  test s ../dest tail", Path::new("/dir"), f);
+        assert!(matches!(res,
+                Err(ParseRowError::InvalidSymlink(ref tail))
+                if tail == "tail"),
+            "Result was: {:?}", res);
+
+        let res = Entry::parse(b"  test s ../dest extra=1",
+            Path::new("/dir"), f);
+        assert!(matches!(res,
+                Err(ParseRowError::InvalidSymlink(ref tail))
+                if tail == "extra=1"),
+            "Result was: {:?}", res);
+    }
+
+    #[test]
+    fn test_parse_owner() {
+        let f = RowFormat {
+            hash_type: HashType::sha512_256(),
+            block_size: 32768,
+            ownership: true,
+            chunked: false,
+        };
+        let res = Entry::parse_with_owner(b"/dir future=1 gid=2 uid=1",
+            Path::new("/"), f).unwrap().unwrap();
+        assert_eq!(res, (Entry::Dir("/dir".into()), Some((1, 2))));
+
+        let res = Entry::parse_with_owner(b"  test f 0 uid=1 gid=2",
+            Path::new("/dir"), f).unwrap().unwrap();
+        assert_eq!(res.1, Some((1, 2)));
+
+        let res = Entry::parse_with_owner(b"/dir future=1",
+            Path::new("/"), f).unwrap().unwrap();
+        assert_eq!(res.1, None);
+
+        let res = Entry::parse(b"/dir uid=1", Path::new("/"), f);
         assert!(matches!(res,
                 Err(ParseRowError::InvalidLine(ref msg))
-                if msg.starts_with("Entry is not fully consumed: \"tail\"")),
+                if msg == "Both uid and gid must be specified"),
+            "Result was: {:?}", res);
+
+        let res = Entry::parse(b"/dir uid=x gid=2", Path::new("/"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidInt(_))),
+            "Result was: {:?}", res);
+
+        // symlinks and whiteouts have no ownership
+        let res = Entry::parse(b"  test s ../dest uid=1 gid=2",
+            Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidLine(_))),
+            "Result was: {:?}", res);
+        let res = Entry::parse(b"  test w gid=2", Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidLine(_))),
+            "Result was: {:?}", res);
+        let res = Entry::parse(b"  test s ../dest future=1",
+            Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidSymlink(_))),
+            "Result was: {:?}", res);
+
+        // only allowed if header has `ownership=yes`
+        let f = RowFormat { ownership: false, ..f };
+        let res = Entry::parse(b"/dir uid=1 gid=2", Path::new("/"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidLine(_))),
+            "Result was: {:?}", res);
+        let res = Entry::parse(b"  test f 0 uid=1 gid=2",
+            Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidLine(_))),
+            "Result was: {:?}", res);
+        let res = Entry::parse(b"  test s ../dest uid=1 gid=2",
+            Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidSymlink(_))),
+            "Result was: {:?}", res);
+    }
+
+    #[test]
+    fn test_parse_chunks() {
+        let f = RowFormat {
+            hash_type: HashType::sha512_256(),
+            block_size: 32768,
+            ownership: true,
+            chunked: true,
+        };
+        let h1 = "8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc";
+        let h2 = "c384d6b21c50e0aa9bf80124256d56ba36c6a05ce0cc09bf858fa09e84aa19d4";
+        let row = format!("  test f 100 40:{} 60:{} uid=1 gid=2", h1, h2);
+        let (entry, owner) = Entry::parse_with_owner(row.as_bytes(),
+            Path::new("/dir"), f).unwrap().unwrap();
+        assert_eq!(owner, Some((1, 2)));
+        match entry {
+            Entry::File { size, hashes, .. } => {
+                assert_eq!(size, 100);
+                assert_eq!(hashes.len(), 2);
+                assert_eq!(hashes.chunk_lengths(), Some(&[40, 60][..]));
+                assert!(hashes.matches_size(100));
+            }
+            _ => panic!("Not a file: {:?}", entry),
+        }
+
+        let res = Entry::parse(b"  test f 0", Path::new("/dir"), f);
+        assert!(matches!(res,
+                Ok(Some(Entry::File { ref hashes, .. }))
+                if hashes.chunk_lengths() == Some(&[][..])),
+            "Result was: {:?}", res);
+
+        let row = format!("  test f 100 40:{} 61:{}", h1, h2);
+        let res = Entry::parse(row.as_bytes(), Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidHash(_))),
+            "Result was: {:?}", res);
+        let row = format!("  test f 100 0:{} 100:{}", h1, h2);
+        let res = Entry::parse(row.as_bytes(), Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidHash(_))),
+            "Result was: {:?}", res);
+        // fixed-size blocks in the chunked index
+        let row = format!("  test f 100 {}", h1);
+        let res = Entry::parse(row.as_bytes(), Path::new("/dir"), f);
+        assert!(matches!(res, Err(ParseRowError::InvalidHash(_))),
+            "Result was: {:?}", res);
+
+        let res = Header::parse(
+            b"DIRSIGNATURE.v1 sha512/256 block_size=32768 chunking=cdc");
+        assert!(res.unwrap().row_format().chunked);
+        let res = Header::parse(
+            b"DIRSIGNATURE.v1 sha512/256 block_size=32768 chunking=rabin");
+        assert!(matches!(res, Err(ParseRowError::InvalidHeader(_))),
             "Result was: {:?}", res);
     }
 
@@ -1188,6 +2702,56 @@ mod test {
         assert_eq!(size, 7);
     }
 
+    #[test]
+    fn test_hashes_covered_len() {
+        // 58394 bytes is not a multiple of the block size
+        let hashes = Hashes::new(vec![0; 64], HashType::sha512_256(), 32768);
+        assert_eq!(hashes.covered_len(), 65536);
+        assert_eq!(hashes.covered_len().min(58394), 58394);
+        assert!(hashes.matches_size(58394));
+        assert!(hashes.matches_size(65536));
+        assert!(!hashes.matches_size(32768));
+        assert!(!hashes.matches_size(65537));
+
+        let empty = Hashes::new(vec![], HashType::sha512_256(), 32768);
+        assert_eq!(empty.covered_len(), 0);
+        assert!(empty.matches_size(0));
+        assert!(!empty.matches_size(1));
+    }
+
+    #[test]
+    fn test_hashes_changed_bytes() {
+        let t = HashType::sha512_256();
+        let old = Hashes::new([[1; 32], [2; 32], [3; 32]].concat(), t, 32768);
+        let new = Hashes::new([[1; 32], [9; 32], [3; 32]].concat(), t, 32768);
+        assert_eq!(old.changed_bytes(&new, 70000, 70000), 32768);
+        assert_eq!(old.changed_bytes(&old, 70000, 70000), 0);
+
+        // only the final partial block changed
+        let new = Hashes::new([[1; 32], [2; 32], [9; 32]].concat(), t, 32768);
+        assert_eq!(old.changed_bytes(&new, 70000, 70000), 70000 - 65536);
+
+        // appended data, the last partial block changes too
+        let new = Hashes::new([[1; 32], [2; 32], [8; 32], [4; 32]].concat(),
+                              t, 32768);
+        assert_eq!(old.changed_bytes(&new, 70000, 100000), 32768 + 1696);
+    }
+
+    #[test]
+    fn test_hashes_missing_blocks() {
+        use std::collections::HashSet;
+
+        let t = HashType::sha512_256();
+        let hashes = Hashes::new(
+            [[1; 32], [2; 32], [3; 32], [4; 32]].concat(), t, 32768);
+        let (b1, b3) = ([1u8; 32], [3u8; 32]);
+        let available = [&b1[..], &b3[..]].iter().cloned()
+            .collect::<HashSet<_>>();
+        assert_eq!(hashes.missing_blocks(&available), vec![1, 3]);
+        assert_eq!(hashes.missing_blocks(&HashSet::new()),
+                   vec![0, 1, 2, 3]);
+    }
+
     #[test]
     fn test_footer_parse() {
         let res = Footer::parse(
@@ -1219,6 +2783,19 @@ mod test {
                 Err(ParseRowError::InvalidHex(ref msg))
                 if msg == "Character ord: 120"),
             "Result was: {:?}", res);
+
+        let res = Footer::parse(
+            b"=8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc",
+            HashType::sha512_256());
+        assert!(matches!(res,
+                Ok(Footer(ref data))
+                if data == &"8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc".from_hex().unwrap()),
+            "Result was: {:?}", res);
+
+        let res = Footer::parse(b"8dd499a36d950b87", HashType::sha512_256());
+        assert!(matches!(res,
+                Err(ParseRowError::InvalidFooterLength(_, 64, 16))),
+            "Result was: {:?}", res);
     }
 
     #[test]
@@ -1277,4 +2854,28 @@ mod test {
         assert_eq!(res, OsStr::new("test 123"));
         assert!(matches!(res, Cow::Owned(_)));
     }
+
+    #[test]
+    fn test_read_line_bytes() {
+        let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+        let mut reader = Cursor::new(&content[..]);
+        let mut total = 0;
+        let mut lines = 0;
+        let mut buf = Vec::new();
+        while (reader.position() as usize) < content.len() {
+            buf.clear();
+            total += read_line(&mut reader, &mut buf, Some(100)).unwrap();
+            lines += 1;
+        }
+        assert_eq!(lines, 5);
+        assert_eq!(total, content.len());
+        assert_eq!(&buf[..],
+            &b"c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb"[..]);
+    }
 }
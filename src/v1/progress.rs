@@ -1,20 +1,24 @@
 use std::io;
+use std::ffi::OsStr;
 use std::sync::Arc;
 use std::path::Path;
 use std::time::{Instant, Duration};
 
 use super::writer::Writer;
-use openat::{Dir, Entry};
-use crate::{Error};
+use super::source::DirSource;
+use crate::{Error, ProgressFormat, ScannerConfig};
 
 
 pub struct Progress<W, S> {
     dest: W,
     progress_dest: S,
     last_print: Instant,
+    interval: Duration,
+    terminator: &'static str,
     files: u64,
     dirs: u64,
     symlinks: u64,
+    bytes: u64,
     started: Instant,
 }
 
@@ -22,25 +26,39 @@ fn duration_float(d: Duration) -> f64 {
     d.as_secs() as f64 + d.subsec_nanos() as f64 / 1_000_000_000.
 }
 
+fn mebibytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024. * 1024.)
+}
+
 impl<W: Writer, S: io::Write> Progress<W, S> {
-    pub fn new(out: S, hasher: W) -> Progress<W, S> {
+    pub fn new(out: S, hasher: W, config: &ScannerConfig) -> Progress<W, S> {
         Progress {
             dest: hasher,
             progress_dest: out,
             last_print: Instant::now(),
+            interval: config.progress_interval,
+            terminator: match config.progress_format {
+                ProgressFormat::Overwrite => "\r",
+                ProgressFormat::Lines => "\n",
+            },
             files: 0,
             dirs: 0,
             symlinks: 0,
+            bytes: 0,
             started: Instant::now(),
         }
     }
     pub fn check_print(&mut self) {
         let now = Instant::now();
-        if now.duration_since(self.last_print) > Duration::from_millis(100) {
+        if now.duration_since(self.last_print) >= self.interval {
             self.last_print = now;
+            let elapsed = duration_float(now.duration_since(self.started));
             write!(&mut self.progress_dest,
-                "Indexing... {} dirs, {} files, {} symlinks\r",
-                self.dirs, self.files, self.symlinks).ok();
+                "Indexing... {} dirs, {} files, {} symlinks, \
+                 {:.1} MiB ({:.1} MiB/s){}",
+                self.dirs, self.files, self.symlinks,
+                mebibytes(self.bytes), mebibytes(self.bytes) / elapsed,
+                self.terminator).ok();
             self.progress_dest.flush().ok();
         }
     }
@@ -51,23 +69,35 @@ impl<W: Writer, S: io::Write> Writer for Progress<W, S>
     where W::TotalHash: ::std::fmt::LowerHex,
 {
     type TotalHash = W::TotalHash;
-    fn start_dir(&mut self, path: &Path) -> Result<(), Error> {
+    fn start_dir(&mut self, path: &Path, owner: Option<(u32, u32)>)
+        -> Result<(), Error>
+    {
         self.dirs += 1;
-        self.dest.start_dir(path)?;
+        self.dest.start_dir(path, owner)?;
         self.check_print();
         Ok(())
     }
-    fn add_file(&mut self, dir: &Arc<Dir>, entry: Entry) -> Result<(), Error> {
+    fn add_file<D: DirSource>(&mut self, dir: &Arc<D>, name: &OsStr)
+        -> Result<(), Error>
+    {
         self.files += 1;
-        self.dest.add_file(dir, entry)?;
+        // with threaded writer this counts bytes queued for hashing, but
+        // it's close enough for progress reporting
+        self.bytes += dir.metadata(name).map(|m| m.size).unwrap_or(0);
+        self.dest.add_file(dir, name)?;
         self.check_print();
         Ok(())
     }
-    fn add_symlink(&mut self, dir: &Arc<Dir>, entry: Entry)
+    fn add_symlink(&mut self, name: &OsStr, dest: &Path)
         -> Result<(), Error>
     {
         self.symlinks += 1;
-        self.dest.add_symlink(dir, entry)?;
+        self.dest.add_symlink(name, dest)?;
+        self.check_print();
+        Ok(())
+    }
+    fn add_whiteout(&mut self, name: &OsStr) -> Result<(), Error> {
+        self.dest.add_whiteout(name)?;
         self.check_print();
         Ok(())
     }
@@ -79,8 +109,8 @@ impl<W: Writer, S: io::Write> Writer for Progress<W, S>
         self.dest.done()?;
         write!(&mut self.progress_dest,
             "Done {:.8x}. Indexed {} dirs, \
-             {} files, {} symlinks in {:.3} sec.\n",
-            hash, self.dirs, self.files, self.symlinks,
+             {} files, {} symlinks, {:.1} MiB in {:.3} sec.\n",
+            hash, self.dirs, self.files, self.symlinks, mebibytes(self.bytes),
             duration_float(Instant::now().duration_since(self.started)),
             ).ok();
         self.progress_dest.flush().ok();
@@ -88,3 +118,62 @@ impl<W: Writer, S: io::Write> Writer for Progress<W, S>
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsStr;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use openat::Dir;
+
+    use crate::{ProgressFormat, ScannerConfig};
+    use crate::v1::hash::Sha512_256;
+    use crate::v1::writer::{Writer, SyncWriter};
+    use super::Progress;
+
+    #[test]
+    fn test_bytes() {
+        let mut out = Vec::new();
+        let writer = SyncWriter::new(&mut out, Sha512_256::new(),
+            &ScannerConfig::new()).unwrap();
+        let mut progress = Progress::new(Vec::new(), writer,
+            &ScannerConfig::new());
+        let dir = Arc::new(Dir::open("tests/dir2/subdir").unwrap());
+        progress.start_dir(Path::new("/"), None).unwrap();
+        progress.add_file(&dir, OsStr::new("bigdata.bin")).unwrap();
+        assert_eq!(progress.bytes, 81920);
+        progress.add_file(&dir, OsStr::new("file3.txt")).unwrap();
+        assert_eq!(progress.bytes, 81932);
+        progress.done().unwrap();
+    }
+
+    fn progress_output(format: ProgressFormat) -> String {
+        let mut cfg = ScannerConfig::new();
+        cfg.progress_format(format);
+        cfg.progress_interval(Duration::from_secs(0));
+        let writer = SyncWriter::new(Vec::new(), Sha512_256::new(), &cfg)
+            .unwrap();
+        let mut progress = Progress::new(Vec::new(), writer, &cfg);
+        progress.start_dir(Path::new("/"), None).unwrap();
+        progress.start_dir(Path::new("/sub"), None).unwrap();
+        String::from_utf8(progress.progress_dest).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let out = progress_output(ProgressFormat::Overwrite);
+        assert!(out.starts_with("Indexing... 1 dirs,"));
+        assert_eq!(out.matches('\r').count(), 2);
+        assert!(out.ends_with("MiB/s)\r"));
+        assert!(!out.contains('\n'));
+
+        let out = progress_output(ProgressFormat::Lines);
+        assert_eq!(out.lines().count(), 2);
+        let last = out.lines().nth(1).unwrap();
+        assert!(last.starts_with("Indexing... 2 dirs,"));
+        assert!(out.ends_with("MiB/s)\n"));
+        assert!(!out.contains('\r'));
+    }
+}
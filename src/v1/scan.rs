@@ -1,25 +1,42 @@
-use std::path::{Path, PathBuf};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 use std::collections::VecDeque;
 
 use openat::Dir;
 use itertools::Itertools;
 
-use crate::{ScannerConfig, Error};
+use crate::{ScannerConfig, Error, CollisionPolicy, TargetMode};
 use crate::Error::{OpenDir as EDir, ListDir as EList, ReadFile as ERead};
-use super::writer::Writer;
+use super::source::{DirSource, EntryType};
+use super::writer::{Writer, retry};
 
 
-fn find_roots(config: &ScannerConfig)
-    -> Result<Vec<(Arc<Dir>, PathBuf)>, Error>
-{
+/// Kind of the directory entry as written to the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Dir,
+    File,
+    Symlink,
+    Whiteout,
+}
+
+impl From<EntryType> for Kind {
+    fn from(typ: EntryType) -> Kind {
+        match typ {
+            EntryType::Dir => Kind::Dir,
+            EntryType::Symlink => Kind::Symlink,
+            EntryType::File | EntryType::Other => Kind::File,
+        }
+    }
+}
+
+pub(crate) fn find_roots(config: &ScannerConfig) -> Result<Vec<Dir>, Error> {
     let mut root = Vec::new();
     for &(ref path, ref prefix) in &config.dirs {
         if prefix == Path::new("/") {
-            root.push((
-                Arc::new(Dir::open(path).map_err(EDir)?),
-                PathBuf::from("."),
-            ));
+            root.push(Dir::open(path).map_err(EDir)?);
         }
     }
     if root.len() == 0 {
@@ -28,68 +45,237 @@ fn find_roots(config: &ScannerConfig)
     return Ok(root);
 }
 
-pub fn scan<W: Writer>(config: &ScannerConfig, mut index: W)
-    -> Result<(), Error>
+/// Returns skipped paths if `collect_skipped` is enabled
+pub fn scan<S: DirSource, W: Writer>(config: &ScannerConfig, roots: Vec<S>,
+    mut index: W)
+    -> Result<Vec<PathBuf>, Error>
 {
-    use openat::SimpleType as T;
     let mut queue = VecDeque::new();
+    let mut skipped = Vec::new();
+    // directories without files that are not written yet (for pruning)
+    let mut pending: Vec<(PathBuf, Option<(u32, u32)>)> = Vec::new();
 
-    queue.push_back((PathBuf::from("/"), find_roots(config)?));
+    // `None` name means the directory itself, it's used for roots only
+    queue.push_back((PathBuf::from("/"), roots.into_iter()
+        .map(|dir| (Arc::new(dir), None::<OsString>))
+        .collect::<Vec<_>>()));
 
     while queue.len() > 0 {
         let (path, dirs) = queue.pop_front().unwrap();
+        let mut entries = Vec::new();
+        let mut owner = None;
+        for (base, name) in dirs {
+            let dir = match name {
+                Some(name) => Arc::new(base.sub_dir(&name).map_err(EList)?),
+                None => base,
+            };
+            if config.store_ownership && owner.is_none() {
+                // if several directories are merged, the first one wins
+                let meta = dir.self_metadata().map_err(ERead)?;
+                owner = Some((meta.uid, meta.gid));
+            }
+            for (name, typ) in dir.list_dir().map_err(EList)? {
+                if let Some(cutoff) = config.mtime_cutoff {
+                    if typ != EntryType::Dir {
+                        let meta = dir.metadata(&name).map_err(ERead)?;
+                        if meta.mtime > cutoff {
+                            debug!("File {:?} is newer than cutoff, skipping",
+                                path.join(&name));
+                            if config.collect_skipped {
+                                skipped.push(path.join(&name));
+                            }
+                            continue;
+                        }
+                    }
+                }
+                if typ == EntryType::Other {
+                    warn!("File {:?} has unknown type, ignoring",
+                        path.join(&name));
+                    if config.collect_skipped {
+                        skipped.push(path.join(&name));
+                    }
+                    continue;
+                }
+                if config.overlay_whiteouts && typ == EntryType::File {
+                    if let Some(target) = whiteout_target(&name) {
+                        entries.push((dir.clone(), target.to_os_string(),
+                                      Kind::Whiteout));
+                        continue;
+                    }
+                }
+                entries.push((dir.clone(), name, typ.into()));
+            }
+        }
+        // stable sort keeps the order of source directories for each name
+        entries.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
         let mut subdirs = Vec::new();
         let mut files = Vec::new();
-        for (base, name) in dirs {
-            let dir = Arc::new(base.sub_dir(&name).map_err(EList)?);
-            for entry in dir.list_dir(".").map_err(EList)? {
-                let entry = entry.map_err(EList)?;
-                let typ = match entry.simple_type() {
-                    Some(x) => x,
-                    None => dir.metadata(&entry).map_err(ERead)?.simple_type(),
-                };
-                match typ {
-                    T::Dir => subdirs.push((dir.clone(), entry)),
-                    T::Symlink => files.push((dir.clone(), entry, true)),
-                    T::File => files.push((dir.clone(), entry, false)),
-                    T::Other => {
-                        let base = dir.recover_path()
-                            // if recover fails, use destination path
-                            // this is better than nothing anyway
-                            .unwrap_or(path.clone());
-                        warn!("File {:?} has unknown type, ignoring",
-                            base.join(entry.file_name()));
+        for (name, group) in &entries.into_iter()
+            .group_by(|(_, name, _)| name.clone())
+        {
+            let mut group = group.collect::<Vec<_>>();
+            let is_dir = |(_, _, kind): &(_, _, Kind)| *kind == Kind::Dir;
+            if group.len() > 1 && !group.iter().all(is_dir) {
+                let winner = match config.on_collision {
+                    CollisionPolicy::Error => {
+                        return Err(Error::PathCollision(path.join(&name)));
                     }
+                    CollisionPolicy::FirstWins => 0,
+                    CollisionPolicy::LastWins => group.len() - 1,
+                };
+                if is_dir(&group[winner]) {
+                    group.retain(is_dir);
+                } else {
+                    group = vec![group.swap_remove(winner)];
+                }
+            }
+            for (dir, name, kind) in group {
+                match kind {
+                    Kind::Dir => subdirs.push((dir, name)),
+                    kind => files.push((dir, name, kind)),
                 }
             }
         }
-        files.sort_by(|&(_, ref a, _), &(_, ref b, _)| {
-            a.file_name().cmp(&b.file_name())
-        });
-        index.start_dir(&path)?;
-        for (dir, entry, is_symlink) in files {
-            // TODO(tailhook) deduplicate!
-            if is_symlink {
-                index.add_symlink(&dir, entry)?;
+        if config.prune_empty_dirs {
+            // traversal is depth-first, so everything pending that is not
+            // a parent of this directory has no files in its subtree
+            pending.retain(|(dir, _)| path.starts_with(dir));
+            if files.is_empty() && path != Path::new("/") {
+                pending.push((path.clone(), owner));
             } else {
-                index.add_file(&dir, entry)?;
+                for (dir, owner) in pending.drain(..) {
+                    index.start_dir(&dir, owner)?;
+                }
+                index.start_dir(&path, owner)?;
             }
+        } else {
+            index.start_dir(&path, owner)?;
         }
-        subdirs.sort_by(|&(_, ref a), &(_, ref b)| {
-            b.file_name().cmp(&a.file_name())  // note: reverse sort
+        for (dir, name, kind) in files {
+            if kind == Kind::Whiteout {
+                index.add_whiteout(&name)?;
+            } else if kind == Kind::Symlink {
+                let dest = retry(config.io_retries, || dir.read_link(&name))
+                    .map_err(ERead)?;
+                let dest = match config.symlink_targets {
+                    TargetMode::Verbatim => dest,
+                    TargetMode::Relative if dest.is_absolute() => {
+                        relative_target(&path, &dest)
+                    }
+                    TargetMode::Absolute if dest.is_relative() => {
+                        normalize(&path.join(dest))
+                    }
+                    _ => dest,
+                };
+                index.add_symlink(&name, &dest)?;
+            } else {
+                index.add_file(&dir, &name)?;
+            }
+        }
+        if let Some(max_depth) = config.max_depth {
+            // root is `/`, i.e. a single component at depth zero
+            if path.components().count() > max_depth {
+                subdirs.clear();
+            }
+        }
+        subdirs.sort_by(|(_, a), (_, b)| {
+            b.cmp(a)  // note: reverse sort
         });
         for (dirpath, seq) in subdirs.into_iter()
-            .group_by(|&(_, ref e)| path.join(e.file_name())).into_iter()
+            .group_by(|&(_, ref name)| path.join(name)).into_iter()
         {
             // TODO(tailhook) deduplicate! (kinda)
             queue.push_front((
                 dirpath,
-                seq.map(|(base, entry)|{
-                    (base, Path::new(entry.file_name()).to_path_buf())
-                }).collect()
+                seq.map(|(base, name)| (base, Some(name))).collect()
             ));
         }
     }
     index.done()?;
-    Ok(())
+    Ok(skipped)
+}
+
+/// Prefix of the file names of overlay whiteouts
+pub(crate) const WHITEOUT_PREFIX: &str = ".wh.";
+/// Marker of the opaque directory, we don't support it
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// Returns the name of the removed entry if `name` is a whiteout
+fn whiteout_target(name: &OsStr) -> Option<&OsStr> {
+    let bytes = name.as_bytes();
+    if bytes.len() > WHITEOUT_PREFIX.len() &&
+        bytes.starts_with(WHITEOUT_PREFIX.as_bytes()) &&
+        name != OPAQUE_MARKER
+    {
+        Some(OsStr::from_bytes(&bytes[WHITEOUT_PREFIX.len()..]))
+    } else {
+        None
+    }
+}
+
+/// Returns path of the whiteout file for the removed entry `path`
+pub(crate) fn whiteout_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(WHITEOUT_PREFIX);
+    name.push(path.file_name().unwrap_or_else(|| OsStr::new("")));
+    path.with_file_name(name)
+}
+
+/// Resolves `.` and `..` in the absolute path without touching file system
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => result.push(name),
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    result
+}
+
+/// Returns path of the `dest` relative to the directory `dir`
+fn relative_target(dir: &Path, dest: &Path) -> PathBuf {
+    let dest = normalize(dest);
+    let mut dir_iter = dir.components().peekable();
+    let mut dest_iter = dest.components().peekable();
+    while let (Some(a), Some(b)) = (dir_iter.peek(), dest_iter.peek()) {
+        if a != b {
+            break;
+        }
+        dir_iter.next();
+        dest_iter.next();
+    }
+    let mut result = PathBuf::new();
+    for _ in dir_iter {
+        result.push("..");
+    }
+    result.extend(dest_iter);
+    if result.as_os_str().is_empty() {
+        result.push(".");
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use super::{normalize, relative_target};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/a/../../c")), Path::new("/c"));
+    }
+
+    #[test]
+    fn test_relative_target() {
+        let rel = |dir, dest| relative_target(Path::new(dir), Path::new(dest));
+        assert_eq!(rel("/a/b", "/a/c"), Path::new("../c"));
+        assert_eq!(rel("/a/b", "/a/b/c"), Path::new("c"));
+        assert_eq!(rel("/", "/a/c"), Path::new("a/c"));
+        assert_eq!(rel("/a", "/"), Path::new(".."));
+        assert_eq!(rel("/a", "/a"), Path::new("."));
+    }
 }
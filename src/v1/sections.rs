@@ -0,0 +1,119 @@
+use std::io::{self, BufRead, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::{ScannerConfig, Error};
+use crate::Error::WriteError as EWrite;
+use super::parser::{row_error, strip_newline, unescape_hex, ParseRowError};
+use super::writer::Name;
+use super::{scan, Parser, ParseError};
+
+const SECTION: &[u8] = b"SECTION ";
+
+type Section = (String, Parser<io::Cursor<Vec<u8>>>);
+
+/// Writes several independent indexes into a single stream
+///
+/// Each index is preceded by a `SECTION <label>` line (label is escaped
+/// the same way as file names are) and has its own header and footer, so
+/// its hash is the same as if it was scanned by [`scan`](fn.scan.html).
+///
+/// All indexes are built in memory first, so nothing is written to `out`
+/// if any of them fails. Use [`Sections`](struct.Sections.html) to read
+/// them back.
+pub fn scan_many<F: Write>(configs: &[(&str, ScannerConfig)], out: &mut F)
+    -> Result<(), Error>
+{
+    let mut bufs = Vec::with_capacity(configs.len());
+    for &(label, ref config) in configs {
+        let mut buf = Vec::new();
+        scan(config, &mut buf)?;
+        bufs.push((label, buf));
+    }
+    for (label, buf) in bufs {
+        writeln!(out, "SECTION {}", Name(Path::new(label))).map_err(EWrite)?;
+        out.write_all(&buf).map_err(EWrite)?;
+    }
+    Ok(())
+}
+
+/// Iterator over sections written by [`scan_many`](fn.scan_many.html)
+///
+/// Yields a label and a parser for every section. Each section is read into
+/// memory as a whole. Line numbers in errors returned by the parsers are
+/// relative to the start of the section.
+pub struct Sections<R: BufRead> {
+    reader: R,
+    line: Vec<u8>,
+    line_num: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Sections<R> {
+    /// Creates an iterator over sections of the reader
+    pub fn new(reader: R) -> Sections<R> {
+        Sections {
+            reader,
+            line: Vec::new(),
+            line_num: 0,
+            done: false,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<usize, ParseError> {
+        self.line.clear();
+        self.line_num += 1;
+        let bytes = self.reader.read_until(b'\n', &mut self.line)
+            .map_err(|e| row_error(self.line_num, e.into()))?;
+        if bytes > 0 {
+            strip_newline(&mut self.line)
+                .map_err(|e| row_error(self.line_num, e))?;
+        }
+        Ok(bytes)
+    }
+
+    fn read_section(&mut self)
+        -> Result<Option<Section>, ParseError>
+    {
+        if self.line.is_empty() && self.read_line()? == 0 {
+            return Ok(None);
+        }
+        let label = match self.line.strip_prefix(SECTION) {
+            Some(label) => unescape_hex(OsStr::from_bytes(label)),
+            None => {
+                return Err(row_error(self.line_num,
+                    ParseRowError::InvalidLine(
+                        "Expected a SECTION line".into())));
+            }
+        };
+        let label = label.to_str()
+            .ok_or_else(|| row_error(self.line_num,
+                ParseRowError::InvalidLine(
+                    "Section label is not valid utf-8".into())))?
+            .to_string();
+        let mut buf = Vec::new();
+        while self.read_line()? > 0 {
+            if self.line.starts_with(SECTION) {
+                break;
+            }
+            buf.extend_from_slice(&self.line);
+            buf.push(b'\n');
+        }
+        Ok(Some((label, Parser::new(io::Cursor::new(buf))?)))
+    }
+}
+
+impl<R: BufRead> Iterator for Sections<R> {
+    type Item = Result<Section, ParseError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_section().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
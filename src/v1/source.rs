@@ -0,0 +1,132 @@
+use std::io;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use openat::{Dir, SimpleType};
+
+
+/// Type of an entry in the directory listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    /// A directory
+    Dir,
+    /// A regular file
+    File,
+    /// A symbolic link
+    Symlink,
+    /// Anything else (devices, sockets, pipes), such entries are skipped
+    Other,
+}
+
+/// Metadata of the entry that is needed by the scanner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Permission bits of the entry, only execute bits are used
+    pub mode: u32,
+    /// Modification time
+    pub mtime: SystemTime,
+    /// Owner user id
+    pub uid: u32,
+    /// Owner group id
+    pub gid: u32,
+}
+
+/// A directory that can be indexed
+///
+/// This allows to index things other than the real file system, for example
+/// an in-memory tree or contents of an archive. The implementation for
+/// `openat::Dir` is used by [`scan`](fn.scan.html).
+///
+/// All names are relative to this directory and never contain a slash.
+pub trait DirSource: Sized + Send + Sync + 'static {
+    /// A file opened for reading
+    type File: io::Read;
+
+    /// Lists the directory
+    ///
+    /// Entries `.` and `..` must not be returned, order doesn't matter.
+    fn list_dir(&self) -> io::Result<Vec<(OsString, EntryType)>>;
+    /// Opens a subdirectory
+    fn sub_dir(&self, name: &OsStr) -> io::Result<Self>;
+    /// Returns metadata of this directory itself
+    fn self_metadata(&self) -> io::Result<Metadata>;
+    /// Returns metadata of the entry, not following symlinks
+    fn metadata(&self, name: &OsStr) -> io::Result<Metadata>;
+    /// Opens a file for reading
+    ///
+    /// Returned metadata must describe the opened file, so size is
+    /// consistent with the data read from the file.
+    fn open_file(&self, name: &OsStr) -> io::Result<(Self::File, Metadata)>;
+    /// Reads the destination of a symlink
+    fn read_link(&self, name: &OsStr) -> io::Result<PathBuf>;
+}
+
+fn stat_metadata(meta: &openat::Metadata) -> Metadata {
+    let stat = meta.stat();
+    let nsec = Duration::new(0, stat.st_mtime_nsec as u32);
+    let mtime = if stat.st_mtime >= 0 {
+        UNIX_EPOCH + Duration::from_secs(stat.st_mtime as u64) + nsec
+    } else {
+        UNIX_EPOCH - Duration::from_secs(stat.st_mtime.unsigned_abs()) + nsec
+    };
+    Metadata {
+        size: stat.st_size as u64,
+        mode: meta.permissions().mode() & 0o7777,
+        mtime,
+        uid: stat.st_uid,
+        gid: stat.st_gid,
+    }
+}
+
+impl DirSource for Dir {
+    type File = File;
+
+    fn list_dir(&self) -> io::Result<Vec<(OsString, EntryType)>> {
+        let mut result = Vec::new();
+        for entry in Dir::list_dir(self, ".")? {
+            let entry = entry?;
+            let typ = match entry.simple_type() {
+                Some(x) => x,
+                None => Dir::metadata(self, &entry)?.simple_type(),
+            };
+            let typ = match typ {
+                SimpleType::Dir => EntryType::Dir,
+                SimpleType::File => EntryType::File,
+                SimpleType::Symlink => EntryType::Symlink,
+                SimpleType::Other => EntryType::Other,
+            };
+            result.push((entry.file_name().to_os_string(), typ));
+        }
+        Ok(result)
+    }
+    fn sub_dir(&self, name: &OsStr) -> io::Result<Dir> {
+        Dir::sub_dir(self, name)
+    }
+    fn self_metadata(&self) -> io::Result<Metadata> {
+        Ok(stat_metadata(&Dir::self_metadata(self)?))
+    }
+    fn metadata(&self, name: &OsStr) -> io::Result<Metadata> {
+        Ok(stat_metadata(&Dir::metadata(self, name)?))
+    }
+    fn open_file(&self, name: &OsStr) -> io::Result<(File, Metadata)> {
+        let f = Dir::open_file(self, name)?;
+        let meta = f.metadata()?;
+        let mtime = meta.modified()?;
+        Ok((f, Metadata {
+            size: meta.len(),
+            mode: meta.permissions().mode() & 0o7777,
+            mtime,
+            uid: meta.uid(),
+            gid: meta.gid(),
+        }))
+    }
+    fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        Dir::read_link(self, name)
+    }
+}
+
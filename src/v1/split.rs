@@ -0,0 +1,78 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Error::{self, WriteError as EWrite};
+use crate::v1::{Emitter, Entry, Parser};
+
+
+/// Splits an index into separate indexes for each of the prefixes
+///
+/// For every prefix a writer is created using `out` function, and a valid
+/// index of the subtree of that prefix is written into it. Paths are
+/// re-rooted, i.e. `/etc/ssl` becomes `/ssl` in the index for `/etc`.
+/// Entries that are not inside any of the prefixes are skipped, entries
+/// inside nested prefixes are written into each of them.
+///
+/// If a prefix is not found in the index, an index with just an empty
+/// root directory is written for it. If a prefix is a file or a symlink in
+/// the index, `Error::NotADirectory` is returned.
+///
+/// Returns writers in the same order as `prefixes`.
+pub fn split_by_prefix<R, W, F>(parser: &mut Parser<R>, prefixes: &[PathBuf],
+    out: F)
+    -> Result<Vec<W>, Error>
+    where R: BufRead, W: Write, F: Fn(&Path) -> W,
+{
+    let header = parser.get_header();
+    let mut outputs = prefixes.iter().map(|p| out(p)).collect::<Vec<_>>();
+    {
+        let mut emitters = Vec::with_capacity(outputs.len());
+        for dest in outputs.iter_mut() {
+            emitters.push(Emitter::with_header(&header, dest)
+                .map_err(EWrite)?);
+        }
+        let mut found = vec![false; prefixes.len()];
+        for entry in parser.iter() {
+            let entry = entry?;
+            let targets = prefixes.iter().zip(&mut emitters).zip(&mut found);
+            for ((prefix, emitter), found) in targets {
+                let path = match entry.path().strip_prefix(prefix) {
+                    Ok(path) => Path::new("/").join(path),
+                    Err(_) => continue,
+                };
+                match entry {
+                    Entry::Dir(_) => {
+                        *found = true;
+                        emitter.start_dir(&path).map_err(EWrite)?;
+                    }
+                    _ if entry.path() == prefix => {
+                        return Err(Error::NotADirectory(prefix.clone()));
+                    }
+                    Entry::File { exe, size, ref hashes, .. } => {
+                        emitter.add_file(file_name(&path), exe, size, hashes)
+                            .map_err(EWrite)?;
+                    }
+                    Entry::Link(_, ref dest) => {
+                        emitter.add_symlink(file_name(&path), dest)
+                            .map_err(EWrite)?;
+                    }
+                    Entry::Whiteout(_) => {
+                        emitter.add_whiteout(file_name(&path))
+                            .map_err(EWrite)?;
+                    }
+                }
+            }
+        }
+        for (emitter, found) in emitters.iter_mut().zip(found) {
+            if !found {
+                emitter.start_dir(Path::new("/")).map_err(EWrite)?;
+            }
+            emitter.finish().map_err(EWrite)?;
+        }
+    }
+    Ok(outputs)
+}
+
+fn file_name(path: &Path) -> &::std::ffi::OsStr {
+    path.file_name().expect("file has a name")
+}
@@ -0,0 +1,63 @@
+//! A module for aggregating information from an index
+//!
+//!
+//! Entry points:
+//!
+//! * [`dir_sizes`](fn.dir_sizes.html) for the total size of files directly
+//!   in each directory
+//! * [`dir_sizes_recursive`](fn.dir_sizes_recursive.html) for the total size
+//!   of files in each directory including subdirectories
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use super::{Entry, Parser, ParseError};
+
+
+/// Returns the sum of file sizes directly within each directory
+///
+/// Every directory of the index is in the result, directories that have
+/// no files (only subdirectories, symlinks or nothing at all) have zero
+/// size.
+pub fn dir_sizes<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<BTreeMap<PathBuf, u64>, ParseError>
+{
+    let mut sizes = BTreeMap::new();
+    let mut current = None;
+    for entry in parser.iter() {
+        match entry? {
+            Entry::Dir(path) => {
+                sizes.insert(path.clone(), 0);
+                current = Some(path);
+            }
+            Entry::File { size, .. } => {
+                let dir = current.as_ref().expect("file is in a directory");
+                *sizes.get_mut(dir).expect("directory is inserted") += size;
+            }
+            Entry::Link(..) | Entry::Whiteout(..) => {}
+        }
+    }
+    Ok(sizes)
+}
+
+/// Returns the sum of file sizes within each directory and its
+/// subdirectories
+///
+/// The value for `/` is the total size of all files in the index.
+pub fn dir_sizes_recursive<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<BTreeMap<PathBuf, u64>, ParseError>
+{
+    let direct = dir_sizes(parser)?;
+    let mut sizes = direct.keys()
+        .map(|path| (path.clone(), 0))
+        .collect::<BTreeMap<_, _>>();
+    for (path, size) in direct {
+        for dir in path.ancestors() {
+            if let Some(total) = sizes.get_mut(dir) {
+                *total += size;
+            }
+        }
+    }
+    Ok(sizes)
+}
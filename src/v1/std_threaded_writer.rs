@@ -0,0 +1,90 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender};
+use std::sync::mpsc::TryRecvError;
+use std::thread::{self, JoinHandle};
+
+use crate::ScannerConfig;
+use crate::error::Error;
+use crate::v1::hash::Hash;
+use crate::v1::writer::FileEntry;
+use crate::v1::threaded_writer::{Pool, ThreadedWriter};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads running jobs from a bounded channel
+///
+/// Used instead of `CpuPool` by the `ThreadedWriter` when only
+/// `std::thread` and channels are available
+pub(crate) struct StdPool {
+    jobs: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl StdPool {
+    fn new(threads: usize, queue_size: usize) -> StdPool {
+        let (tx, rx) = sync_channel::<Job>(queue_size);
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..threads).map(|_| {
+            let rx = rx.clone();
+            thread::spawn(move || loop {
+                // lock is released before running the job
+                let job = rx.lock().expect("job queue is poisoned").recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            })
+        }).collect();
+        StdPool { jobs: Some(tx), workers }
+    }
+}
+
+impl Pool for StdPool {
+    type Pending = Receiver<Result<FileEntry, Error>>;
+    fn spawn<F>(&self, func: F) -> Self::Pending
+        where F: FnOnce() -> Result<FileEntry, Error> + Send + 'static
+    {
+        let (tx, rx) = channel();
+        self.jobs.as_ref().expect("pool is running")
+            .send(Box::new(move || {
+                // receiver is dropped if writer has failed, that's fine
+                tx.send(func()).ok();
+            }))
+            .expect("hashing threads are running");
+        rx
+    }
+    fn poll(rx: &mut Self::Pending, blocking: bool)
+        -> Result<Option<FileEntry>, Error>
+    {
+        if blocking {
+            return rx.recv().expect("hashing thread panicked").map(Some);
+        }
+        match rx.try_recv() {
+            Ok(entry) => entry.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                panic!("hashing thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for StdPool {
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+impl<F: io::Write, H: Hash> ThreadedWriter<F, H, StdPool> {
+    /// Same as `ThreadedWriter::new` but uses only `std::thread`
+    pub fn new_std(threads: usize, f: F, hash: H, config: &ScannerConfig)
+        -> Result<ThreadedWriter<F, H, StdPool>, Error>
+    {
+        ThreadedWriter::with_pool(StdPool::new(threads, threads*16), threads,
+            f, hash, config)
+    }
+}
@@ -0,0 +1,49 @@
+use std::io::BufRead;
+
+use crate::v1::{Entry, Parser, ParseError};
+use crate::v1::hash;
+use crate::v1::writer::Name;
+
+
+/// Computes a hash of the directory structure described by the index
+///
+/// The hash covers paths, kinds and sizes of the entries, but neither
+/// block hashes, symlink destinations nor the footer. So two trees with
+/// the same layout produce the same structural hash even if contents of
+/// some files differ (while their sizes are the same).
+///
+/// The hash (of the same type as in the index header) is computed over the
+/// following lines, one per entry in the order of the index, each
+/// terminated by a newline:
+///
+/// * `d <path>` for a directory
+/// * `f <path> <size>` for a file, or `x <path> <size>` if it's executable
+/// * `s <path>` for a symlink
+/// * `w <path>` for a whiteout
+///
+/// Where `<path>` is a full path escaped in the same way as in the index.
+pub fn structural_hash<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<Vec<u8>, ParseError>
+{
+    let hash_type = parser.get_header().get_hash_type();
+    hash::digest(hash_type, |out| {
+        for entry in parser.iter() {
+            match entry? {
+                Entry::Dir(ref path) => {
+                    writeln!(out, "d {}", Name(path))
+                }
+                Entry::File { ref path, exe, size, .. } => {
+                    writeln!(out, "{} {} {}",
+                        if exe { "x" } else { "f" }, Name(path), size)
+                }
+                Entry::Link(ref path, _) => {
+                    writeln!(out, "s {}", Name(path))
+                }
+                Entry::Whiteout(ref path) => {
+                    writeln!(out, "w {}", Name(path))
+                }
+            }.expect("writing to hasher never fails");
+        }
+        Ok(())
+    })
+}
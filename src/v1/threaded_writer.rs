@@ -1,99 +1,115 @@
 use std::collections::VecDeque;
+use std::ffi::OsStr;
 use std::io::{self, Write};
 use std::sync::Arc;
 use std::path::{Path, PathBuf};
-use std::os::unix::fs::PermissionsExt;
 
-use futures::{Async, Future, executor};
-use openat::{Dir, Entry};
-use futures_cpupool::{CpuPool, CpuFuture};
+#[cfg(feature="threads")] use futures::{Async, Future, executor};
+#[cfg(feature="threads")] use futures_cpupool::{CpuPool, CpuFuture};
 
+use crate::ScannerConfig;
 use crate::error::Error::{self, WriteError as EWrite, ReadFile as EFile};
-use crate::v1::writer::{Writer, HashWriter, Name, EXE_MASK, MAGIC, VERSION};
+use crate::v1::writer::{Writer, HashWriter, FileEntry, Name, Owner, Blocks};
+use crate::v1::writer::{hash_file_stable, retry, write_header, FOOTER_MARKER};
 use crate::v1::hash::Hash;
+use crate::v1::source::DirSource;
 
+/// A set of threads hashing files for the `ThreadedWriter`
+pub(crate) trait Pool {
+    /// A file that is being hashed
+    type Pending;
+    fn spawn<F>(&self, func: F) -> Self::Pending
+        where F: FnOnce() -> Result<FileEntry, Error> + Send + 'static;
+    /// Returns `None` if file is not hashed yet and `blocking` is false
+    fn poll(pending: &mut Self::Pending, blocking: bool)
+        -> Result<Option<FileEntry>, Error>;
+}
+
+#[cfg(feature="threads")]
 #[derive(Clone)]
 struct Notify;
 
-struct FileEntry {
-    file_name: PathBuf,
-    exe: bool,
-    size: u64,
-    hashes: String,
-}
-
-enum Operation {
-    StartDir(PathBuf),
-    File(CpuFuture<FileEntry, Error>),
-    Symlink(Arc<Dir>, Entry),
+enum Operation<P: Pool> {
+    StartDir(PathBuf, Option<(u32, u32)>),
+    File(P::Pending),
+    Symlink(PathBuf, PathBuf),
+    Whiteout(PathBuf),
 }
 
-pub struct ThreadedWriter<F, H: Hash> {
-    pool: CpuPool,
+pub(crate) struct ThreadedWriter<F, H: Hash, P: Pool> {
+    pool: P,
     file: HashWriter<F, H>,
-    block_size: u64,
+    blocks: Blocks,
+    io_retries: u32,
+    stable_read: u32,
+    exe_mask: u32,
+    store_ownership: bool,
+    marked_footer: bool,
     hash: H,
     queue_limit: usize,
-    queue: VecDeque<Operation>,
+    queue: VecDeque<Operation<P>>,
 }
 
-impl<F: io::Write, H: Hash> ThreadedWriter<F, H> {
-    pub fn new(threads: usize, mut f: F, hash: H, block_size: u64)
-        -> Result<ThreadedWriter<F, H>, Error>
+#[cfg(feature="threads")]
+impl<F: io::Write, H: Hash> ThreadedWriter<F, H, CpuPool> {
+    pub fn new(threads: usize, f: F, hash: H, config: &ScannerConfig)
+        -> Result<ThreadedWriter<F, H, CpuPool>, Error>
     {
-        writeln!(&mut f,
-            "{}.{} {} block_size={}",
-            MAGIC,
-            VERSION,
-            hash.name(),
-            block_size,
-        ).map_err(EWrite)?;
+        ThreadedWriter::with_pool(CpuPool::new(threads), threads,
+            f, hash, config)
+    }
+}
+
+impl<F: io::Write, H: Hash, P: Pool> ThreadedWriter<F, H, P> {
+    pub(crate) fn with_pool(pool: P, threads: usize, mut f: F, hash: H,
+        config: &ScannerConfig)
+        -> Result<ThreadedWriter<F, H, P>, Error>
+    {
+        let blocks = Blocks::new(config)?;
+        write_header(&mut f, &hash, config).map_err(EWrite)?;
         Ok(ThreadedWriter {
             file: HashWriter { file: f, digest: hash.clone() },
-            block_size: block_size,
+            blocks,
+            io_retries: config.io_retries,
+            stable_read: config.stable_read,
+            exe_mask: config.exe_bit.mask(),
+            store_ownership: config.store_ownership,
+            marked_footer: config.marked_footer,
             hash: hash,
             queue_limit: threads*16,
             queue: VecDeque::with_capacity(threads*16),
-            pool: CpuPool::new(threads),
+            pool,
         })
     }
-    fn poll_item(&mut self, item: Operation, blocking: bool)
+    fn poll_item(&mut self, item: Operation<P>, blocking: bool)
         -> Result<bool, Error>
     {
         match item {
-            Operation::StartDir(ref path) => {
-                writeln!(&mut self.file, "{}", Name(path))
+            Operation::StartDir(ref path, owner) => {
+                writeln!(&mut self.file, "{}{}", Name(path), Owner(owner))
                     .map_err(EWrite)?;
             }
-            Operation::File(mut fut) => {
-                let entry = if blocking {
-                        fut.wait()?
-                    } else {
-                        let r = executor::spawn(&mut fut)
-                            .poll_future_notify(&&Notify, 0);
-                        match r? {
-                            Async::Ready(entry) => entry,
-                            Async::NotReady => {
-                                self.queue.push_front(Operation::File(fut));
-                                return Ok(false);
-                            }
-                        }
-                    };
-
-                write!(&mut self.file, "  {} {} {}{}\n",
-                    Name(&entry.file_name),
-                    if entry.exe { "x" } else { "f" },
-                    entry.size,
-                    entry.hashes,  // includes space
-                ).map_err(EWrite)?;
+            Operation::File(mut pending) => {
+                let entry = match P::poll(&mut pending, blocking)? {
+                    Some(entry) => entry,
+                    None => {
+                        self.queue.push_front(Operation::File(pending));
+                        return Ok(false);
+                    }
+                };
+                entry.write_to(&mut self.file, self.store_ownership)
+                    .map_err(EWrite)?;
             }
-            Operation::Symlink(dir, entry) => {
-                let dest = dir.read_link(&entry).map_err(EFile)?;
+            Operation::Symlink(ref name, ref dest) => {
                 write!(&mut self.file, "  {} s {}\n",
-                    Name(&Path::new(entry.file_name())),
-                    Name(&dest),
+                    Name(name),
+                    Name(dest),
                 ).map_err(EWrite)?;
             }
+            Operation::Whiteout(ref name) => {
+                writeln!(&mut self.file, "  {} w", Name(name))
+                    .map_err(EWrite)?;
+            }
         }
         return Ok(true);
     }
@@ -115,44 +131,43 @@ impl<F: io::Write, H: Hash> ThreadedWriter<F, H> {
     }
 }
 
-impl<F: io::Write, H: Hash> Writer for ThreadedWriter<F, H> {
+impl<F: io::Write, H: Hash, P: Pool> Writer for ThreadedWriter<F, H, P> {
     type TotalHash = H::Output;
-    fn start_dir(&mut self, path: &Path) -> Result<(), Error> {
+    fn start_dir(&mut self, path: &Path, owner: Option<(u32, u32)>)
+        -> Result<(), Error>
+    {
         // TODO(tailhook) optimize allocation if no queue is present
-        self.queue.push_back(Operation::StartDir(path.to_path_buf()));
+        self.queue.push_back(Operation::StartDir(path.to_path_buf(), owner));
         self.poll_queue()
     }
-    fn add_file(&mut self, dir: &Arc<Dir>, entry: Entry)
+    fn add_file<D: DirSource>(&mut self, dir: &Arc<D>, name: &OsStr)
         -> Result<(), Error>
     {
-        use std::fmt::Write;
         let dir = dir.clone();
-        let block_size = self.block_size;
-        let mut hash = self.hash.clone();
-        self.queue.push_back(Operation::File(self.pool.spawn_fn(move || {
-            let mut f = dir.open_file(&entry).map_err(EFile)?;
-            let meta = f.metadata().map_err(EFile)?;
-            let mut n = meta.len();
-            let mut buf = String::with_capacity((33*n/block_size) as usize);
-            while n > 0 {
-                let h = hash.hash_file(&mut f, block_size).map_err(EFile)?;
-                write!(&mut buf, " {:x}", h).unwrap();
-                n = n.saturating_sub(block_size);
-            }
-            Ok(FileEntry {
-                file_name: Path::new(entry.file_name()).to_path_buf(),
-                exe: meta.permissions().mode() & EXE_MASK > 0,
-                size: meta.len(),
-                hashes: buf,
-            })
+        let name = name.to_os_string();
+        let blocks = self.blocks;
+        let io_retries = self.io_retries;
+        let stable_read = self.stable_read;
+        let exe_mask = self.exe_mask;
+        let hash = self.hash.clone();
+        self.queue.push_back(Operation::File(self.pool.spawn(move || {
+            retry(io_retries, || {
+                hash_file_stable(&*dir, &name, &mut hash.clone(), blocks,
+                    stable_read, exe_mask)
+            }).map_err(EFile)
         })));
         self.poll_queue()
     }
-    fn add_symlink(&mut self, dir: &Arc<Dir>, entry: Entry)
+    fn add_symlink(&mut self, name: &OsStr, dest: &Path)
         -> Result<(), Error>
     {
         // TODO(tailhook) optimize allocation if no queue is present
-        self.queue.push_back(Operation::Symlink(dir.clone(), entry));
+        self.queue.push_back(Operation::Symlink(PathBuf::from(name),
+            dest.to_path_buf()));
+        self.poll_queue()
+    }
+    fn add_whiteout(&mut self, name: &OsStr) -> Result<(), Error> {
+        self.queue.push_back(Operation::Whiteout(PathBuf::from(name)));
         self.poll_queue()
     }
     fn get_hash(&mut self) -> Result<Self::TotalHash, Error> {
@@ -162,10 +177,33 @@ impl<F: io::Write, H: Hash> Writer for ThreadedWriter<F, H> {
     fn done(mut self) -> Result<(), Error>
     {
         let hash = self.get_hash()?;
-        write!(&mut self.file.file, "{:x}\n", hash).map_err(EFile)
+        let marker = if self.marked_footer { FOOTER_MARKER } else { "" };
+        writeln!(&mut self.file.file, "{}{:x}", marker, hash).map_err(EFile)
+    }
+}
+
+#[cfg(feature="threads")]
+impl Pool for CpuPool {
+    type Pending = CpuFuture<FileEntry, Error>;
+    fn spawn<F>(&self, func: F) -> Self::Pending
+        where F: FnOnce() -> Result<FileEntry, Error> + Send + 'static
+    {
+        self.spawn_fn(func)
+    }
+    fn poll(fut: &mut Self::Pending, blocking: bool)
+        -> Result<Option<FileEntry>, Error>
+    {
+        if blocking {
+            return fut.wait().map(Some);
+        }
+        match executor::spawn(fut).poll_future_notify(&&Notify, 0)? {
+            Async::Ready(entry) => Ok(Some(entry)),
+            Async::NotReady => Ok(None),
+        }
     }
 }
 
+#[cfg(feature="threads")]
 impl executor::Notify for Notify {
     fn notify(&self, _: usize) {
         // nothing to notify, it's fine, we'll just poll when needed
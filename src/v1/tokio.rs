@@ -0,0 +1,94 @@
+//! Asynchronous reader of the signature file
+//!
+//! Only reading is asynchronous, parsing is done by the same code as in
+//! the synchronous [`Parser`](../struct.Parser.html).
+use std::path::PathBuf;
+
+use ::tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::v1::{Entry, Header, ParseError};
+use crate::v1::parser::{parse_header, parse_row, strip_newline};
+use crate::v1::parser::{extra_lines_error, row_error, ParseRowError};
+
+
+/// v1 format parser that reads from an `AsyncBufRead`
+pub struct AsyncParser<R: AsyncBufRead + Unpin> {
+    header: Header,
+    reader: R,
+    current_row: Vec<u8>,
+    current_row_num: usize,
+    current_dir: PathBuf,
+    exhausted: bool,
+}
+
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>)
+    -> Result<(), ParseRowError>
+{
+    buf.clear();
+    let _ = reader.read_until(b'\n', buf).await?;
+    strip_newline(buf)
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncParser<R> {
+    /// Creates a directory signature parser (format v1)
+    /// Reads and parses header
+    pub async fn new(mut reader: R) -> Result<AsyncParser<R>, ParseError> {
+        let mut header_line = Vec::new();
+        read_line(&mut reader, &mut header_line).await
+            .map_err(|e| row_error(1, e))?;
+        let header = parse_header(&header_line, false)?;
+        Ok(AsyncParser {
+            header,
+            reader,
+            current_row: header_line,
+            current_row_num: 1,
+            current_dir: PathBuf::new(),
+            exhausted: false,
+        })
+    }
+
+    /// Returns parsed `Header`
+    pub fn get_header(&self) -> Header {
+        self.header.clone()
+    }
+
+    /// Reads next entry of the signature file
+    ///
+    /// Returns `None` after the footer is read and validated. Like the
+    /// synchronous iterator, it should not be called again after an error.
+    pub async fn next_entry(&mut self) -> Option<Result<Entry, ParseError>> {
+        if self.exhausted {
+            return None;
+        }
+        self.current_row_num += 1;
+        let row_num = self.current_row_num;
+        if let Err(e) = read_line(&mut self.reader, &mut self.current_row)
+            .await
+        {
+            return Some(Err(row_error(row_num, e)));
+        }
+        let entry = parse_row(&self.current_row, row_num,
+            &mut self.current_dir,
+            self.header.row_format());
+        match entry {
+            Ok(Some((entry, _owner))) => Some(Ok(entry)),
+            Ok(None) => {
+                let mut test_buf = [0; 1];
+                match self.reader.read(&mut test_buf).await {
+                    Ok(0) => {
+                        self.exhausted = true;
+                        None
+                    }
+                    Ok(_) => Some(Err(extra_lines_error(row_num))),
+                    Err(e) => Some(Err(row_error(row_num, e.into()))),
+                }
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Returns the underlying reader
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+}
@@ -0,0 +1,104 @@
+//! A module for building an in-memory tree of the index
+//!
+//!
+//! Entry points:
+//!
+//! * [`build`](fn.build.html) reads the whole index into a
+//!   [`DirTree`](struct.DirTree.html)
+
+use std::ffi::OsStr;
+use std::io::BufRead;
+use std::path::{Component, Path, PathBuf};
+
+use crate::v1::{Entry, Parser, ParseError};
+
+
+/// A directory with its files and subdirectories
+///
+/// Files and subdirectories are in the same order as in the index (i.e.
+/// sorted by name).
+#[derive(Debug)]
+pub struct DirTree {
+    path: PathBuf,
+    files: Vec<Entry>,
+    dirs: Vec<DirTree>,
+}
+
+impl DirTree {
+    /// Absolute path of the directory in the index
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Name of the directory (`None` for the root directory)
+    pub fn name(&self) -> Option<&OsStr> {
+        self.path.file_name()
+    }
+    /// Files and symlinks of this directory
+    pub fn files(&self) -> &[Entry] {
+        &self.files
+    }
+    /// Subdirectories of this directory
+    pub fn children(&self) -> &[DirTree] {
+        &self.dirs
+    }
+    /// Finds a subdirectory by name
+    pub fn child<N: AsRef<OsStr>>(&self, name: N) -> Option<&DirTree> {
+        let name = name.as_ref();
+        self.dirs.binary_search_by(|d| d.name().unwrap_or_default().cmp(name))
+            .ok().map(|idx| &self.dirs[idx])
+    }
+    /// Finds a directory by path relative to this one
+    ///
+    /// Absolute paths are looked up from this directory too, so
+    /// `root.find("/subdir")` works for the root of the tree.
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<&DirTree> {
+        let mut dir = self;
+        for component in path.as_ref().components() {
+            match component {
+                Component::Normal(name) => dir = dir.child(name)?,
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir | Component::Prefix(_) => return None,
+            }
+        }
+        Some(dir)
+    }
+}
+
+/// Reads the whole index into a tree of directories
+///
+/// All entries are kept in memory, so this is not suitable for huge indexes.
+pub fn build<R: BufRead>(parser: &mut Parser<R>) -> Result<DirTree, ParseError>
+{
+    let mut stack: Vec<DirTree> = Vec::new();
+    for entry in parser.iter() {
+        match entry? {
+            Entry::Dir(path) => {
+                while stack.len() > 1 &&
+                    !path.starts_with(&stack[stack.len()-1].path)
+                {
+                    let dir = stack.pop().expect("stack is not empty");
+                    stack.last_mut().expect("stack is not empty")
+                        .dirs.push(dir);
+                }
+                stack.push(DirTree {
+                    path,
+                    files: Vec::new(),
+                    dirs: Vec::new(),
+                });
+            }
+            entry => {
+                stack.last_mut().expect("files are always in a directory")
+                    .files.push(entry);
+            }
+        }
+    }
+    while stack.len() > 1 {
+        let dir = stack.pop().expect("stack is not empty");
+        stack.last_mut().expect("stack is not empty").dirs.push(dir);
+    }
+    Ok(stack.pop().unwrap_or_else(|| DirTree {
+        path: PathBuf::from("/"),
+        files: Vec::new(),
+        dirs: Vec::new(),
+    }))
+}
@@ -0,0 +1,65 @@
+//! A module for checking the signature file without reading the directory
+//!
+//!
+//! Entry points:
+//!
+//! * [`check_structure`](fn.check_structure.html) for checking that every
+//!   line of the file is well-formed
+//! * [`check_grouping`](fn.check_grouping.html) for checking that files
+//!   belong to the directory they are listed under
+
+use std::io::BufRead;
+
+use crate::v1::{Entry, Parser, ParseError};
+use crate::v1::parser::{row_error, ParseRowError};
+
+
+/// Checks that all the lines of the signature file are well-formed
+///
+/// This includes checking that every file has exactly as many hashes as
+/// needed for its size and block size, that every hash has proper length
+/// and that the file ends with a valid footer. Contents of the files are not
+/// checked, and the footer hash is not compared against the data.
+///
+/// Returns the first error found.
+pub fn check_structure<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<(), ParseError>
+{
+    for entry in parser.iter() {
+        entry?;
+    }
+    Ok(())
+}
+
+/// Checks that every file is listed under its own directory
+///
+/// Files (and symlinks, whiteouts) following a directory line must have
+/// that directory as their parent, until the next directory line. A file
+/// name containing a slash, `..` or an absolute path breaks this and usually
+/// means the file is corrupt.
+///
+/// Returns the first error found, including errors of the
+/// [`check_structure`](fn.check_structure.html).
+pub fn check_grouping<R: BufRead>(parser: &mut Parser<R>)
+    -> Result<(), ParseError>
+{
+    let mut dir = None;
+    // header is the first line
+    for (idx, entry) in parser.iter().enumerate() {
+        let entry = entry?;
+        let path = match entry {
+            Entry::Dir(path) => {
+                dir = Some(path);
+                continue;
+            }
+            Entry::File { ref path, .. } | Entry::Link(ref path, _) |
+            Entry::Whiteout(ref path) => path,
+        };
+        if path.parent() != dir.as_deref() {
+            return Err(row_error(idx + 2, ParseRowError::InvalidLine(
+                format!("{:?} doesn't belong to directory {:?}",
+                    path, dir.as_deref().unwrap_or("".as_ref())))));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,577 @@
+//! A module for verifying a directory against an index
+//!
+//!
+//! Entry points:
+//!
+//! * [`verify_dir`](fn.verify_dir.html) for finding all differences between
+//!   index and directory
+//! * [`verify_dir_fast`](fn.verify_dir_fast.html) for just checking whether
+//!   directory matches the index
+//! * [`verify_paths`](fn.verify_paths.html) for checking only selected files
+//! * [`verify_dir_with`](fn.verify_dir_with.html) for verifying with
+//!   [`VerifyOptions`](struct.VerifyOptions.html)
+//! * [`changed_heuristic`](fn.changed_heuristic.html) for a quick list of
+//!   likely changed files, without reading their contents
+//! * [`verify_dir_streaming`](fn.verify_dir_streaming.html) for reporting
+//!   results file by file, e.g. to show progress
+//! * [`verify_against_union`](fn.verify_against_union.html) for verifying
+//!   a directory against several overlaid signature files
+//!
+//! Verification is driven by the index: directories are listed and files are
+//! read only when index contains them, so the memory usage is proportional
+//! to the size of the largest directory rather than the whole tree.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::mem;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use openat::{Dir, SimpleType};
+
+use crate::Error::{self, OpenDir as EDir, ListDir as EList, ReadFile as ERead};
+use crate::v1::{Entry, EntryKind, Parser};
+use crate::v1::merge::{write_union, MergeError, MergedSignatures};
+use crate::v1::writer::EXE_MASK;
+use crate::v1::scan::whiteout_path;
+
+
+/// A single difference between the index and the directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// Entry is in the index but not in the directory
+    Missing(PathBuf),
+    /// Entry is in the directory but not in the index
+    Extra(PathBuf),
+    /// Entry has a different type in the directory (e.g. a directory
+    /// or a symlink instead of a file)
+    TypeMismatch(PathBuf),
+    /// Executable bit of the file differs
+    ExeMismatch(PathBuf),
+    /// Size of the file differs
+    SizeMismatch {
+        /// Path of the file
+        path: PathBuf,
+        /// Size recorded in the index
+        expected: u64,
+        /// Size of the file in the directory
+        actual: u64,
+    },
+    /// Contents of the file differ
+    ContentMismatch(PathBuf),
+    /// Symlink points to a different destination
+    LinkMismatch {
+        /// Path of the symlink
+        path: PathBuf,
+        /// Destination recorded in the index
+        expected: PathBuf,
+        /// Destination of the symlink in the directory
+        actual: PathBuf,
+    },
+}
+
+impl Discrepancy {
+    /// Path of the entry (relative to the root of the index)
+    pub fn path(&self) -> &Path {
+        use self::Discrepancy::*;
+        match *self {
+            Missing(ref path) | Extra(ref path) | TypeMismatch(ref path) |
+            ExeMismatch(ref path) | ContentMismatch(ref path) |
+            SizeMismatch { ref path, .. } | LinkMismatch { ref path, .. }
+            => path,
+        }
+    }
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Discrepancy::*;
+        match *self {
+            Missing(ref path) => write!(f, "{:?}: missing", path),
+            Extra(ref path) => write!(f, "{:?}: not in index", path),
+            TypeMismatch(ref path) => write!(f, "{:?}: type mismatch", path),
+            ExeMismatch(ref path) => {
+                write!(f, "{:?}: executable bit mismatch", path)
+            }
+            SizeMismatch { ref path, expected, actual } => {
+                write!(f, "{:?}: size {} (expected {})",
+                    path, actual, expected)
+            }
+            ContentMismatch(ref path) => {
+                write!(f, "{:?}: content mismatch", path)
+            }
+            LinkMismatch { ref path, ref expected, ref actual } => {
+                write!(f, "{:?}: symlink to {:?} (expected {:?})",
+                    path, actual, expected)
+            }
+        }
+    }
+}
+
+/// Result of verification of a single entry
+///
+/// Passed to the callback of
+/// [`verify_dir_streaming`](fn.verify_dir_streaming.html)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVerifyResult {
+    /// Path of the entry (relative to the root of the index)
+    pub path: PathBuf,
+    /// The difference found or `None` if entry matches the index
+    pub discrepancy: Option<Discrepancy>,
+    /// Size of the file if its contents were read, zero otherwise
+    pub bytes: u64,
+}
+
+/// Verifies directory against the index and returns all the differences
+///
+/// Empty result means directory matches the index.
+pub fn verify_dir<R, P>(parser: &mut Parser<R>, root: P)
+    -> Result<Vec<Discrepancy>, Error>
+    where R: BufRead, P: AsRef<Path>,
+{
+    let mut result = Vec::new();
+    walk(parser, root.as_ref(), |d| {
+        result.push(d);
+        true
+    })?;
+    Ok(result)
+}
+
+/// Verifies directory against the index calling `report` for every entry
+///
+/// The callback is called for every file, symlink and whiteout in the index
+/// right after it's checked, whether it matches or not. Differences that
+/// are not related to a single file of the index (extra entries, missing
+/// directories) are reported too, with zero `bytes`.
+///
+/// Returns `true` if directory matches the index.
+pub fn verify_dir_streaming<R, P, F>(parser: &mut Parser<R>, root: P,
+    mut report: F)
+    -> Result<bool, Error>
+    where R: BufRead, P: AsRef<Path>, F: FnMut(FileVerifyResult),
+{
+    _walk(parser, root.as_ref(), &mut |result| {
+        report(result);
+        true
+    })
+}
+
+/// Options of the directory verification
+///
+/// Used by [`verify_dir_with`](fn.verify_dir_with.html)
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    strict_types: bool,
+    ignore_exe_bit: bool,
+}
+
+impl VerifyOptions {
+    /// Create options with defaults
+    pub fn new() -> VerifyOptions {
+        VerifyOptions::default()
+    }
+    /// Stop verification on the first `TypeMismatch`
+    ///
+    /// Type mismatch (e.g. a directory in place of a file, or a file in
+    /// place of a symlink) usually means that the directory is not the one
+    /// the index was built for, so there is little sense in checking the
+    /// rest of it. By default such entries are reported and verification
+    /// continues (skipping contents of mismatched directories).
+    pub fn strict_types(&mut self) -> &mut Self {
+        self.strict_types = true;
+        self
+    }
+    /// Don't report files that differ only in the executable bit
+    ///
+    /// Useful for file systems that don't preserve permissions (e.g. some
+    /// network mounts). Size and contents of the files are still checked.
+    pub fn ignore_exe_bit(&mut self) -> &mut Self {
+        self.ignore_exe_bit = true;
+        self
+    }
+}
+
+/// Verifies directory against the index using the specified options
+///
+/// Returns differences found. In strict mode the last element is the
+/// type mismatch that stopped the verification.
+pub fn verify_dir_with<R, P>(parser: &mut Parser<R>, root: P,
+    options: &VerifyOptions)
+    -> Result<Vec<Discrepancy>, Error>
+    where R: BufRead, P: AsRef<Path>,
+{
+    let mut result = Vec::new();
+    walk(parser, root.as_ref(), |d| {
+        // exe bit is checked last, so size and contents match here
+        if options.ignore_exe_bit &&
+            matches!(d, Discrepancy::ExeMismatch(..))
+        {
+            return true;
+        }
+        let stop = options.strict_types &&
+            matches!(d, Discrepancy::TypeMismatch(..));
+        result.push(d);
+        !stop
+    })?;
+    Ok(result)
+}
+
+/// Verifies directory against the index and returns `false` on the first
+/// difference
+///
+/// Nothing is read from the directory after the first difference is found.
+pub fn verify_dir_fast<R, P>(parser: &mut Parser<R>, root: P)
+    -> Result<bool, Error>
+    where R: BufRead, P: AsRef<Path>,
+{
+    walk(parser, root.as_ref(), |_| false)
+}
+
+/// Verifies directory against the union (overlay) of several signatures
+///
+/// Signatures in `merged` are layers in order, for every path the entry
+/// from the last layer wins and whiteouts remove entries of the previous
+/// layers, exactly like [`merge::union`](../merge/fn.union.html) does.
+/// So the directory must look like all the layers extracted on top of
+/// each other.
+///
+/// The union is built in memory before verification, so memory usage is
+/// proportional to the size of the resulting index.
+///
+/// # Panics
+///
+/// If there are no signatures in `merged`
+pub fn verify_against_union<K, R, P>(merged: &mut MergedSignatures<K, R>,
+    root: P)
+    -> Result<Vec<Discrepancy>, Error>
+    where R: BufRead, P: AsRef<Path>,
+{
+    let mut buf = Vec::new();
+    write_union(merged, &mut buf).map_err(|e| match e {
+        MergeError::Parse(e) => Error::Parse(e),
+        MergeError::Io(_, e) => Error::WriteError(e),
+        e => unreachable!("merged signatures are validated: {}", e),
+    })?;
+    let mut parser = Parser::new(io::Cursor::new(buf))?;
+    verify_dir(&mut parser, root)
+}
+
+/// Verifies only the specified files and symlinks against the index
+///
+/// Paths are absolute paths in the index (i.e. `/dir/file`), not paths in
+/// the file system. Paths that are not files or symlinks in the index are
+/// reported as `Extra` if they exist in the directory and are ignored
+/// otherwise. The index is read sequentially up to the last requested path,
+/// but only requested files are read from the directory.
+pub fn verify_paths<R, P>(parser: &mut Parser<R>, root: P, paths: &[PathBuf])
+    -> Result<Vec<Discrepancy>, Error>
+    where R: BufRead, P: AsRef<Path>,
+{
+    let root = Dir::open(root.as_ref()).map_err(EDir)?;
+    let mut kinds = paths.iter()
+        .filter(|p| p.is_absolute() && p.file_name().is_some())
+        .map(|p| EntryKind::File(p.as_path()))
+        .collect::<Vec<_>>();
+    kinds.sort();
+    kinds.dedup();
+    let mut result = Vec::new();
+    let mut iter = parser.iter();
+    for kind in kinds {
+        let path = kind.path();
+        let name = path.file_name().expect("filtered above");
+        let parent = path.parent().expect("filtered above");
+        let rel = parent.strip_prefix("/").unwrap_or(parent);
+        let dir = if rel == Path::new("") {
+            root.sub_dir(".")
+        } else {
+            root.sub_dir(rel)
+        };
+        let typ = match dir.and_then(|dir| {
+            let typ = dir.metadata(name)?.simple_type();
+            Ok((dir, typ))
+        }) {
+            Ok(x) => Some(x),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(ERead(e)),
+        };
+        match (iter.advance(&kind).transpose()?, typ) {
+            (Some(entry), Some((dir, typ))) => {
+                result.extend(check_entry(&dir, typ, entry)?);
+            }
+            (Some(entry), None) => {
+                result.push(Discrepancy::Missing(entry.path().to_path_buf()));
+            }
+            (None, Some(_)) => {
+                result.push(Discrepancy::Extra(path.to_path_buf()));
+            }
+            (None, None) => {}
+        }
+    }
+    Ok(result)
+}
+
+/// Returns files that are likely changed, without reading their contents
+///
+/// A file is reported when it is missing in the directory, is not a regular
+/// file anymore, or its size differs from the one in the index. Index
+/// doesn't store modification times, so files changed in place without
+/// changing size are not detected: use [`verify_dir`](fn.verify_dir.html)
+/// for a full check. New files in the directory are not reported either.
+///
+/// Returned paths are absolute paths in the index in the index order.
+pub fn changed_heuristic<R, P>(parser: &mut Parser<R>, root: P)
+    -> Result<Vec<PathBuf>, Error>
+    where R: BufRead, P: AsRef<Path>,
+{
+    let root = Dir::open(root.as_ref()).map_err(EDir)?;
+    let mut result = Vec::new();
+    for entry in parser.iter() {
+        if let Entry::File { path, size, .. } = entry? {
+            let rel = path.strip_prefix("/").unwrap_or(&path);
+            let changed = match root.metadata(rel) {
+                Ok(meta) => {
+                    meta.simple_type() != SimpleType::File ||
+                    meta.stat().st_size as u64 != size
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => true,
+                Err(e) => return Err(ERead(e)),
+            };
+            if changed {
+                result.push(path);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Compares a file or a symlink from the index with the one in `dir`
+///
+/// `typ` is a type of the entry in the file system (for whiteouts it's
+/// the type of the `.wh.<name>` file)
+fn check_entry(dir: &Dir, typ: SimpleType, entry: Entry)
+    -> Result<Option<Discrepancy>, Error>
+{
+    match entry {
+        Entry::Dir(..) => unreachable!("only files and symlinks are checked"),
+        Entry::File { path, exe, size, hashes } => {
+            if typ != SimpleType::File {
+                return Ok(Some(Discrepancy::TypeMismatch(path)));
+            }
+            let name = path.file_name().expect("file has a name");
+            let meta = dir.metadata(name).map_err(ERead)?;
+            let actual = meta.stat().st_size as u64;
+            if actual != size {
+                return Ok(Some(Discrepancy::SizeMismatch {
+                    path, expected: size, actual,
+                }));
+            }
+            // zero-byte files have no hashes, nothing to read
+            if size > 0 || hashes.len() > 0 {
+                let f = dir.open_file(name).map_err(ERead)?;
+                if !hashes.check_file(f).map_err(ERead)? {
+                    return Ok(Some(Discrepancy::ContentMismatch(path)));
+                }
+            }
+            if (meta.permissions().mode() & EXE_MASK > 0) != exe {
+                return Ok(Some(Discrepancy::ExeMismatch(path)));
+            }
+            Ok(None)
+        }
+        Entry::Link(path, expected) => {
+            if typ != SimpleType::Symlink {
+                return Ok(Some(Discrepancy::TypeMismatch(path)));
+            }
+            let name = path.file_name().expect("symlink has a name");
+            let actual = dir.read_link(name).map_err(ERead)?;
+            if actual != expected {
+                return Ok(Some(Discrepancy::LinkMismatch {
+                    path, expected, actual,
+                }));
+            }
+            Ok(None)
+        }
+        Entry::Whiteout(path) => {
+            if typ != SimpleType::File {
+                return Ok(Some(Discrepancy::TypeMismatch(path)));
+            }
+            Ok(None)
+        }
+    }
+}
+
+struct Walker<'a> {
+    root: Dir,
+    current: Option<(PathBuf, Dir, HashMap<OsString, SimpleType>)>,
+    /// Entries found in the directory that are not matched by the index yet
+    ///
+    /// Value is `true` for directories. Directories can only be matched by
+    /// the index later, but we keep all kinds of entries to detect
+    /// directories in the index that are files in the filesystem.
+    pending: BTreeMap<PathBuf, bool>,
+    /// Subtree that is already reported and must be skipped
+    skip: Option<PathBuf>,
+    matched: bool,
+    report: &'a mut dyn FnMut(FileVerifyResult) -> bool,
+}
+
+/// Walks over the index and reports differences to the callback
+///
+/// Returns `true` if the directory matches index. When `report` returns
+/// `false` the walk is stopped immediately.
+fn walk<R, F>(parser: &mut Parser<R>, root: &Path, mut report: F)
+    -> Result<bool, Error>
+    where R: BufRead, F: FnMut(Discrepancy) -> bool,
+{
+    _walk(parser, root, &mut |result| match result.discrepancy {
+        Some(discrepancy) => report(discrepancy),
+        None => true,
+    })
+}
+
+/// Same as `walk` but also reports entries that match the index
+fn _walk<R: BufRead>(parser: &mut Parser<R>, root: &Path,
+    report: &mut dyn FnMut(FileVerifyResult) -> bool)
+    -> Result<bool, Error>
+{
+    let mut walker = Walker {
+        root: Dir::open(root).map_err(EDir)?,
+        current: None,
+        pending: BTreeMap::new(),
+        skip: None,
+        matched: true,
+        report,
+    };
+    walker.pending.insert(PathBuf::from("/"), true);
+    for entry in parser.iter() {
+        if !walker.entry(entry?)? {
+            return Ok(false);
+        }
+    }
+    walker.close_dir();
+    let extra = mem::take(&mut walker.pending);
+    for (path, _) in extra {
+        if !walker.emit(Discrepancy::Extra(path)) {
+            return Ok(false);
+        }
+    }
+    Ok(walker.matched)
+}
+
+impl<'a> Walker<'a> {
+    fn emit(&mut self, discrepancy: Discrepancy) -> bool {
+        self.matched = false;
+        (self.report)(FileVerifyResult {
+            path: discrepancy.path().to_path_buf(),
+            discrepancy: Some(discrepancy),
+            bytes: 0,
+        })
+    }
+
+    /// Returns `false` if walk should be stopped
+    fn entry(&mut self, entry: Entry) -> Result<bool, Error> {
+        if let Some(ref skip) = self.skip {
+            if entry.path().starts_with(skip) {
+                return Ok(true);
+            }
+        }
+        self.skip = None;
+        match entry {
+            Entry::Dir(path) => self.dir(path),
+            entry => {
+                let disk_path = match entry {
+                    Entry::Whiteout(ref path) => whiteout_path(path),
+                    _ => entry.path().to_path_buf(),
+                };
+                let (dir, typ) = match self.take(&disk_path) {
+                    Some(x) => x,
+                    None => {
+                        let path = entry.path().to_path_buf();
+                        return Ok(self.emit(Discrepancy::Missing(path)));
+                    }
+                };
+                let (path, size) = match entry {
+                    Entry::File { ref path, size, .. } => (path.clone(), size),
+                    ref entry => (entry.path().to_path_buf(), 0),
+                };
+                let discrepancy = check_entry(dir, typ, entry)?;
+                // file is only read when size and type match
+                let bytes = match discrepancy {
+                    None |
+                    Some(Discrepancy::ContentMismatch(..)) |
+                    Some(Discrepancy::ExeMismatch(..)) => size,
+                    Some(_) => 0,
+                };
+                if discrepancy.is_some() {
+                    self.matched = false;
+                }
+                Ok((self.report)(FileVerifyResult {
+                    path, discrepancy, bytes,
+                }))
+            }
+        }
+    }
+
+    fn dir(&mut self, path: PathBuf) -> Result<bool, Error> {
+        self.close_dir();
+        // Directories in the index are sorted, so everything before this
+        // directory will never be matched
+        let before = self.pending.range(..path.clone())
+            .map(|(p, _)| p.clone())
+            .collect::<Vec<_>>();
+        for extra in before {
+            self.pending.remove(&extra);
+            if !self.emit(Discrepancy::Extra(extra)) {
+                return Ok(false);
+            }
+        }
+        match self.pending.remove(&path) {
+            Some(true) => {}
+            Some(false) => {
+                self.skip = Some(path.clone());
+                return Ok(self.emit(Discrepancy::TypeMismatch(path)));
+            }
+            None => {
+                self.skip = Some(path.clone());
+                return Ok(self.emit(Discrepancy::Missing(path)));
+            }
+        }
+        let rel = path.strip_prefix("/").unwrap_or(&path);
+        let dir = if rel == Path::new("") {
+            self.root.sub_dir(".")
+        } else {
+            self.root.sub_dir(rel)
+        }.map_err(EList)?;
+        let mut items = HashMap::new();
+        for item in dir.list_dir(".").map_err(EList)? {
+            let item = item.map_err(EList)?;
+            let typ = match item.simple_type() {
+                Some(x) => x,
+                None => dir.metadata(&item).map_err(ERead)?.simple_type(),
+            };
+            items.insert(item.file_name().to_os_string(), typ);
+        }
+        self.current = Some((path, dir, items));
+        Ok(true)
+    }
+
+    /// Finds a file in the current directory
+    fn take(&mut self, path: &Path) -> Option<(&Dir, SimpleType)> {
+        let (ref dir_path, ref dir, ref mut items) = *self.current.as_mut()?;
+        if path.parent() != Some(dir_path) {
+            return None;
+        }
+        let typ = items.remove(path.file_name()?)?;
+        Some((dir, typ))
+    }
+
+    /// Moves unmatched entries of the current directory to `pending`
+    fn close_dir(&mut self) {
+        if let Some((path, _, items)) = self.current.take() {
+            for (name, typ) in items {
+                self.pending.insert(path.join(name), typ == SimpleType::Dir);
+            }
+        }
+    }
+}
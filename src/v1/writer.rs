@@ -1,30 +1,72 @@
 use std::fmt;
+use std::ffi::OsStr;
 use std::io::{self, Write};
 use std::sync::Arc;
-use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+use std::path::{Path, PathBuf};
 use std::os::unix::ffi::OsStrExt;
-use std::os::unix::fs::PermissionsExt;
-
-use openat::{Dir, Entry};
 
+use crate::{ScannerConfig, ExeBitMode, HashType};
 use crate::error::Error::{self, WriteError as EWrite, ReadFile as EFile};
+use super::cdc;
 use super::hash::Hash;
+use super::parser::Hexlified;
+use super::source::DirSource;
 
 
 pub(crate) struct Name<'a>(pub &'a Path);
 
+/// Ownership attributes of an entry line, written with a leading space
+pub(crate) struct Owner(pub Option<(u32, u32)>);
+
 pub(crate) const EXE_MASK: u32 = 0o100;
+pub(crate) const ANY_EXE_MASK: u32 = 0o111;
+
+/// How files are split into blocks for hashing
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Blocks {
+    /// Blocks of the same size, except the last one
+    Fixed(u64),
+    /// Content-defined chunks of the average size
+    Chunks(HashType, u32),
+}
+
+impl Blocks {
+    pub(crate) fn new(config: &ScannerConfig) -> Result<Blocks, Error> {
+        if config.content_defined_chunks {
+            let avg_size = cdc::check_avg_size(config.block_size)?;
+            Ok(Blocks::Chunks(config.hash, avg_size))
+        } else {
+            Ok(Blocks::Fixed(config.block_size))
+        }
+    }
+}
+
+impl ExeBitMode {
+    pub(crate) fn mask(self) -> u32 {
+        match self {
+            ExeBitMode::OwnerOnly => EXE_MASK,
+            ExeBitMode::AnyExecute => ANY_EXE_MASK,
+        }
+    }
+}
 
 pub(crate) const MAGIC: &'static str = "DIRSIGNATURE";
 pub(crate) const VERSION: &'static str = "v1";
+/// Prefix of the footer line with `marked_footer` option
+pub(crate) const FOOTER_MARKER: &str = "=";
 
 
 pub trait Writer {
     type TotalHash;
-    fn start_dir(&mut self, path: &Path) -> Result<(), Error>;
-    fn add_file(&mut self, dir: &Arc<Dir>, entry: Entry) -> Result<(), Error>;
-    fn add_symlink(&mut self, dir: &Arc<Dir>, entry: Entry)
+    fn start_dir(&mut self, path: &Path, owner: Option<(u32, u32)>)
+        -> Result<(), Error>;
+    fn add_file<D: DirSource>(&mut self, dir: &Arc<D>, name: &OsStr)
         -> Result<(), Error>;
+    fn add_symlink(&mut self, name: &OsStr, dest: &Path)
+        -> Result<(), Error>;
+    fn add_whiteout(&mut self, name: &OsStr) -> Result<(), Error>;
     fn get_hash(&mut self) -> Result<Self::TotalHash, Error>;
     fn done(self) -> Result<(), Error>;
 }
@@ -36,75 +78,228 @@ pub(crate) struct HashWriter<F, H: Hash> {
 
 pub(crate) struct SyncWriter<F, H: Hash> {
     file: HashWriter<F, H>,
-    block_size: u64,
+    blocks: Blocks,
+    io_retries: u32,
+    stable_read: u32,
+    exe_mask: u32,
+    store_ownership: bool,
+    marked_footer: bool,
     hash: H,
 }
 
+pub(crate) struct FileEntry {
+    pub(crate) file_name: PathBuf,
+    pub(crate) exe: bool,
+    pub(crate) size: u64,
+    pub(crate) hashes: String,
+    pub(crate) owner: (u32, u32),
+    pub(crate) mtime: SystemTime,
+}
+
 impl<F: io::Write, H: Hash> Writer for SyncWriter<F, H> {
     type TotalHash = H::Output;
-    fn start_dir(&mut self, path: &Path) -> Result<(), Error> {
-        writeln!(&mut self.file, "{}", Name(path)).map_err(EWrite)?;
+    fn start_dir(&mut self, path: &Path, owner: Option<(u32, u32)>)
+        -> Result<(), Error>
+    {
+        writeln!(&mut self.file, "{}{}", Name(path), Owner(owner))
+            .map_err(EWrite)?;
         Ok(())
     }
-    fn add_file(&mut self, dir: &Arc<Dir>, entry: Entry)
+    fn add_file<D: DirSource>(&mut self, dir: &Arc<D>, name: &OsStr)
         -> Result<(), Error>
     {
-        let mut f = dir.open_file(&entry).map_err(EFile)?;
-        let meta = f.metadata().map_err(EFile)?;
-        let mut n = meta.len();
-        write!(&mut self.file, "  {} {} {}",
-            Name(&Path::new(entry.file_name())),
-            if meta.permissions().mode() & EXE_MASK > 0 { "x" } else { "f" },
-            n,
-        ).map_err(EWrite)?;
-        while n > 0 {
-            let h = self.hash.hash_file(&mut f, self.block_size)
-                .map_err(EFile)?;
-            write!(&mut self.file, " {:x}", h).map_err(EWrite)?;
-            n = n.saturating_sub(self.block_size);
-        }
-        self.file.write_all(b"\n").map_err(EWrite)?;
+        // every file is hashed with a fresh hasher
+        let entry = retry(self.io_retries, || {
+            let mut hash = self.hash.clone();
+            hash_file_stable(&**dir, name, &mut hash, self.blocks,
+                self.stable_read, self.exe_mask)
+        }).map_err(EFile)?;
+        entry.write_to(&mut self.file, self.store_ownership)
+            .map_err(EWrite)?;
         Ok(())
     }
-    fn add_symlink(&mut self, dir: &Arc<Dir>, entry: Entry)
+    fn add_symlink(&mut self, name: &OsStr, dest: &Path)
         -> Result<(), Error>
     {
-        let dest = dir.read_link(&entry).map_err(EFile)?;
         write!(&mut self.file, "  {} s {}\n",
-            Name(&Path::new(entry.file_name())),
-            Name(&dest),
+            Name(Path::new(name)),
+            Name(dest),
         ).map_err(EWrite)?;
         Ok(())
     }
+    fn add_whiteout(&mut self, name: &OsStr) -> Result<(), Error> {
+        writeln!(&mut self.file, "  {} w", Name(Path::new(name)))
+            .map_err(EWrite)?;
+        Ok(())
+    }
     fn get_hash(&mut self) -> Result<H::Output, Error> {
         Ok(self.file.digest.total_hash())
     }
     fn done(mut self) -> Result<(), Error>
     {
         let hash = self.get_hash()?;
-        write!(&mut self.file.file, "{:x}\n", hash).map_err(EFile)
+        let marker = if self.marked_footer { FOOTER_MARKER } else { "" };
+        writeln!(&mut self.file.file, "{}{:x}", marker, hash).map_err(EFile)
     }
 }
 
 impl<F: io::Write, H: Hash> SyncWriter<F, H> {
-    pub fn new(mut f: F, hash: H, block_size: u64)
+    pub fn new(mut f: F, hash: H, config: &ScannerConfig)
         -> Result<SyncWriter<F, H>, Error>
     {
-        writeln!(&mut f,
-            "{}.{} {} block_size={}",
-            MAGIC,
-            VERSION,
-            hash.name(),
-            block_size,
-        ).map_err(EWrite)?;
+        let blocks = Blocks::new(config)?;
+        write_header(&mut f, &hash, config).map_err(EWrite)?;
         Ok(SyncWriter {
             file: HashWriter { file: f, digest: hash.clone() },
-            block_size: block_size,
+            blocks,
+            io_retries: config.io_retries,
+            stable_read: config.stable_read,
+            exe_mask: config.exe_bit.mask(),
+            store_ownership: config.store_ownership,
+            marked_footer: config.marked_footer,
             hash: hash,
         })
     }
 }
 
+pub(crate) fn write_header<F: io::Write, H: Hash>(f: &mut F, hash: &H,
+    config: &ScannerConfig)
+    -> io::Result<()>
+{
+    write!(f,
+        "{}.{} {} block_size={}",
+        MAGIC,
+        VERSION,
+        hash.name(),
+        config.block_size,
+    )?;
+    if config.store_ownership {
+        write!(f, " ownership=yes")?;
+    }
+    if config.content_defined_chunks {
+        write!(f, " chunking=cdc")?;
+    }
+    if config.marked_footer {
+        write!(f, " footer=marked")?;
+    }
+    writeln!(f)
+}
+
+impl FileEntry {
+    pub(crate) fn write_to<W: io::Write>(&self, out: &mut W,
+        store_ownership: bool)
+        -> io::Result<()>
+    {
+        writeln!(out, "  {} {} {}{}{}",
+            Name(&self.file_name),
+            if self.exe { "x" } else { "f" },
+            self.size,
+            self.hashes,  // includes space
+            Owner(if store_ownership { Some(self.owner) } else { None }),
+        )
+    }
+}
+
+/// Reads and hashes a single file
+///
+/// The hasher may be left in an inconsistent state on error, so caller should
+/// pass a fresh clone of the hasher when restarting the operation.
+pub(crate) fn hash_file<D, H>(dir: &D, name: &OsStr, hash: &mut H,
+    blocks: Blocks, exe_mask: u32)
+    -> io::Result<FileEntry>
+    where D: DirSource, H: Hash,
+{
+    use std::fmt::Write;
+    use std::io::Read;
+
+    let (mut f, meta) = dir.open_file(name)?;
+    let (size, buf) = match blocks {
+        Blocks::Fixed(block_size) => {
+            let mut n = meta.size;
+            let mut buf = String::with_capacity((65*n/block_size) as usize);
+            while n > 0 {
+                let h = hash.hash_file(&mut f, block_size)?;
+                write!(&mut buf, " {:x}", h).unwrap();
+                n = n.saturating_sub(block_size);
+            }
+            (meta.size, buf)
+        }
+        Blocks::Chunks(hash_type, avg_size) => {
+            // size is what was actually chunked, as it must match lengths
+            let mut size = 0;
+            let mut buf = String::new();
+            let data = (&mut f).take(meta.size);
+            for chunk in cdc::read_chunks(hash_type, avg_size, data)? {
+                write!(&mut buf, " {}:{:x}",
+                    chunk.length, Hexlified(&chunk.hash)).unwrap();
+                size += chunk.length;
+            }
+            (size, buf)
+        }
+    };
+    Ok(FileEntry {
+        file_name: PathBuf::from(name),
+        exe: meta.mode & exe_mask > 0,
+        size,
+        hashes: buf,
+        owner: (meta.uid, meta.gid),
+        mtime: meta.mtime,
+    })
+}
+
+/// Hashes a file again while its metadata changes after reading
+///
+/// File is hashed at most `retries + 1` times, then an error is returned.
+pub(crate) fn hash_file_stable<D, H>(dir: &D, name: &OsStr, hash: &mut H,
+    blocks: Blocks, retries: u32, exe_mask: u32)
+    -> io::Result<FileEntry>
+    where D: DirSource, H: Hash,
+{
+    if retries == 0 {
+        return hash_file(dir, name, hash, blocks, exe_mask);
+    }
+    let orig = hash.clone();
+    let mut attempt = 0;
+    loop {
+        let entry = hash_file(dir, name, hash, blocks, exe_mask)?;
+        let meta = dir.metadata(name)?;
+        if meta.size == entry.size && meta.mtime == entry.mtime {
+            return Ok(entry);
+        }
+        if attempt >= retries {
+            return Err(io::Error::other(
+                format!("file {:?} keeps changing while reading", name)));
+        }
+        attempt += 1;
+        debug!("File {:?} changed while reading, hashing again", name);
+        *hash = orig.clone();
+    }
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(err.kind(), Interrupted | WouldBlock | TimedOut)
+}
+
+/// Runs `op` again on transient errors, at most `retries` more times
+///
+/// Delay between attempts is doubled each time, starting from 10ms.
+pub(crate) fn retry<T, F>(retries: u32, mut op: F) -> io::Result<T>
+    where F: FnMut() -> io::Result<T>
+{
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(ref e) if attempt < retries && is_transient(e) => {
+                debug!("Transient error {}, retrying", e);
+                sleep(Duration::from_millis(10 << attempt.min(8)));
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
 impl<F: io::Write, H: Hash> io::Write for HashWriter<F, H> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let n = self.file.write(buf)?;
@@ -116,6 +311,24 @@ impl<F: io::Write, H: Hash> io::Write for HashWriter<F, H> {
     }
 }
 
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((uid, gid)) = self.0 {
+            write!(f, " uid={} gid={}", uid, gid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a file name (or a path) as it's written in the index
+///
+/// Spaces, control characters, backslashes and all non-ASCII bytes are
+/// written as `\xNN`. The result can be converted back by
+/// [`unescape_name`](fn.unescape_name.html).
+pub fn escape_name(name: &OsStr) -> String {
+    Name(Path::new(name)).to_string()
+}
+
 impl<'a> fmt::Display for Name<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use std::fmt::Write;
@@ -138,3 +351,54 @@ fn test_escapes() {
     assert_eq!(&format!("{}", Name(Path::new("a\\x05b"))),
                r"a\x5cx05b");
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Read};
+    use super::retry;
+
+    struct Flaky<R> {
+        failures: u32,
+        inner: R,
+    }
+
+    impl<R: Read> Read for Flaky<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    fn read_all<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn retry_transient() {
+        let mut f = Flaky { failures: 1, inner: &b"hello"[..] };
+        assert_eq!(retry(1, || read_all(&mut f)).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn retry_exhausted() {
+        let mut f = Flaky { failures: 2, inner: &b"hello"[..] };
+        let err = retry(1, || read_all(&mut f)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn no_retry_on_permanent() {
+        let mut calls = 0;
+        let err = retry(5, || -> io::Result<()> {
+            calls += 1;
+            Err(io::ErrorKind::NotFound.into())
+        }).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+    }
+}
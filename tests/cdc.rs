@@ -0,0 +1,52 @@
+use dir_signature::{Error, HashType};
+use dir_signature::v1::cdc::chunks;
+
+
+fn random_data(len: usize) -> Vec<u8> {
+    // xorshift, just to have reproducible incompressible data
+    let mut state = 0x2545f4914f6cdd1du64;
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 24) as u8
+    }).collect()
+}
+
+#[test]
+fn test_chunk_sizes() {
+    let data = random_data(1 << 20);
+    let list = chunks(HashType::blake3_256(), 8192, &data[..]).unwrap();
+    assert!(list.len() > 32 && list.len() < 512, "{} chunks", list.len());
+    let mut offset = 0;
+    for chunk in &list[..list.len()-1] {
+        assert_eq!(chunk.offset, offset);
+        assert!(chunk.length >= 2048 && chunk.length <= 32768);
+        offset += chunk.length;
+    }
+    assert_eq!(offset + list.last().unwrap().length, 1 << 20);
+    assert_eq!(chunks(HashType::blake3_256(), 8192, &b""[..]).unwrap(),
+               vec![]);
+}
+
+#[test]
+fn test_insertion_changes_one_chunk() {
+    let data = random_data(1 << 20);
+    let mut edited = data.clone();
+    edited.insert(500_000, b'x');
+    let old = chunks(HashType::sha512_256(), 8192, &data[..]).unwrap();
+    let new = chunks(HashType::sha512_256(), 8192, &edited[..]).unwrap();
+    let changed = new.iter()
+        .filter(|c| !old.iter().any(|o| o.hash == c.hash))
+        .collect::<Vec<_>>();
+    assert_eq!(changed.len(), 1);
+    assert!(changed[0].offset <= 500_000);
+    assert!(changed[0].offset + changed[0].length > 500_000);
+    assert_eq!(old.len(), new.len());
+}
+
+#[test]
+fn test_invalid_avg_size() {
+    let err = chunks(HashType::blake3_256(), 32, &b"data"[..]).unwrap_err();
+    assert!(matches!(err, Error::InvalidChunkSize(32)), "{}", err);
+}
@@ -0,0 +1,87 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::process::Command;
+
+use tempfile::TempDir;
+
+
+const INDEX: &str = env!("CARGO_BIN_EXE_index");
+
+#[test]
+fn test_verify_exit_code_and_json() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    File::create(dir.join("a.txt")).unwrap().write_all(b"hello\n").unwrap();
+    let index = tmp.path().join("index.ds1");
+
+    let status = Command::new(INDEX)
+        .arg("-q").arg("-o").arg(&index).arg(&dir)
+        .status().unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    let out = Command::new(INDEX)
+        .arg("--verify").arg(&index).arg("--format").arg("json").arg(&dir)
+        .output().unwrap();
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "");
+
+    File::create(dir.join("a.txt")).unwrap().write_all(b"hi\n").unwrap();
+    let out = Command::new(INDEX)
+        .arg("--verify").arg(&index).arg("--format").arg("json").arg(&dir)
+        .output().unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&out.stdout),
+        "{\"kind\":\"size_mismatch\",\"path\":\"/a.txt\",\
+          \"expected\":6,\"actual\":3}\n");
+
+    let out = Command::new(INDEX)
+        .arg("--verify").arg(tmp.path().join("nonexistent")).arg(&dir)
+        .output().unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_id_only() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    File::create(dir.join("a.txt")).unwrap().write_all(b"hello\n").unwrap();
+    let index = tmp.path().join("index.ds1");
+
+    let status = Command::new(INDEX)
+        .arg("-q").arg("-o").arg(&index).arg(&dir)
+        .status().unwrap();
+    assert_eq!(status.code(), Some(0));
+    let hash = dir_signature::get_hash(&mut File::open(&index).unwrap())
+        .unwrap();
+    let hex = hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    let out = Command::new(INDEX)
+        .arg("-q").arg("--id-only").arg(&dir)
+        .output().unwrap();
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), format!("{}\n", hex));
+}
+
+#[test]
+fn test_progress_lines() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    File::create(dir.join("a.txt")).unwrap().write_all(b"hello\n").unwrap();
+    let index = tmp.path().join("index.ds1");
+
+    let out = Command::new(INDEX)
+        .arg("--progress-lines").arg("--progress-interval").arg("0")
+        .arg("-o").arg(&index).arg(&dir)
+        .output().unwrap();
+    assert_eq!(out.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!stderr.contains('\r'));
+    let lines = stderr.lines().collect::<Vec<_>>();
+    assert!(lines.len() >= 3, "{:?}", lines);
+    assert!(lines[..lines.len()-1].iter()
+        .all(|line| line.starts_with("Indexing... ")));
+    assert!(lines[lines.len()-1].starts_with("Done "));
+}
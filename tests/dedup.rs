@@ -0,0 +1,33 @@
+use std::io::{BufReader, Cursor};
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::v1::Parser;
+use dir_signature::v1::dedup::{block_refcounts, unique_blocks};
+
+
+fn scan_dir2() -> Vec<u8> {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir2", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn test_unique_blocks() {
+    let buf = scan_dir2();
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    let blocks = unique_blocks(&mut parser).unwrap();
+    // first two blocks of bigdata.bin are identical
+    assert_eq!(blocks.len(), 5);
+}
+
+#[test]
+fn test_block_refcounts() {
+    let buf = scan_dir2();
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    let counts = block_refcounts(&mut parser).unwrap();
+    assert_eq!(counts.len(), 5);
+    assert_eq!(counts.values().sum::<usize>(), 6);
+    assert_eq!(counts.values().filter(|&&c| c == 2).count(), 1);
+}
@@ -0,0 +1,265 @@
+use std::ffi::OsStr;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+use dir_signature::HashType;
+use dir_signature::v1::{self, Emitter, Parser};
+use dir_signature::v1::diff::{Diff, DiffEntry};
+
+
+const DIRS: usize = 2000;
+const FILES: usize = 10;
+
+/// Generates a synthetic index, `changed` is called for every file and
+/// returns its size or `None` if file should be skipped
+fn generate<F>(dirs: usize, changed: F) -> Vec<u8>
+    where F: Fn(usize, usize) -> Option<u64>
+{
+    let hash = HashType::sha512_256();
+    let block = [7u8; 32];
+    let mut buf = Vec::new();
+    {
+        let mut e = Emitter::new(hash, 32768, &mut buf).unwrap();
+        e.start_dir(Path::new("/")).unwrap();
+        for d in 0..dirs {
+            e.start_dir(&Path::new("/").join(format!("d{:05}", d))).unwrap();
+            for f in 0..FILES {
+                if let Some(size) = changed(d, f) {
+                    let name = format!("f{}", f);
+                    e.add_file_blocks(OsStr::new(&name), false, size, hash,
+                        &[&block]).unwrap();
+                }
+            }
+        }
+        e.finish().unwrap();
+    }
+    buf
+}
+
+fn parser(buf: &[u8]) -> Parser<BufReader<Cursor<&[u8]>>> {
+    Parser::new(BufReader::new(Cursor::new(buf))).unwrap()
+}
+
+#[test]
+fn test_diff_stream() {
+    let old = generate(DIRS, |d, f| match (d, f) {
+        (3, 3) => None,
+        _ => Some(100),
+    });
+    let new = generate(DIRS + 1, |d, f| match (d, f) {
+        (1, 3) => None,
+        (1500, 5) => Some(200),
+        _ => Some(100),
+    });
+    let mut out = Vec::new();
+    v1::diff_stream(&mut parser(&old), &mut parser(&new), &mut out).unwrap();
+    let mut expected = String::from("\
+- /d00001/f3
++ /d00003/f3
+~ /d01500/f5
++ /d02000
+");
+    for f in 0..FILES {
+        expected.push_str(&format!("+ /d02000/f{}\n", f));
+    }
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+    let mut out = Vec::new();
+    v1::diff_stream(&mut parser(&old), &mut parser(&old), &mut out).unwrap();
+    assert_eq!(out, b"");
+}
+
+#[test]
+fn test_diff_iterator() {
+    let old = generate(2, |_, _| Some(1));
+    let new = generate(2, |d, f| match (d, f) {
+        (0, 0) => Some(2),
+        _ => Some(1),
+    });
+    let mut old_parser = parser(&old);
+    let mut new_parser = parser(&new);
+    let items = Diff::new(&mut old_parser, &mut new_parser)
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(items.len(), 1);
+    match items[0] {
+        DiffEntry::Changed(ref old, ref new) => {
+            assert_eq!(old.path(), Path::new("/d00000/f0"));
+            assert_eq!(new.path(), Path::new("/d00000/f0"));
+        }
+        ref item => panic!("unexpected item {:?}", item),
+    }
+}
+
+#[test]
+fn test_size_delta() {
+    let old = generate(10, |d, f| match (d, f) {
+        (3, 3) => None,
+        _ => Some(100),
+    });
+    let new = generate(11, |d, f| match (d, f) {
+        (1, 3) => None,
+        (5, 5) => Some(250),
+        _ => Some(100),
+    });
+    // -100 for /d00001/f3, +100 for /d00003/f3, +150 for /d00005/f5,
+    // and a new directory with 10 files
+    assert_eq!(v1::size_delta(&mut parser(&old), &mut parser(&new)).unwrap(),
+               150 + 100 * FILES as i64);
+    assert_eq!(v1::size_delta(&mut parser(&new), &mut parser(&old)).unwrap(),
+               -150 - 100 * FILES as i64);
+    assert_eq!(v1::size_delta(&mut parser(&old), &mut parser(&old)).unwrap(),
+               0);
+}
+
+#[test]
+fn test_append_only_changes() {
+    let hash = HashType::sha512_256();
+    let (a, b, c, d) = ([1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]);
+    let index = |files: &[(&str, u64, &[&[u8]])]| {
+        let mut buf = Vec::new();
+        {
+            let mut e = Emitter::new(hash, 32768, &mut buf).unwrap();
+            e.start_dir(Path::new("/")).unwrap();
+            for &(name, size, blocks) in files {
+                e.add_file_blocks(OsStr::new(name), false, size, hash,
+                    blocks).unwrap();
+            }
+            e.finish().unwrap();
+        }
+        buf
+    };
+    let old = index(&[
+        ("appended.log", 65536, &[&a, &b]),
+        ("edited.log", 65536, &[&a, &b]),
+        ("same.log", 32768, &[&a]),
+    ]);
+    let new = index(&[
+        ("appended.log", 98304, &[&a, &b, &c]),
+        ("edited.log", 98304, &[&a, &d, &c]),
+        ("same.log", 32768, &[&a]),
+    ]);
+    assert_eq!(
+        v1::append_only_changes(&mut parser(&old), &mut parser(&new))
+            .unwrap(),
+        vec![Path::new("/appended.log").to_path_buf()]);
+    assert_eq!(
+        v1::append_only_changes(&mut parser(&new), &mut parser(&old))
+            .unwrap(),
+        Vec::<std::path::PathBuf>::new());
+}
+
+#[test]
+fn test_block_diffs() {
+    use dir_signature::v1::diff::{BlockDiff, BlockDiffs};
+
+    let hash = HashType::sha512_256();
+    let (a, b, c, d) = ([1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]);
+    let index = |files: &[(&str, u64, &[&[u8]])]| {
+        let mut buf = Vec::new();
+        {
+            let mut e = Emitter::new(hash, 32768, &mut buf).unwrap();
+            e.start_dir(Path::new("/")).unwrap();
+            for &(name, size, blocks) in files {
+                e.add_file_blocks(OsStr::new(name), false, size, hash,
+                    blocks).unwrap();
+            }
+            e.finish().unwrap();
+        }
+        buf
+    };
+    let old = index(&[
+        ("grown.bin", 32768, &[&a]),
+        ("middle.bin", 98304, &[&a, &b, &c]),
+        ("removed.bin", 32768, &[&a]),
+        ("same.bin", 32768, &[&a]),
+    ]);
+    let new = index(&[
+        ("grown.bin", 65536, &[&a, &b]),
+        ("middle.bin", 98304, &[&a, &d, &c]),
+        ("same.bin", 32768, &[&a]),
+    ]);
+    let mut old_parser = parser(&old);
+    let mut new_parser = parser(&new);
+    let items = BlockDiffs::new(&mut old_parser, &mut new_parser)
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(items, vec![
+        BlockDiff { path: Path::new("/grown.bin").into(), changed: vec![1] },
+        BlockDiff { path: Path::new("/middle.bin").into(), changed: vec![1] },
+    ]);
+}
+
+#[test]
+fn test_scan_patch() {
+    use std::fs::File;
+    use std::io::Write;
+    use dir_signature::ScannerConfig;
+    use dir_signature::v1::diff::BlockDiff;
+
+    let dir = tempfile::tempdir().unwrap();
+    File::create(dir.path().join("a.txt")).unwrap()
+        .write_all(b"hello").unwrap();
+    File::create(dir.path().join("b.txt")).unwrap()
+        .write_all(b"same").unwrap();
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    let (prior, _) = v1::scan_to_vec(&cfg).unwrap();
+
+    File::create(dir.path().join("a.txt")).unwrap()
+        .write_all(b"world").unwrap();
+    let patch = v1::scan_patch(&cfg, &mut parser(&prior)).unwrap();
+    assert_eq!(patch.entries.len(), 1);
+    match patch.entries[0] {
+        DiffEntry::Changed(ref old, ref new) => {
+            assert_eq!(old.path(), Path::new("/a.txt"));
+            assert_eq!(new.path(), Path::new("/a.txt"));
+        }
+        ref item => panic!("unexpected item {:?}", item),
+    }
+    assert_eq!(patch.blocks, vec![
+        BlockDiff { path: Path::new("/a.txt").into(), changed: vec![0] },
+    ]);
+    let (current, image_id) = v1::scan_to_vec(&cfg).unwrap();
+    assert_eq!(patch.image_id, image_id);
+    let patch = v1::scan_patch(&cfg, &mut parser(&current)).unwrap();
+    assert!(patch.entries.is_empty());
+}
+
+#[test]
+fn test_detect_renames() {
+    let hash = HashType::sha512_256();
+    let (a, b, c) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+    let index = |dirs: &[(&str, &[(&str, u64, &[&[u8]])])]| {
+        let mut buf = Vec::new();
+        {
+            let mut e = Emitter::new(hash, 32768, &mut buf).unwrap();
+            for &(dir, files) in dirs {
+                e.start_dir(Path::new(dir)).unwrap();
+                for &(name, size, blocks) in files {
+                    e.add_file_blocks(OsStr::new(name), false, size, hash,
+                        blocks).unwrap();
+                }
+            }
+            e.finish().unwrap();
+        }
+        buf
+    };
+    let old = index(&[
+        ("/", &[("copy1", 10, &[&a]), ("copy2", 10, &[&a]), ("empty", 0, &[])]),
+        ("/src", &[("moved.txt", 100, &[&b]), ("stays.txt", 100, &[&c])]),
+    ]);
+    let new = index(&[
+        ("/", &[("other_empty", 0, &[])]),
+        ("/dst", &[("copy", 10, &[&a]), ("moved.txt", 100, &[&b])]),
+        ("/src", &[("stays.txt", 100, &[&c])]),
+    ]);
+    assert_eq!(
+        v1::detect_renames(&mut parser(&old), &mut parser(&new)).unwrap(),
+        vec![
+            (Path::new("/copy1").into(), Path::new("/dst/copy").into()),
+            (Path::new("/src/moved.txt").into(),
+             Path::new("/dst/moved.txt").into()),
+        ]);
+    assert_eq!(
+        v1::detect_renames(&mut parser(&old), &mut parser(&old)).unwrap(),
+        vec![]);
+}
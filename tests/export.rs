@@ -0,0 +1,73 @@
+use std::io::{BufReader, Cursor};
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::v1::Parser;
+use dir_signature::v1::export::{manifest, symlinks, flat_sorted};
+
+
+#[test]
+fn test_manifest() {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    let mut out = Vec::new();
+    manifest(&mut parser, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+dir\t/
+6\t/hello.txt
+0\t/test.txt
+dir\t/subdir
+7\t/subdir/.hidden
+10\t/subdir/file.txt
+");
+}
+
+#[test]
+fn test_symlinks() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&content[..])))
+        .unwrap();
+    let mut out = Vec::new();
+    symlinks(&mut parser, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+        "/subdir/just\\x20link -> ../hello.txt\n");
+}
+
+#[test]
+fn test_flat_sorted() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+  x s hello.txt
+/a
+  z.txt x 0
+/subdir
+  .hidden f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&content[..])))
+        .unwrap();
+    let mut out = Vec::new();
+    flat_sorted(&mut parser, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+/
+/a
+/a/z.txt x 0
+/hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+/subdir/.hidden f 0
+/x s hello.txt
+");
+}
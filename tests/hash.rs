@@ -0,0 +1,54 @@
+use std::io::{self, Read};
+
+use dir_signature::{Error, HashType, v1};
+
+
+fn all_types() -> Vec<HashType> {
+    vec![HashType::sha512_256(), HashType::blake2b_256(),
+         HashType::blake3_256()]
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+#[test]
+fn test_many_blocks_match_single_shot() {
+    let data = data(1000 * 64 + 13);
+    for hash_type in all_types() {
+        let (size, hashes) = v1::hash_reader(&data[..], hash_type, 64)
+            .unwrap();
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(hashes.len(), 1001);
+        for (block, hash) in data.chunks(64).zip(hashes.iter()) {
+            assert_eq!(hash, &v1::hash_bytes(hash_type, block)[..],
+                       "{:?}", hash_type);
+        }
+    }
+}
+
+#[test]
+fn test_hash_reader_matches_hash_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file");
+    let data = data(100_000);
+    std::fs::write(&path, &data).unwrap();
+    for hash_type in all_types() {
+        assert_eq!(v1::hash_file(&path, hash_type, 4096).unwrap(),
+                   v1::hash_reader(&data[..], hash_type, 4096).unwrap());
+    }
+    assert!(matches!(v1::hash_file(&path, HashType::blake3_256(), 0),
+                     Err(Error::ZeroBlockSize)));
+}
+
+#[test]
+fn test_hash_reader_edges() {
+    let hash_type = HashType::blake3_256();
+    let (size, hashes) = v1::hash_reader(io::empty(), hash_type, 64).unwrap();
+    assert_eq!((size, hashes.len()), (0, 0));
+    // exact multiple of the block size has no trailing empty block
+    let (size, hashes) = v1::hash_reader(io::repeat(1).take(128),
+                                         hash_type, 64).unwrap();
+    assert_eq!((size, hashes.len()), (128, 2));
+    assert!(v1::hash_reader(io::empty(), hash_type, 0).is_err());
+}
@@ -4,9 +4,9 @@ use std::path::{Path, PathBuf};
 #[macro_use] extern crate matches;
 
 
-use dir_signature::HashType;
+use dir_signature::{HashType, ScannerConfig, v1};
 use dir_signature::v1::{Entry, EntryKind, Parser};
-use dir_signature::v1::merge::{MergeError, MergedSignatures};
+use dir_signature::v1::merge::{MergeError, MergedSignatures, union};
 
 #[test]
 fn test_merger() {
@@ -224,3 +224,139 @@ c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
 
     assert!(merged_iter.next().is_none());
 }
+
+#[test]
+fn test_merge_advance_for() {
+    let content1 = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let content2 = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 5 339d14455c458d1ad7b57ee247a6628e4fc8e39958bbc23f2579827456818fc8
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let parsers = vec!(
+        (
+            PathBuf::from("/111"),
+            Parser::new(BufReader::new(Cursor::new(&content1[..]))).unwrap()
+        ),
+        (
+            PathBuf::from("/222"),
+            Parser::new(BufReader::new(Cursor::new(&content2[..]))).unwrap()
+        ),
+    );
+
+    let mut merger = MergedSignatures::new(parsers).unwrap();
+    let mut merged_iter = merger.iter();
+
+    let entry = merged_iter.advance_for(1, &EntryKind::File("/hello.txt"));
+    assert!(matches!(entry, Some((base_path, Ok(Entry::File{ref path, size, ..})))
+                     if base_path == Path::new("/222") &&
+                     path == Path::new("/hello.txt") && size == 5),
+            "Was: {:?}", entry);
+    let entry = merged_iter.advance_for(2, &EntryKind::File("/hello.txt"));
+    assert!(entry.is_none(), "Was: {:?}", entry);
+
+    // first source is not advanced yet
+    let entries = merged_iter.next().unwrap();
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert!(matches!(entry, &(base_path, Ok(Entry::Dir(ref path)))
+                     if base_path == Path::new("/111") &&
+                     path == Path::new("/")),
+            "Was: {:?}", entry);
+}
+
+#[test]
+fn test_union_whiteouts() {
+    use std::fs;
+
+    fn scan(dir: &Path) -> Vec<u8> {
+        let mut cfg = ScannerConfig::new();
+        cfg.overlay_whiteouts();
+        cfg.add_dir(dir, "/");
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        buf
+    }
+    fn parser(buf: &[u8]) -> Parser<BufReader<Cursor<&[u8]>>> {
+        Parser::new(BufReader::new(Cursor::new(buf))).unwrap()
+    }
+
+    let base = tempfile::tempdir().unwrap();
+    fs::create_dir_all(base.path().join("sub")).unwrap();
+    fs::create_dir_all(base.path().join("sub2/nested")).unwrap();
+    fs::write(base.path().join("a.txt"), "hello\n").unwrap();
+    fs::write(base.path().join("b.txt"), "world\n").unwrap();
+    fs::write(base.path().join("sub/c.txt"), "c\n").unwrap();
+    fs::write(base.path().join("sub2/nested/d.txt"), "d\n").unwrap();
+
+    let layer = tempfile::tempdir().unwrap();
+    fs::create_dir_all(layer.path().join("sub")).unwrap();
+    fs::write(layer.path().join("a.txt"), "changed\n").unwrap();
+    fs::write(layer.path().join(".wh.b.txt"), "").unwrap();
+    fs::write(layer.path().join(".wh.sub2"), "").unwrap();
+    fs::write(layer.path().join("sub/e.txt"), "e\n").unwrap();
+
+    let layer_index = scan(layer.path());
+    assert!(String::from_utf8_lossy(&layer_index).contains("\n  b.txt w\n"));
+    assert!(String::from_utf8_lossy(&layer_index).contains("\n  sub2 w\n"));
+
+    let expected = tempfile::tempdir().unwrap();
+    fs::create_dir_all(expected.path().join("sub")).unwrap();
+    fs::write(expected.path().join("a.txt"), "changed\n").unwrap();
+    fs::write(expected.path().join("sub/c.txt"), "c\n").unwrap();
+    fs::write(expected.path().join("sub/e.txt"), "e\n").unwrap();
+
+    let base_index = scan(base.path());
+    let mut out = Vec::new();
+    union(vec![parser(&base_index), parser(&layer_index)], &mut out)
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               String::from_utf8(scan(expected.path())).unwrap());
+}
+
+#[test]
+fn test_union_unique_blocks() {
+    use dir_signature::v1::merge::union_unique_blocks;
+
+    let content1 = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let content2 = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  byebye.txt f 3 339d14455c458d1ad7b57ee247a6628e4fc8e39958bbc23f2579827456818fc8
+  copy.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let parser = |content: &'static [u8]| {
+        Parser::new(BufReader::new(Cursor::new(content))).unwrap()
+    };
+    let mut merged = MergedSignatures::new(vec![
+        (1, parser(&content1[..])),
+        (2, parser(&content2[..])),
+    ]).unwrap();
+    // 3 blocks in each of the files, but only the block of byebye.txt
+    // is not shared
+    assert_eq!(union_unique_blocks(&mut merged).unwrap(), 4);
+
+    let mut single = MergedSignatures::new(vec![
+        (1, parser(&content1[..])),
+    ]).unwrap();
+    assert_eq!(union_unique_blocks(&mut single).unwrap(), 3);
+}
@@ -8,7 +8,7 @@ use rustc_hex::FromHex;
 
 
 use dir_signature::HashType;
-use dir_signature::v1::{Entry, EntryKind, Parser};
+use dir_signature::v1::{Entry, EntryKind, EntryRef, Parser, SignatureIndex};
 
 #[test]
 fn test_parser() {
@@ -264,6 +264,27 @@ fn test_parser_invalid_header_signature() {
     }
 }
 
+#[test]
+fn test_parser_invalid_utf8() {
+    let content = b"\xff\n";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let err = Parser::new(reader).err().expect("error");
+    assert_eq!(err.to_string(),
+        "Parse error at line 1: Invalid header: invalid utf-8 at byte 0");
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  a.txt f 1\xff
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut parser = Parser::new(reader).unwrap();
+    let mut iter = parser.iter();
+    assert!(matches!(iter.next(), Some(Ok(Entry::Dir(..)))));
+    assert_eq!(iter.next().unwrap().unwrap_err().to_string(),
+        "Parse error at line 3: Invalid utf-8 at byte 1");
+}
+
 #[test]
 fn test_parser_invalid_footer() {
     let content = "\
@@ -313,3 +334,695 @@ DIRSIGNATURE.v1 sha512/256 block_size=32768
             Some(Ok(Entry::Dir(ref path))) if path == Path::new("/")),
         "Entry result was: {:?}", entry);
 }
+
+#[test]
+fn test_entry_content_eq() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hello.sh x 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+  link s ../hello.txt
+/subdir
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+  link s ../hello.txt
+  world.txt f 6 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut signature_parser = Parser::new(reader).unwrap();
+    let entries = signature_parser.iter()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    let (root, hello_exe, hello, link) =
+        (&entries[0], &entries[1], &entries[2], &entries[3]);
+    let (subdir, moved_hello, moved_link, world) =
+        (&entries[4], &entries[5], &entries[6], &entries[7]);
+
+    assert_ne!(hello, moved_hello);
+    assert!(hello.content_eq(moved_hello));
+    assert!(link.content_eq(moved_link));
+    assert!(root.content_eq(subdir));
+    assert!(!hello.content_eq(hello_exe));
+    assert!(!hello.content_eq(world));
+    assert!(!hello.content_eq(link));
+    assert!(!root.content_eq(hello));
+}
+
+#[test]
+fn test_entry_path_bytes() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/caf\\xe9
+  \\xff\\xfe.txt f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut signature_parser = Parser::new(reader).unwrap();
+    let entries = signature_parser.iter()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries[0].path_bytes(), b"/caf\xe9");
+    assert_eq!(entries[1].path_bytes(), b"/caf\xe9/\xff\xfe.txt");
+}
+
+#[test]
+fn test_signature_index_nth() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+/subdir/x
+  file.txt f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut signature_parser = Parser::new(reader).unwrap();
+    let entries = signature_parser.iter()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let signature_parser = Parser::new(reader).unwrap();
+    let mut index = SignatureIndex::new(signature_parser).unwrap();
+    assert_eq!(index.len(), 8);
+    assert_eq!(index.nth(5).unwrap().unwrap(), entries[5]);
+    assert_eq!(index.nth(0).unwrap().unwrap(), entries[0]);
+    assert_eq!(index.nth(2).unwrap().unwrap(), entries[2]);
+    assert_eq!(index.nth(7).unwrap().unwrap(), entries[7]);
+    assert!(index.nth(8).is_none());
+}
+
+#[test]
+fn test_subtree() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 0
+  just\\x20link s ../hello.txt
+/subdir/x
+  file.txt f 0
+/subdir2
+  file.txt f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut signature_parser = Parser::new(reader).unwrap();
+    let paths = signature_parser.subtree("/subdir")
+        .map(|e| e.unwrap().path().to_path_buf())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec![
+        Path::new("/subdir"),
+        Path::new("/subdir/.hidden"),
+        Path::new("/subdir/just link"),
+        Path::new("/subdir/x"),
+        Path::new("/subdir/x/file.txt"),
+    ]);
+
+    let mut reader = signature_parser.into_reader();
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    let mut signature_parser = Parser::new(reader).unwrap();
+    assert_eq!(signature_parser.subtree("/missing").count(), 0);
+}
+
+#[test]
+fn test_open_with_capacity() {
+    use std::io::Write;
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(content).unwrap();
+
+    let mut signature_parser = Parser::open(file.path()).unwrap();
+    let expected = signature_parser.iter()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(expected.len(), 6);
+    for &capacity in &[1, 16, 17, 100, 65536] {
+        let mut signature_parser = Parser::open_with_capacity(
+            file.path(), capacity).unwrap();
+        assert_eq!(signature_parser.get_header().get_block_size(), 32768);
+        let entries = signature_parser.iter()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, expected, "capacity {}", capacity);
+    }
+}
+
+#[test]
+fn test_max_line_length() {
+    let mut content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  huge.txt f 1 ".to_vec();
+    content.extend(vec![b'a'; 1 << 20]);
+
+    let reader = BufReader::with_capacity(16, Cursor::new(&content[..]));
+    let mut signature_parser = Parser::with_max_line_length(reader, 100)
+        .unwrap();
+    let err = signature_parser.iter().nth(1).unwrap().unwrap_err();
+    assert!(err.to_string().contains("Line is longer than 100 bytes"),
+        "{}", err);
+    // the rest of the line is not buffered
+    let position = signature_parser.into_reader().into_inner().position();
+    assert!(position < 1000, "position {}", position);
+}
+
+#[test]
+fn test_filter_entries() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hello.txt f 0
+  run.sh x 0
+/bin
+  ls x 0
+  readme f 0
+/sbin
+  init x 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let is_exe = |entry: &Entry| matches!(*entry, Entry::File { exe: true, .. });
+
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut signature_parser = Parser::new(reader).unwrap();
+    let paths = signature_parser.iter().filter_entries(is_exe)
+        .map(|e| e.unwrap().path().to_path_buf())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec![
+        Path::new("/run.sh"),
+        Path::new("/bin/ls"),
+        Path::new("/sbin/init"),
+    ]);
+
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut signature_parser = Parser::new(reader).unwrap();
+    let mut iter = signature_parser.iter().filter_entries(is_exe);
+    assert!(iter.advance(&EntryKind::File("/bin/readme")).is_none());
+    let entry = iter.advance(&EntryKind::File("/sbin/init"));
+    assert_eq!(entry.unwrap().unwrap().path(), Path::new("/sbin/init"));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_hashes_into_iter_owned() {
+    use dir_signature::v1::Hashes;
+
+    fn owned_hashes(hashes: Hashes) -> impl Iterator<Item=Vec<u8>> {
+        hashes.into_iter_owned()
+    }
+
+    let (size, hashes) = Hashes::hash_file(HashType::sha512_256(), 4,
+        Cursor::new(b"HeLLo\n")).unwrap();
+    assert_eq!(size, 6);
+    assert_eq!(hashes.len(), 2);
+    let expected = hashes.iter().map(|h| h.to_vec()).collect::<Vec<_>>();
+    let owned = owned_hashes(hashes).collect::<Vec<Vec<u8>>>();
+    assert_eq!(owned.len(), 2);
+    assert_eq!(owned, expected);
+    assert_eq!(owned[0].len(), 32);
+}
+
+#[test]
+fn test_hashes_from_bytes() {
+    use dir_signature::v1::Hashes;
+
+    let (_, hashes) = Hashes::hash_file(HashType::blake3_256(), 4,
+        Cursor::new(b"HeLLo\n")).unwrap();
+    assert_eq!(hashes.as_bytes().len(), 64);
+    let copy = Hashes::from_bytes(hashes.as_bytes().to_vec(),
+        HashType::blake3_256(), 4).unwrap();
+    assert_eq!(copy, hashes);
+    assert_eq!(copy.get(1), hashes.get(1));
+
+    let err = Hashes::from_bytes(vec![0; 33], HashType::blake3_256(), 4)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "invalid hashes: \
+        33 bytes is not a multiple of blake3/256 hash size 32");
+    assert!(Hashes::from_bytes(Vec::new(), HashType::blake3_256(), 4)
+        .unwrap().len() == 0);
+}
+
+#[test]
+fn test_iter_borrowed() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden x 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+/sub\\x20dir
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let owned = parser.iter().collect::<Result<Vec<_>, _>>().unwrap();
+
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let mut entries = parser.iter_borrowed();
+    let mut idx = 0;
+    while let Some(entry) = entries.next_entry() {
+        let entry = entry.unwrap();
+        assert_eq!(entry.to_entry(), owned[idx]);
+        assert_eq!(entry.path(), owned[idx].path());
+        if let (EntryRef::File { hashes, .. }, Entry::File { hashes: h, .. })
+            = (entry, &owned[idx])
+        {
+            assert_eq!(hashes.len(), h.len());
+            assert_eq!(hashes.iter().collect::<Vec<_>>(),
+                       h.iter().collect::<Vec<_>>());
+        }
+        idx += 1;
+    }
+    assert_eq!(idx, owned.len());
+    assert_eq!(idx, 7);
+    assert!(entries.next_entry().is_none());
+}
+
+#[test]
+fn test_iter_borrowed_error() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let mut entries = parser.iter_borrowed();
+    assert!(entries.next_entry().unwrap().is_ok());
+    let err = entries.next_entry().unwrap().unwrap_err();
+    assert!(err.to_string().starts_with("Parse error at line 3: Invalid hash"));
+    assert!(entries.next_entry().is_none());
+}
+
+#[test]
+fn test_resolve_links() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  abs s /subdir/../empty.txt
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut parser = Parser::new(reader).unwrap();
+    let entries = parser.iter().resolve_links()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries.len(), 6);
+    assert_eq!(entries[0].1, None);
+    assert_eq!(entries[1].1, None);
+    assert_eq!(entries[4].0.path(), Path::new("/subdir/abs"));
+    assert_eq!(entries[4].1.as_ref().unwrap(), Path::new("/empty.txt"));
+    assert_eq!(entries[5].0.path(), Path::new("/subdir/just link"));
+    assert_eq!(entries[5].1.as_ref().unwrap(), Path::new("/hello.txt"));
+}
+
+#[test]
+fn test_peek() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let reader = BufReader::new(Cursor::new(&content[..]));
+    let mut parser = Parser::new(reader).unwrap();
+    let mut iter = parser.iter();
+    assert_eq!(iter.peek().unwrap().as_ref().unwrap().path(), Path::new("/"));
+    assert_eq!(iter.peek().unwrap().as_ref().unwrap().path(), Path::new("/"));
+    assert_eq!(iter.next().unwrap().unwrap().path(), Path::new("/"));
+    assert_eq!(iter.peek().unwrap().as_ref().unwrap().path(),
+               Path::new("/empty.txt"));
+    // advance to an entry before the peeked one doesn't consume it
+    assert!(iter.advance(&EntryKind::Dir("/")).is_none());
+    // advance to the peeked entry returns it
+    assert_eq!(iter.advance(&EntryKind::File("/empty.txt")).unwrap().unwrap()
+               .path(), Path::new("/empty.txt"));
+    assert_eq!(iter.peek().unwrap().as_ref().unwrap().path(),
+               Path::new("/hello.txt"));
+    // advance beyond the peeked entry skips it
+    assert_eq!(iter.advance(&EntryKind::Dir("/subdir")).unwrap().unwrap()
+               .path(), Path::new("/subdir"));
+    assert_eq!(iter.next().unwrap().unwrap().path(),
+               Path::new("/subdir/just link"));
+    assert!(iter.peek().is_none());
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_is_signature() {
+    use std::io::Read;
+    use dir_signature::v1::is_signature;
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut reader = BufReader::new(Cursor::new(&content[..]));
+    assert!(is_signature(&mut reader).unwrap());
+    // nothing is consumed
+    assert!(Parser::new(reader).is_ok());
+
+    for data in &[&b""[..], b"DIRSIGNATURE.v1", b"DIRSIGNATURE.v2 sha512/256",
+                  b"DIRSIGNATURE.v10 x", b"hello world\n"]
+    {
+        let mut reader = BufReader::new(Cursor::new(*data));
+        assert!(!is_signature(&mut reader).unwrap(), "{:?}", data);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(&buf[..], *data);
+    }
+}
+
+#[test]
+fn test_parser_uppercase_hex() {
+    use dir_signature::v1::Emitter;
+
+    let lower = "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  a\\x5cb\\x7f f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+  link s ../a\\x5cb\\x7f
+/dir\\xc3\\xa9
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let upper = "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  a\\x5Cb\\x7F f 6 8DD499A36D950B8732F85A3BFFBC8D8BEE4A0AF391E8EE2BB0AA0C4553B6C0FC
+  link s \\x2E\\x2E/a\\x5Cb\\x7F
+/dir\\xC3\\xA9
+C23F2579827456818FC855C458D1AD7339D144B57EE247A6628E4FC8E39958BB
+";
+    let entries = |content: &str| {
+        let reader = BufReader::new(Cursor::new(content.as_bytes()));
+        Parser::new(reader).unwrap()
+            .iter().collect::<Result<Vec<_>, _>>().unwrap()
+    };
+    let lower_entries = entries(lower);
+    let upper_entries = entries(upper);
+    assert_eq!(lower_entries, upper_entries);
+    assert_eq!(upper_entries[1].path(), Path::new("/a\\b\x7f"));
+    assert_eq!(upper_entries[3].path(), Path::new("/diré"));
+
+    // emitter always writes lowercase
+    let mut buf = Vec::new();
+    {
+        let mut e = Emitter::new(HashType::sha512_256(), 32768, &mut buf)
+            .unwrap();
+        for entry in &upper_entries {
+            let name = entry.path().file_name().unwrap_or_default();
+            match *entry {
+                Entry::Dir(ref path) => e.start_dir(path),
+                Entry::File { exe, size, ref hashes, .. } => {
+                    e.add_file(name, exe, size, hashes)
+                }
+                Entry::Link(_, ref dest) => e.add_symlink(name, dest),
+                Entry::Whiteout(_) => e.add_whiteout(name),
+                _ => unreachable!(),
+            }.unwrap();
+        }
+    }
+    let emitted = String::from_utf8(buf).unwrap();
+    assert_eq!(emitted.lines().take(5).collect::<Vec<_>>(),
+               lower.lines().take(5).collect::<Vec<_>>());
+
+    assert_eq!(
+        dir_signature::get_hash(&mut Cursor::new(upper.as_bytes())).unwrap(),
+        dir_signature::get_hash(&mut Cursor::new(lower.as_bytes())).unwrap());
+}
+
+#[test]
+fn test_parser_symlink_spaces() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  just\\x20link s ../hello\\x20world.txt
+";
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&content[..])))
+        .unwrap();
+    let entry = parser.iter().nth(1).unwrap().unwrap();
+    assert_eq!(entry.kind(), EntryKind::File(Path::new("/just link")));
+    assert!(matches!(entry,
+        Entry::Link(_, ref dest) if dest == Path::new("../hello world.txt")));
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  just\\x20link s ../hello world.txt
+";
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&content[..])))
+        .unwrap();
+    let err = parser.iter().nth(1).unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "Parse error at line 3: \
+        Invalid symlink: unexpected \"world.txt\" after destination \
+        (spaces in destination must be escaped as \\x20)");
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  just\\x20link s  ../hello.txt
+";
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&content[..])))
+        .unwrap();
+    let err = parser.iter().nth(1).unwrap().unwrap_err();
+    assert_eq!(err.to_string(),
+        "Parse error at line 3: Invalid line: Row has multiple spaces");
+}
+
+#[test]
+fn test_parser_clone() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let mut copy = parser.clone();
+    assert_eq!(copy.get_header().get_block_size(), 32768);
+
+    let mut iter = parser.iter();
+    assert_eq!(iter.next().unwrap().unwrap().path(), Path::new("/"));
+    assert_eq!(iter.next().unwrap().unwrap().path(), Path::new("/empty.txt"));
+
+    // the copy is not affected by iterating the original
+    let paths = copy.iter()
+        .map(|e| e.unwrap().path().to_path_buf())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec![
+        Path::new("/"),
+        Path::new("/empty.txt"),
+        Path::new("/hello.txt"),
+        Path::new("/subdir"),
+        Path::new("/subdir/just link"),
+    ]);
+    assert_eq!(iter.next().unwrap().unwrap().path(), Path::new("/hello.txt"));
+}
+
+#[test]
+fn test_parser_short_footer() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+c23f2579827456818fc855c458d1ad73
+";
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let err = parser.iter().nth(2).unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "Parse error at line 4: \
+        Invalid footer: sha512/256 hash must have 64 hex digits \
+        but footer has 32");
+}
+
+#[test]
+fn test_files_only() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+/subdir/empty
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let paths = parser.iter().files_only()
+        .map(|e| e.unwrap().path().to_path_buf())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec![
+        Path::new("/empty.txt"),
+        Path::new("/hello.txt"),
+        Path::new("/subdir/.hidden"),
+        Path::new("/subdir/just link"),
+    ]);
+}
+
+#[test]
+fn test_escape_name_roundtrip() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use dir_signature::v1::{escape_name, unescape_name};
+
+    let names: &[&[u8]] = &[
+        b"hello.txt",
+        b"just link",
+        b"a\x05b\n\t\x7f",
+        b"back\\slash",
+        b"a\\x05b",
+        b"\\",
+        b"\xd0\xbf\xd1\x80\xd0\xb8\xff",
+    ];
+    for &name in names {
+        let escaped = escape_name(OsStr::from_bytes(name));
+        assert!(!escaped.contains(' '));
+        assert_eq!(unescape_name(&escaped).as_bytes(), name);
+    }
+    assert_eq!(escape_name(OsStr::new("a\x05b")), r"a\x05b");
+    assert_eq!(escape_name(OsStr::new("a\\x05b")), r"a\x5cx05b");
+    assert_eq!(unescape_name(r"just\x20link"), OsStr::new("just link"));
+}
+
+#[test]
+fn test_future_version() {
+    let content = b"\
+DIRSIGNATURE.v2 blake3/256 block_size=32768 something=new
+/
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let err = Parser::new(Cursor::new(&content[..])).err().unwrap();
+    assert_eq!(err.unsupported_version(), None);
+    assert_eq!(err.to_string(), "Parse error at line 1: \
+        Invalid version: expected \"v1\" but was \"v2\"");
+
+    let err = Parser::with_version_detection(Cursor::new(&content[..]))
+        .err().unwrap();
+    assert_eq!(err.unsupported_version(), Some("v2"));
+    assert_eq!(err.to_string(), "Parse error at line 1: \
+        Unsupported version: file has version \"v2\" but only \"v1\" \
+        is supported, upgrade the library to read it");
+
+    let err = Parser::with_version_detection(
+            Cursor::new(&b"DIRSIGNATURE.vx sha512/256 block_size=32768\n"[..]))
+        .err().unwrap();
+    assert_eq!(err.unsupported_version(), None);
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    assert!(Parser::with_version_detection(Cursor::new(&content[..])).is_ok());
+}
+
+#[test]
+fn test_zero_block_size() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=0
+/
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let err = Parser::new(Cursor::new(&content[..])).err().unwrap();
+    assert_eq!(err.to_string(), "Parse error at line 1: \
+        Invalid block size: 0 (must be a positive integer)");
+}
+
+#[test]
+fn test_huge_file_size() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  huge.bin f 18446744073709551615 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(Cursor::new(&content[..])).unwrap();
+    let err = parser.iter().nth(1).unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "Parse error at line 3: Invalid hash: \
+        Expected 562949953421312 hashes but found 1");
+}
+
+#[cfg(feature="gzip")]
+#[test]
+fn test_gzip_multiple_members() {
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let (first, second) = content.split_at(content.len() / 2);
+    let mut compressed = Vec::new();
+    for part in &[first, second] {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(part).unwrap();
+        compressed.extend(enc.finish().unwrap());
+    }
+    let mut parser = Parser::from_gzip(&compressed[..]).unwrap();
+    let entries = parser.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries.len(), 6);
+    assert_eq!(entries[5].path(), Path::new("/subdir/just link"));
+}
+
+#[test]
+fn test_read_footer() {
+    use dir_signature::v1::read_footer;
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let expected: Vec<u8> = "c23f2579827456818fc855c458d1ad73\
+        39d144b57ee247a6628e4fc8e39958bb".from_hex().unwrap();
+    let footer = read_footer(&mut Cursor::new(&content[..]),
+                             HashType::sha512_256()).unwrap();
+    assert_eq!(footer.hash(), &expected[..]);
+
+    let mut marked = content[..content.len()-65].to_vec();
+    marked.push(b'=');
+    marked.extend(&content[content.len()-65..]);
+    let footer = read_footer(&mut Cursor::new(&marked[..]),
+                             HashType::sha512_256()).unwrap();
+    assert_eq!(footer.into_hash(), expected);
+
+    // hash of a wrong length
+    let short = b"DIRSIGNATURE.v1 sha512/256 block_size=32768\n/\nc23f25\n";
+    assert!(read_footer(&mut Cursor::new(&short[..]),
+                        HashType::sha512_256()).is_err());
+    // missing final newline
+    assert!(read_footer(&mut Cursor::new(&content[..content.len()-1]),
+                        HashType::sha512_256()).is_err());
+}
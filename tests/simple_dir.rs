@@ -65,10 +65,10 @@ DIRSIGNATURE.v1 sha512/256 block_size=32768
 ", "\n", 0);
 }
 
-#[cfg(feature="threads")]
+#[cfg(any(feature="threads", feature="std-threads"))]
 mod threads {
 
-    use dir_signature::{ScannerConfig, v1};
+    use dir_signature::{HashType, ScannerConfig, v1};
 
     #[test]
     fn test_dir1() {
@@ -133,6 +133,22 @@ DIRSIGNATURE.v1 sha512/256 block_size=32768
 141a80ae97aa3ed18cc84004b0cabb37b75619bb2c9cba753d9a710270f85e70
 ", "\n", 0);
     }
+
+    #[test]
+    fn test_blake3_same_as_single_thread() {
+        let mut cfg = ScannerConfig::new();
+        cfg.hash(HashType::blake3_256());
+        cfg.add_dir("tests/dir1", "/");
+        cfg.add_dir("tests/dir2", "/");
+        cfg.threads(1);
+        let mut single = Vec::new();
+        v1::scan(&cfg, &mut single).unwrap();
+        cfg.threads(4);
+        let mut threaded = Vec::new();
+        v1::scan(&cfg, &mut threaded).unwrap();
+        assert_diff!(&String::from_utf8_lossy(&threaded),
+                     &String::from_utf8_lossy(&single), "\n", 0);
+    }
 }
 
 #[test]
@@ -167,10 +183,737 @@ fn test_blake3_dir2() {
 /
   file2.txt f 18 99b6111fa45d4c059be2ca5b1b385386aeb1e97ea5c904a52129fb83540fc83b
 /sub2
-  hello.txt f 6 1f24336b1d7e12241911ed57c018aab77735f2193cc96048c2ce228ae6356204
+  hello.txt f 6 26e70f0a438787ee143979a9b519a4a330ea21e0a23d31fcb47051e70b8fe5ad
+/subdir
+  bigdata.bin f 81920 ac169ead597dac88b2d7223edd85c9895392532cfc7a3c5c29a3fbe3ccba37f2 ac169ead597dac88b2d7223edd85c9895392532cfc7a3c5c29a3fbe3ccba37f2 111f6c2f2ac0fc43154414a6e3e4c104cb04907e9453d3ac85cc5f55cc015b48
+  file3.txt f 12 1bf82b88aa13e45ffa7bf078480cbb0dd01d3f28305b95f1cd95687f99bcef5b
+57ba1f0b5f124384c77d61f6baab461bf4db588f37944eb7f5812f807af0722c
+", "\n", 0);
+}
+
+#[test]
+fn test_mtime_cutoff() {
+    use std::fs::File;
+    use std::io::Write;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = tempfile::tempdir().unwrap();
+    let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let new = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    for &(name, mtime) in &[("old.txt", old), ("new.txt", new)] {
+        let mut f = File::create(dir.path().join(name)).unwrap();
+        f.write_all(b"hello\n").unwrap();
+        f.set_modified(mtime).unwrap();
+    }
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    cfg.mtime_cutoff(UNIX_EPOCH + Duration::from_secs(1_200_000_000));
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    assert_diff!(&String::from_utf8_lossy(&buf), "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  old.txt f 6 7f3f0c0d5219f51459578305ed2bbc198588758da85d08024c79c1195d1cd611
+d93618a9107b6ee77e722918d97984b4735008fd1acbff5b97b6391e642394d1
+", "\n", 0);
+}
+
+#[test]
+fn test_prune_empty_dirs() {
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+    fs::create_dir_all(dir.path().join("a/d")).unwrap();
+    fs::create_dir_all(dir.path().join("e/f")).unwrap();
+    fs::create_dir_all(dir.path().join("g")).unwrap();
+    File::create(dir.path().join("e/f/hello.txt")).unwrap()
+        .write_all(b"hello\n").unwrap();
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    cfg.prune_empty_dirs();
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let text = String::from_utf8_lossy(&buf);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(&lines[1..lines.len()-1], &[
+        "/",
+        "/e",
+        "/e/f",
+        "  hello.txt f 6 \
+         7f3f0c0d5219f51459578305ed2bbc198588758da85d08024c79c1195d1cd611",
+    ]);
+
+    fs::remove_dir_all(dir.path().join("e")).unwrap();
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let text = String::from_utf8_lossy(&buf);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(&lines[1..lines.len()-1], &["/"]);
+}
+
+#[test]
+fn test_max_depth() {
+    use std::fs::{self, File};
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    File::create(dir.path().join("root.txt")).unwrap();
+    File::create(dir.path().join("a/first.txt")).unwrap();
+    File::create(dir.path().join("a/b/second.txt")).unwrap();
+
+    let scan = |depth| {
+        let mut cfg = ScannerConfig::new();
+        cfg.add_dir(dir.path(), "/");
+        if let Some(depth) = depth {
+            cfg.max_depth(depth);
+        }
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines = text.lines().map(|l| l.to_string()).collect::<Vec<_>>();
+        lines[1..lines.len()-1].to_vec()
+    };
+    assert_eq!(scan(Some(1)), vec![
+        "/",
+        "  root.txt f 0",
+        "/a",
+        "  first.txt f 0",
+    ]);
+    assert_eq!(scan(Some(0)), vec!["/", "  root.txt f 0"]);
+    assert_eq!(scan(Some(2)), scan(None));
+    assert_eq!(scan(None).len(), 6);
+}
+
+#[test]
+fn test_exe_bit() {
+    use std::fs::{self, File};
+    use std::os::unix::fs::PermissionsExt;
+    use dir_signature::ExeBitMode;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("group.sh");
+    File::create(&path).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o654)).unwrap();
+
+    let scan = |mode| {
+        let mut cfg = ScannerConfig::new();
+        cfg.add_dir(dir.path(), "/");
+        if let Some(mode) = mode {
+            cfg.exe_bit(mode);
+        }
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines = text.lines().map(|l| l.to_string()).collect::<Vec<_>>();
+        lines[1..lines.len()-1].to_vec()
+    };
+    assert_eq!(scan(None), vec!["/", "  group.sh f 0"]);
+    assert_eq!(scan(Some(ExeBitMode::OwnerOnly)), scan(None));
+    assert_eq!(scan(Some(ExeBitMode::AnyExecute)),
+               vec!["/", "  group.sh x 0"]);
+}
+
+#[test]
+fn test_collect_skipped() {
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    File::create(dir.path().join("old.txt")).unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+        .unwrap();
+    File::create(dir.path().join("sub/new.txt")).unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(1_500_000_000))
+        .unwrap();
+    let status = Command::new("mkfifo").arg(dir.path().join("fifo"))
+        .status().unwrap();
+    assert!(status.success());
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    cfg.mtime_cutoff(UNIX_EPOCH + Duration::from_secs(1_200_000_000));
+    let mut buf = Vec::new();
+    let stats = v1::scan_with_stats(&cfg, &mut buf).unwrap();
+    assert_eq!(stats.skipped, Vec::<PathBuf>::new());
+
+    cfg.collect_skipped();
+    let mut buf2 = Vec::new();
+    let mut stats = v1::scan_with_stats(&cfg, &mut buf2).unwrap();
+    assert_eq!(buf, buf2);
+    stats.skipped.sort();
+    assert_eq!(stats.skipped, vec![
+        PathBuf::from("/fifo"),
+        PathBuf::from("/sub/new.txt"),
+    ]);
+}
+
+#[test]
+fn test_symlink_non_utf8_target() {
+    use std::ffi::OsStr;
+    use std::io::{BufReader, Cursor};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+    use dir_signature::v1::{Entry, Parser};
+
+    let target = Path::new(OsStr::from_bytes(b"caf\xe9 \\\x80\x7f.txt"));
+    let dir = tempfile::tempdir().unwrap();
+    symlink(target, dir.path().join("link")).unwrap();
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf).contains(
+        "  link s caf\\xe9\\x20\\x5c\\x80\\x7f.txt\n"));
+
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    let entries = parser.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    match entries[1] {
+        Entry::Link(ref path, ref dest) => {
+            assert_eq!(path, Path::new("/link"));
+            assert_eq!(dest, target);
+        }
+        ref e => panic!("unexpected entry {:?}", e),
+    }
+}
+
+#[test]
+fn test_structural_hash() {
+    use std::fs;
+    use std::io::{BufReader, Cursor};
+    use dir_signature::v1::{Parser, structural_hash};
+
+    fn hash(dir: &std::path::Path) -> Vec<u8> {
+        let mut cfg = ScannerConfig::new();
+        cfg.add_dir(dir, "/");
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        let mut parser = Parser::new(BufReader::new(Cursor::new(&buf)))
+            .unwrap();
+        structural_hash(&mut parser).unwrap()
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/a.txt"), b"hello\n").unwrap();
+    let original = hash(dir.path());
+    assert_eq!(original.len(), 32);
+
+    fs::write(dir.path().join("sub/a.txt"), b"world\n").unwrap();
+    assert_eq!(hash(dir.path()), original);
+
+    fs::write(dir.path().join("b.txt"), b"").unwrap();
+    assert_ne!(hash(dir.path()), original);
+}
+
+#[test]
+fn test_store_ownership() {
+    use std::fs;
+    use std::io::{BufReader, Cursor};
+    use std::os::unix::fs::MetadataExt;
+    use dir_signature::v1::Parser;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    fs::write(dir.path().join("sub/a.txt"), b"hello\n").unwrap();
+    let meta = fs::metadata(dir.path()).unwrap();
+    let owner = (meta.uid(), meta.gid());
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    cfg.store_ownership();
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let text = String::from_utf8(buf.clone()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0],
+        "DIRSIGNATURE.v1 sha512/256 block_size=32768 ownership=yes");
+    assert_eq!(lines[1], format!("/ uid={} gid={}", owner.0, owner.1));
+    assert_eq!(lines[3], format!("  a.txt f 6 \
+        7f3f0c0d5219f51459578305ed2bbc198588758da85d08024c79c1195d1cd611 \
+        uid={} gid={}", owner.0, owner.1));
+
+    cfg.threads(2);
+    let mut threaded = Vec::new();
+    v1::scan(&cfg, &mut threaded).unwrap();
+    assert_eq!(threaded, buf);
+
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    assert_eq!(parser.get_header().attr("ownership"), Some("yes"));
+    let entries = parser.iter_with_ownership()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries.len(), 3);
+    for (entry, entry_owner) in entries {
+        assert_eq!(entry_owner, Some(owner), "{:?}", entry);
+    }
+}
+
+#[test]
+fn test_hash_bytes_matches_footer() {
+    use rustc_hex::ToHex;
+
+    for &hash in &[HashType::sha512_256(), HashType::blake2b_256(),
+                   HashType::blake3_256()]
+    {
+        let mut cfg = ScannerConfig::new();
+        cfg.add_dir("tests/dir1", "/");
+        cfg.hash(hash);
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        let body_start = buf.iter().position(|&c| c == b'\n').unwrap() + 1;
+        let footer_start = buf[..buf.len()-1].iter()
+            .rposition(|&c| c == b'\n').unwrap() + 1;
+        let footer = String::from_utf8_lossy(&buf[footer_start..buf.len()-1])
+            .into_owned();
+        let hash = v1::hash_bytes(hash, &buf[body_start..footer_start]);
+        assert_eq!(hash.to_hex(), footer);
+    }
+}
+
+#[test]
+fn test_copy_verified() {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir2", "/");
+    let (buf, id) = v1::scan_to_vec(&cfg).unwrap();
+    let mut copy = Vec::new();
+    assert_eq!(v1::copy_verified(&buf[..], &mut copy).unwrap(), id);
+    assert_eq!(copy, buf);
+
+    cfg.marked_footer();
+    let (buf, id) = v1::scan_to_vec(&cfg).unwrap();
+    assert_eq!(v1::copy_verified(&buf[..], &mut Vec::new()).unwrap(), id);
+
+    let tampered = String::from_utf8(buf).unwrap()
+        .replace("file2.txt f 18", "file2.txt x 18");
+    let err = v1::copy_verified(tampered.as_bytes(), &mut Vec::new())
+        .unwrap_err();
+    assert!(matches!(err, dir_signature::Error::FooterMismatch(..)),
+        "unexpected error: {}", err);
+
+    let truncated = &tampered.as_bytes()[..tampered.len()-66];
+    let err = v1::copy_verified(truncated, &mut Vec::new()).unwrap_err();
+    assert!(err.to_string().ends_with("Missing footer"), "{}", err);
+}
+
+#[test]
+fn test_path_collision() {
+    use std::fs;
+    use dir_signature::{CollisionPolicy, Error};
+
+    let dir1 = tempfile::tempdir().unwrap();
+    let dir2 = tempfile::tempdir().unwrap();
+    fs::create_dir(dir1.path().join("sub")).unwrap();
+    fs::create_dir(dir2.path().join("sub")).unwrap();
+    fs::write(dir1.path().join("sub/a.txt"), b"first\n").unwrap();
+    fs::write(dir2.path().join("sub/a.txt"), b"second one\n").unwrap();
+    fs::write(dir2.path().join("sub/b.txt"), b"").unwrap();
+
+    let scan = |policy| {
+        let mut cfg = ScannerConfig::new();
+        cfg.add_dir(dir1.path(), "/");
+        cfg.add_dir(dir2.path(), "/");
+        cfg.on_collision(policy);
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).map(|()| String::from_utf8(buf).unwrap())
+    };
+    match scan(CollisionPolicy::Error) {
+        Err(Error::PathCollision(path)) => {
+            assert_eq!(path, std::path::Path::new("/sub/a.txt"));
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    let first = scan(CollisionPolicy::FirstWins).unwrap();
+    assert!(first.contains("\n  a.txt f 6 "));
+    assert!(first.contains("\n  b.txt f 0\n"));
+    let last = scan(CollisionPolicy::LastWins).unwrap();
+    assert!(last.contains("\n  a.txt f 11 "));
+    assert_eq!(last.matches("a.txt").count(), 1);
+}
+
+#[test]
+fn test_hash_file() {
+    use rustc_hex::ToHex;
+
+    let (size, hashes) = v1::hash_file("tests/dir1/hello.txt",
+        HashType::sha512_256(), 32768).unwrap();
+    assert_eq!(size, 6);
+    assert_eq!(hashes.len(), 1);
+    let hash: String = hashes.get(0).unwrap().to_hex();
+    assert_eq!(hash,
+        "a79eef66019bfb9a41f798f2cff2d2d36ed294cc3f96bf53bbfc5192ebe60192");
+
+    let (size, hashes) = v1::hash_file("tests/dir1/hello.txt",
+        HashType::sha512_256(), 4).unwrap();
+    assert_eq!(size, 6);
+    assert_eq!(hashes.len(), 2);
+
+    let (size, hashes) = v1::hash_file("tests/dir1/test.txt",
+        HashType::sha512_256(), 32768).unwrap();
+    assert_eq!(size, 0);
+    assert_eq!(hashes.len(), 0);
+}
+
+#[test]
+fn test_verify_block() {
+    use std::fs::{File, read};
+    use std::io::Cursor;
+
+    for &typ in &[HashType::sha512_256(), HashType::blake3_256()] {
+        let (size, hashes) = v1::hash_file("tests/dir2/subdir/bigdata.bin",
+            typ, 32768).unwrap();
+        assert_eq!(size, 81920);
+        assert_eq!(hashes.len(), 3);
+        let mut f = File::open("tests/dir2/subdir/bigdata.bin").unwrap();
+        assert!(hashes.verify_block(0, &mut f).unwrap());
+        assert!(hashes.verify_block(2, &mut f).unwrap());
+        assert!(hashes.verify_block(1, &mut f).unwrap());
+        assert!(!hashes.verify_block(3, &mut f).unwrap());
+
+        let mut data = read("tests/dir2/subdir/bigdata.bin").unwrap();
+        data[40000] ^= 1;
+        let mut f = Cursor::new(data);
+        assert!(hashes.verify_block(0, &mut f).unwrap());
+        assert!(!hashes.verify_block(1, &mut f).unwrap());
+    }
+}
+
+#[test]
+fn test_symlink_targets() {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use dir_signature::TargetMode;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    symlink("/a/c", dir.path().join("a/b/abs")).unwrap();
+    symlink("../../c", dir.path().join("a/b/rel")).unwrap();
+
+    let scan = |mode| {
+        let mut cfg = ScannerConfig::new();
+        cfg.add_dir(dir.path(), "/");
+        cfg.symlink_targets(mode);
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        text.lines()
+            .filter(|line| line.contains(" s "))
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(scan(TargetMode::Verbatim),
+        vec!["  abs s /a/c", "  rel s ../../c"]);
+    assert_eq!(scan(TargetMode::Relative),
+        vec!["  abs s ../c", "  rel s ../../c"]);
+    assert_eq!(scan(TargetMode::Absolute),
+        vec!["  abs s /a/c", "  rel s /c"]);
+}
+
+#[test]
+fn test_no_root_directory() {
+    use dir_signature::Error;
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/subdir");
+    let mut buf = Vec::new();
+    match v1::scan(&cfg, &mut buf) {
+        Err(e @ Error::NoRootDirectory) => {
+            assert_eq!(e.to_string(), "no root directory to build index for: \
+                add a directory with `/` prefix, i.e. `add_dir(path, \"/\")`");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(buf, b"");
+}
+
+#[test]
+fn test_no_carriage_returns() {
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("crlf\r\n.txt"), b"line\r\n").unwrap();
+    fs::create_dir(dir.path().join("sub\r")).unwrap();
+    fs::write(dir.path().join("sub\r/file"), b"data\n").unwrap();
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    for &threads in &[0, 2] {
+        cfg.threads(threads);
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        assert!(!buf.contains(&b'\r'));
+        assert!(String::from_utf8(buf).unwrap()
+            .contains("\n  crlf\\x0d\\x0a.txt f 6 "));
+    }
+}
+
+#[test]
+fn test_scan_to_vec() {
+    use std::io::Cursor;
+
+    for &typ in &[HashType::sha512_256(), HashType::blake3_256()] {
+        let mut cfg = ScannerConfig::new();
+        cfg.hash(typ);
+        cfg.add_dir("tests/dir1", "/");
+        let (buf, id) = v1::scan_to_vec(&cfg).unwrap();
+        assert_eq!(id.len(), 32);
+        assert_eq!(id, dir_signature::get_hash(&mut Cursor::new(&buf))
+            .unwrap());
+        let mut expected = Vec::new();
+        v1::scan(&cfg, &mut expected).unwrap();
+        assert_eq!(buf, expected);
+    }
+}
+
+#[test]
+fn test_scan_combined_hash() {
+    for &typ in HashType::all() {
+        let mut cfg = ScannerConfig::new();
+        cfg.hash(typ);
+        cfg.add_dir("tests/dir2", "/");
+        let (_, id) = v1::scan_to_vec(&cfg).unwrap();
+        assert_eq!(v1::scan_combined_hash(&cfg).unwrap(), id);
+        cfg.marked_footer();
+        assert_eq!(v1::scan_combined_hash(&cfg).unwrap(), id);
+    }
+}
+
+#[test]
+fn test_default_block_size() {
+    use dir_signature::DEFAULT_BLOCK_SIZE;
+
+    let mut cfg = ScannerConfig::new();
+    assert_eq!(cfg.get_block_size(), DEFAULT_BLOCK_SIZE);
+    cfg.add_dir("tests/dir1", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let mut parser = v1::Parser::new(&buf[..]).unwrap();
+    assert_eq!(parser.get_header().get_block_size(), DEFAULT_BLOCK_SIZE);
+    assert!(parser.iter().all(|entry| match entry.unwrap() {
+        v1::Entry::File { hashes, .. } => {
+            hashes.block_size() == DEFAULT_BLOCK_SIZE
+        }
+        _ => true,
+    }));
+}
+
+#[test]
+fn test_estimate_size() {
+    for &(dir, ownership) in &[("tests/dir1", false), ("tests/dir2", false),
+                               ("tests/dir2", true)] {
+        let mut cfg = ScannerConfig::new();
+        if ownership {
+            cfg.store_ownership();
+        }
+        cfg.add_dir(dir, "/");
+        let mut buf = Vec::new();
+        v1::scan(&cfg, &mut buf).unwrap();
+        assert_eq!(v1::estimate_size(&cfg).unwrap(), buf.len() as u64);
+    }
+}
+
+#[test]
+fn test_scan_many() {
+    let mut cfg1 = ScannerConfig::new();
+    cfg1.add_dir("tests/dir1", "/");
+    let mut cfg2 = ScannerConfig::new();
+    cfg2.add_dir("tests/dir2", "/");
+    let configs = [("first", cfg1), ("second image", cfg2)];
+    let mut buf = Vec::new();
+    v1::scan_many(&configs, &mut buf).unwrap();
+    assert!(buf.starts_with(b"SECTION first\nDIRSIGNATURE.v1"));
+
+    let sections = v1::Sections::new(&buf[..])
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(sections.len(), 2);
+    for ((label, mut parser), &(exp_label, ref cfg)) in
+        sections.into_iter().zip(&configs)
+    {
+        assert_eq!(label, exp_label);
+        let (expected, _) = v1::scan_to_vec(cfg).unwrap();
+        let mut expected = v1::Parser::new(&expected[..]).unwrap();
+        let entries = parser.iter()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(!entries.is_empty());
+        assert_eq!(entries, expected.iter()
+            .collect::<Result<Vec<_>, _>>().unwrap());
+    }
+
+    match v1::Sections::new(&b"/\n"[..]).next() {
+        Some(Err(e)) => assert_eq!(e.to_string(),
+            "Parse error at line 1: Invalid line: Expected a SECTION line"),
+        _ => panic!("error expected"),
+    }
+    assert!(v1::Sections::new(&b""[..]).next().is_none());
+}
+
+#[test]
+fn test_scan_twice_identical() {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    assert!(v1::scan_twice_identical(&cfg).unwrap());
+    cfg.threads(4);
+    assert!(v1::scan_twice_identical(&cfg).unwrap());
+}
+
+#[test]
+fn test_auto_block_size() {
+    use std::fs::{self, File};
+    use dir_signature::DEFAULT_BLOCK_SIZE;
+
+    // small tree gets the default block size
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir2", "/");
+    cfg.auto_block_size();
+    assert_eq!(v1::choose_block_size(&cfg).unwrap(), DEFAULT_BLOCK_SIZE);
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let mut expected = Vec::new();
+    v1::scan(ScannerConfig::new().add_dir("tests/dir2", "/"), &mut expected)
+        .unwrap();
+    assert_eq!(buf, expected);
+
+    // sparse files, only metadata is read
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    for i in 0..100 {
+        File::create(dir.path().join(format!("sub/{}.bin", i))).unwrap()
+            .set_len(1 << 30).unwrap();
+    }
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    assert_eq!(v1::choose_block_size(&cfg).unwrap(), DEFAULT_BLOCK_SIZE);
+    cfg.auto_block_size();
+    // 1GiB file gives 4096 hashes with 256KiB blocks
+    assert_eq!(v1::choose_block_size(&cfg).unwrap(), 256 << 10);
+
+    File::create(dir.path().join("huge.bin")).unwrap()
+        .set_len(1 << 40).unwrap();
+    assert_eq!(v1::choose_block_size(&cfg).unwrap(), 64 << 20);
+}
+
+#[test]
+fn test_marked_footer() {
+    use std::io::Cursor;
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    let (plain, id) = v1::scan_to_vec(&cfg).unwrap();
+    cfg.marked_footer();
+    let (marked, marked_id) = v1::scan_to_vec(&cfg).unwrap();
+    assert_eq!(id, marked_id);
+    assert_eq!(v1::estimate_size(&cfg).unwrap(), marked.len() as u64);
+    assert_diff!(&String::from_utf8_lossy(&marked), "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768 footer=marked
+/
+  hello.txt f 6 a79eef66019bfb9a41f798f2cff2d2d36ed294cc3f96bf53bbfc5192ebe60192
+  test.txt f 0
 /subdir
-  bigdata.bin f 81920 3c14a8b1cc0bf7a59bb848df9ca8618b54ea4fe3a8bc2b02015d3f63bfb305f3 e3a7977eb650db263073579117ca57171987044d539f4f3a12b36d4ff216519f ec8343a48a538a03a7dfa06f1d849cb0952d9739c9ac72bbd21318e3354c5725
-  file3.txt f 12 0c244b7e2c3241d0b2c06ce095dbb9c75e603e00a7ea36fb4b6a77ce137c90ac
-d4a144758b5e126e4c2ee60f743a409294bfc18bf226a68d524d3ecb43a8991e
+  .hidden f 7 6d7f5f9804ee4dbc1ff7e12c7665387e0119e8ea629996c52d38b75c12ad0acf
+  file.txt f 10 0119865c765e02554f6fc5a06fa76aa92c590c09225775c092144079f9964899
+=552ca5730ee95727e890a2155c88609d244624034ff70de264cf88220d11d6df
 ", "\n", 0);
+    assert_eq!(dir_signature::get_hash(&mut Cursor::new(&marked)).unwrap(),
+               id);
+
+    // both kinds of footers are accepted by the parser
+    for buf in &[plain, marked] {
+        let mut parser = v1::Parser::new(&buf[..]).unwrap();
+        let entries = parser.iter()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), 6);
+    }
+}
+
+#[test]
+fn test_content_defined_chunks() {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    cfg.content_defined_chunks();
+    let (buf, _) = v1::scan_to_vec(&cfg).unwrap();
+    assert_diff!(&String::from_utf8_lossy(&buf), "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768 chunking=cdc
+/
+  hello.txt f 6 6:a79eef66019bfb9a41f798f2cff2d2d36ed294cc3f96bf53bbfc5192ebe60192
+  test.txt f 0
+/subdir
+  .hidden f 7 7:6d7f5f9804ee4dbc1ff7e12c7665387e0119e8ea629996c52d38b75c12ad0acf
+  file.txt f 10 10:0119865c765e02554f6fc5a06fa76aa92c590c09225775c092144079f9964899
+e2794b804f6cfd0c4d0ac6fba34518d589bcb1b785f0e10b72a75c14b9e9b8c3
+", "\n", 0);
+    let mut parser = v1::Parser::new(&buf[..]).unwrap();
+    let owned = parser.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    for entry in &owned {
+        if let v1::Entry::File { size, ref hashes, .. } = *entry {
+            assert_eq!(hashes.chunk_lengths().unwrap().iter().sum::<u64>(),
+                       size);
+        }
+    }
+    let mut parser = v1::Parser::new(&buf[..]).unwrap();
+    let mut entries = parser.iter_borrowed();
+    let mut idx = 0;
+    while let Some(entry) = entries.next_entry() {
+        assert_eq!(entry.unwrap().to_entry(), owned[idx]);
+        idx += 1;
+    }
+    assert_eq!(idx, owned.len());
+
+    // emitter keeps the chunks
+    let mut parser = v1::Parser::new(&buf[..]).unwrap();
+    let split = v1::split_by_prefix(&mut parser, &["/".into()],
+        |_| Vec::new()).unwrap();
+    assert_eq!(split[0], buf);
+}
+
+#[test]
+fn test_chunks_insertion() {
+    use std::fs::{self, File};
+
+    // xorshift, just to have reproducible incompressible data
+    let mut state = 0x2545f4914f6cdd1du64;
+    let data = (0..1 << 20).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 24) as u8
+    }).collect::<Vec<_>>();
+    let mut edited = data.clone();
+    edited.insert(500_000, b'x');
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir.path(), "/");
+    cfg.hash(HashType::blake3_256());
+    cfg.content_defined_chunks();
+    let index = |data: &[u8]| {
+        fs::write(dir.path().join("file.bin"), data).unwrap();
+        let (buf, _) = v1::scan_to_vec(&cfg).unwrap();
+        let mut parser = v1::Parser::new(&buf[..]).unwrap();
+        match parser.iter().nth(1).unwrap().unwrap() {
+            v1::Entry::File { hashes, .. } => hashes,
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    };
+    let old = index(&data);
+    let new = index(&edited);
+    assert_eq!(old.len(), new.len());
+    let changed = new.iter()
+        .filter(|hash| !old.iter().any(|o| o == *hash))
+        .count();
+    assert_eq!(changed, 1);
+
+    let mut f = File::open(dir.path().join("file.bin")).unwrap();
+    assert!(new.check_file(&mut f).unwrap());
+    assert!(!old.check_file(&edited[..]).unwrap());
+    for idx in 0..new.len() {
+        assert!(new.verify_block(idx, &mut f).unwrap());
+    }
+    assert!(!new.verify_block(new.len(), &mut f).unwrap());
 }
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Cursor};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::v1::{DirSource, EntryType, Metadata};
+
+
+enum Node {
+    Dir(MemDir),
+    File(&'static [u8]),
+}
+
+#[derive(Clone)]
+struct MemDir(Arc<BTreeMap<OsString, Node>>);
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such entry")
+}
+
+fn meta(size: u64) -> Metadata {
+    Metadata { size, mode: 0o644, mtime: UNIX_EPOCH, uid: 0, gid: 0 }
+}
+
+impl DirSource for MemDir {
+    type File = Cursor<&'static [u8]>;
+
+    fn list_dir(&self) -> io::Result<Vec<(OsString, EntryType)>> {
+        Ok(self.0.iter().map(|(name, node)| {
+            (name.clone(), match *node {
+                Node::Dir(..) => EntryType::Dir,
+                Node::File(..) => EntryType::File,
+            })
+        }).collect())
+    }
+    fn sub_dir(&self, name: &OsStr) -> io::Result<MemDir> {
+        match self.0.get(name) {
+            Some(&Node::Dir(ref dir)) => Ok(dir.clone()),
+            _ => Err(not_found()),
+        }
+    }
+    fn self_metadata(&self) -> io::Result<Metadata> {
+        Ok(meta(0))
+    }
+    fn metadata(&self, name: &OsStr) -> io::Result<Metadata> {
+        match self.0.get(name) {
+            Some(&Node::Dir(..)) => Ok(meta(0)),
+            Some(&Node::File(data)) => Ok(meta(data.len() as u64)),
+            None => Err(not_found()),
+        }
+    }
+    fn open_file(&self, name: &OsStr) -> io::Result<(Self::File, Metadata)> {
+        match self.0.get(name) {
+            Some(&Node::File(data)) => {
+                Ok((Cursor::new(data), meta(data.len() as u64)))
+            }
+            _ => Err(not_found()),
+        }
+    }
+    fn read_link(&self, _name: &OsStr) -> io::Result<PathBuf> {
+        Err(not_found())
+    }
+}
+
+fn mem_dir(items: Vec<(&str, Node)>) -> MemDir {
+    MemDir(Arc::new(items.into_iter()
+        .map(|(name, node)| (OsString::from(name), node))
+        .collect()))
+}
+
+#[test]
+fn test_in_memory_source() {
+    let root = mem_dir(vec![
+        ("hello.txt", Node::File(b"HeLLo\n")),
+        ("test.txt", Node::File(b"")),
+        ("subdir", Node::Dir(mem_dir(vec![
+            (".hidden", Node::File(b"Hidden\n")),
+            ("file.txt", Node::File(b"SomenData\n")),
+        ]))),
+    ]);
+
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    let mut expected = Vec::new();
+    v1::scan(&cfg, &mut expected).unwrap();
+
+    let mut buf = Vec::new();
+    v1::scan_source(&ScannerConfig::new(), root.clone(), &mut buf).unwrap();
+    assert_eq!(String::from_utf8_lossy(&buf),
+               String::from_utf8_lossy(&expected));
+
+    let mut cfg = ScannerConfig::new();
+    cfg.threads(2);
+    let mut buf = Vec::new();
+    v1::scan_source(&cfg, root, &mut buf).unwrap();
+    assert_eq!(buf, expected);
+}
+
+/// A directory with a single file that is being appended to
+///
+/// Every `open_file` returns the next version of the file, while
+/// `metadata` returns the size of the latest opened version or the next
+/// one, as if the file was written right after it was opened.
+#[derive(Clone)]
+struct LiveDir(Arc<(Vec<&'static [u8]>, AtomicUsize)>);
+
+impl LiveDir {
+    fn current(&self, opening: bool) -> &'static [u8] {
+        let (ref versions, ref opened) = *self.0;
+        let idx = if opening {
+            opened.fetch_add(1, Ordering::SeqCst)
+        } else {
+            opened.load(Ordering::SeqCst)
+        };
+        versions[idx.min(versions.len() - 1)]
+    }
+}
+
+impl DirSource for LiveDir {
+    type File = Cursor<&'static [u8]>;
+
+    fn list_dir(&self) -> io::Result<Vec<(OsString, EntryType)>> {
+        Ok(vec![(OsString::from("log.txt"), EntryType::File)])
+    }
+    fn sub_dir(&self, _name: &OsStr) -> io::Result<LiveDir> {
+        Err(not_found())
+    }
+    fn self_metadata(&self) -> io::Result<Metadata> {
+        Ok(meta(0))
+    }
+    fn metadata(&self, _name: &OsStr) -> io::Result<Metadata> {
+        Ok(meta(self.current(false).len() as u64))
+    }
+    fn open_file(&self, _name: &OsStr) -> io::Result<(Self::File, Metadata)> {
+        let data = self.current(true);
+        Ok((Cursor::new(data), meta(data.len() as u64)))
+    }
+    fn read_link(&self, _name: &OsStr) -> io::Result<PathBuf> {
+        Err(not_found())
+    }
+}
+
+#[test]
+fn test_stable_read() {
+    let live = || LiveDir(Arc::new((
+        vec![&b"a"[..], &b"ab"[..], &b"abc"[..]],
+        AtomicUsize::new(0),
+    )));
+    let size = |buf: &[u8]| {
+        String::from_utf8_lossy(buf).lines()
+            .find(|line| line.starts_with("  log.txt"))
+            .and_then(|line| line.split(' ').nth(4).map(String::from))
+            .unwrap()
+    };
+
+    let mut buf = Vec::new();
+    v1::scan_source(&ScannerConfig::new(), live(), &mut buf).unwrap();
+    assert_eq!(size(&buf), "1");
+
+    let mut cfg = ScannerConfig::new();
+    cfg.stable_read(2);
+    let mut buf = Vec::new();
+    v1::scan_source(&cfg, live(), &mut buf).unwrap();
+    assert_eq!(size(&buf), "3");
+
+    let mut cfg = ScannerConfig::new();
+    cfg.stable_read(1);
+    let mut buf = Vec::new();
+    assert!(v1::scan_source(&cfg, live(), &mut buf).is_err());
+}
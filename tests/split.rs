@@ -0,0 +1,87 @@
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+
+use dir_signature::v1::{Parser, split_by_prefix};
+
+
+#[test]
+fn test_split_by_prefix() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  readme.txt f 0
+/etc
+  hosts f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/etc/ssl
+  cert.pem s ../../opt/app/cert.pem
+/opt
+/opt/app
+  cert.pem f 0
+  run x 5 339d14455c458d1ad7b57ee247a6628e4fc8e39958bbc23f2579827456818fc8
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&content[..])))
+        .unwrap();
+    let prefixes = vec![PathBuf::from("/etc"), PathBuf::from("/opt")];
+    let outputs = split_by_prefix(&mut parser, &prefixes, |prefix| {
+        assert!(prefix == Path::new("/etc") || prefix == Path::new("/opt"));
+        Vec::new()
+    }).unwrap();
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(String::from_utf8_lossy(&outputs[0]), "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  hosts f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/ssl
+  cert.pem s ../../opt/app/cert.pem
+0f98066fd795c8696fd4983dd911f8ad4535cea819b616aafea7d0134467c82c
+");
+    assert_eq!(String::from_utf8_lossy(&outputs[1]), "\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+/app
+  cert.pem f 0
+  run x 5 339d14455c458d1ad7b57ee247a6628e4fc8e39958bbc23f2579827456818fc8
+5b6d7223a7a98f2034014108e62d939dd70d4c05a89d63a7436b5e2330f01392
+");
+}
+
+#[test]
+fn test_split_missing_and_file_prefix() {
+    use dir_signature::Error;
+
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  readme.txt f 0
+/etc
+  hosts f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+";
+    let parser = || {
+        Parser::new(BufReader::new(Cursor::new(&content[..]))).unwrap()
+    };
+    let outputs = split_by_prefix(&mut parser(), &[PathBuf::from("/var")],
+        |_| Vec::new()).unwrap();
+    let text = String::from_utf8(outputs[0].clone()).unwrap();
+    assert_eq!(text.lines().take(2).collect::<Vec<_>>(), vec![
+        "DIRSIGNATURE.v1 sha512/256 block_size=32768",
+        "/",
+    ]);
+    assert_eq!(text.lines().count(), 3);
+    let mut check = Parser::new(BufReader::new(Cursor::new(text.as_bytes())))
+        .unwrap();
+    let entries = check.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(entries.len(), 1);
+
+    for prefix in &["/readme.txt", "/etc/hosts"] {
+        let res = split_by_prefix(&mut parser(), &[PathBuf::from(prefix)],
+            |_| Vec::new());
+        match res {
+            Err(Error::NotADirectory(ref path)) => {
+                assert_eq!(path, Path::new(prefix));
+            }
+            _ => panic!("unexpected result for {}", prefix),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, Cursor};
+use std::path::PathBuf;
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::v1::Parser;
+use dir_signature::v1::stats::{dir_sizes, dir_sizes_recursive};
+
+
+fn scan_dir1() -> Vec<u8> {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    buf
+}
+
+fn sizes(items: &[(&str, u64)]) -> BTreeMap<PathBuf, u64> {
+    items.iter().map(|&(p, s)| (PathBuf::from(p), s)).collect()
+}
+
+#[test]
+fn test_dir_sizes() {
+    let buf = scan_dir1();
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    assert_eq!(dir_sizes(&mut parser).unwrap(),
+               sizes(&[("/", 6), ("/subdir", 17)]));
+}
+
+#[test]
+fn test_dir_sizes_recursive() {
+    let buf = scan_dir1();
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    assert_eq!(dir_sizes_recursive(&mut parser).unwrap(),
+               sizes(&[("/", 23), ("/subdir", 17)]));
+}
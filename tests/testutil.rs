@@ -0,0 +1,42 @@
+#![cfg(feature="test-util")]
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::testutil::TreeBuilder;
+
+
+#[test]
+fn test_build_and_scan() {
+    let tree = TreeBuilder::new()
+        .file("hello.txt", b"hello\n")
+        .exe("bin/run", b"")
+        .symlink("bin/hello", "../hello.txt")
+        .dir("empty")
+        .build().unwrap();
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(tree.path(), "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let text = String::from_utf8(buf).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(&lines[1..lines.len()-1], &[
+        "/",
+        "  hello.txt f 6 \
+         7f3f0c0d5219f51459578305ed2bbc198588758da85d08024c79c1195d1cd611",
+        "/bin",
+        "  hello s ../hello.txt",
+        "  run x 0",
+        "/empty",
+    ]);
+
+    // same spec produces the same image
+    let again = TreeBuilder::new()
+        .file("hello.txt", b"hello\n")
+        .exe("bin/run", b"")
+        .symlink("bin/hello", "../hello.txt")
+        .dir("empty")
+        .build().unwrap();
+    let mut cfg2 = ScannerConfig::new();
+    cfg2.add_dir(again.path(), "/");
+    assert_eq!(v1::scan_to_vec(&cfg).unwrap().1,
+               v1::scan_to_vec(&cfg2).unwrap().1);
+}
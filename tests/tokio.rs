@@ -0,0 +1,49 @@
+#![cfg(feature="tokio")]
+
+use std::io::{BufReader, Cursor};
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::v1::Parser;
+use dir_signature::v1::tokio::AsyncParser;
+
+
+#[tokio::test]
+async fn test_async_parser() {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir1", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf[..])))
+        .unwrap();
+    let expected = parser.iter().collect::<Result<Vec<_>, _>>().unwrap();
+
+    let reader = tokio::io::BufReader::new(&buf[..]);
+    let mut parser = AsyncParser::new(reader).await.unwrap();
+    assert_eq!(parser.get_header().get_block_size(), 32768);
+    let mut entries = Vec::new();
+    while let Some(entry) = parser.next_entry().await {
+        entries.push(entry.unwrap());
+    }
+    assert_eq!(entries, expected);
+    assert!(parser.next_entry().await.is_none());
+}
+
+#[tokio::test]
+async fn test_async_parser_extra_lines() {
+    let content = b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+/extra
+";
+    let reader = tokio::io::BufReader::new(&content[..]);
+    let mut parser = AsyncParser::new(reader).await.unwrap();
+    assert!(parser.next_entry().await.unwrap().is_ok());
+    assert!(parser.next_entry().await.unwrap().is_ok());
+    let err = parser.next_entry().await.unwrap().unwrap_err();
+    assert_eq!(err.to_string(),
+        "Parse error at line 4: Invalid line: \
+         Found extra lines after the footer");
+}
@@ -0,0 +1,38 @@
+use std::ffi::OsStr;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+use dir_signature::{ScannerConfig, v1};
+use dir_signature::v1::Parser;
+use dir_signature::v1::tree::build;
+
+
+#[test]
+fn test_tree() {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir("tests/dir2", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    let mut parser = Parser::new(BufReader::new(Cursor::new(&buf))).unwrap();
+    let root = build(&mut parser).unwrap();
+
+    assert_eq!(root.path(), Path::new("/"));
+    assert_eq!(root.name(), None);
+    let files = root.files().iter().map(|e| e.path()).collect::<Vec<_>>();
+    assert_eq!(files, vec![Path::new("/file2.txt")]);
+    let dirs = root.children().iter().map(|d| d.name().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(dirs, vec![OsStr::new("sub2"), OsStr::new("subdir")]);
+
+    let subdir = root.find("/subdir").unwrap();
+    assert_eq!(subdir.path(), Path::new("/subdir"));
+    let files = subdir.files().iter().map(|e| e.path()).collect::<Vec<_>>();
+    assert_eq!(files, vec![
+        Path::new("/subdir/bigdata.bin"),
+        Path::new("/subdir/file3.txt"),
+    ]);
+    assert!(subdir.children().is_empty());
+    assert_eq!(root.child("sub2").unwrap().files().len(), 1);
+    assert!(root.find("missing").is_none());
+    assert!(root.find("/subdir/file3.txt").is_none());
+}
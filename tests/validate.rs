@@ -0,0 +1,80 @@
+use std::io::{BufReader, Cursor};
+
+use dir_signature::v1::Parser;
+use dir_signature::v1::validate::{check_structure, check_grouping};
+
+
+fn check(content: &[u8]) -> Result<(), String> {
+    let mut parser = Parser::new(BufReader::new(Cursor::new(content)))
+        .unwrap();
+    check_structure(&mut parser).map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_valid() {
+    assert_eq!(check(b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6c0fc
+/subdir
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819 9ce28248299290fe84340d7821adf01b3b6a579ef827e1e58bc3949de4b7e5d9
+  just\\x20link s ../hello.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+"), Ok(()));
+}
+
+#[test]
+fn test_truncated_hash() {
+    assert_eq!(check(b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+  hello.txt f 6 8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+"), Err("Parse error at line 4: Invalid hash: Expected hash with length \
+             of 64: \"8dd499a36d950b8732f85a3bffbc8d8bee4a0af391e8ee2bb0aa0c4553b6\""
+            .into()));
+}
+
+#[test]
+fn test_missing_hash() {
+    assert_eq!(check(b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  .hidden f 58394 24f72d3a930b5f7933ddd91a5c7cb7ba09a093f936a04bf6486c8b1763c59819
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+"), Err("Parse error at line 3: Invalid hash: \
+             Expected 2 hashes but found 1".into()));
+}
+
+fn grouping(content: &[u8]) -> Result<(), String> {
+    let mut parser = Parser::new(BufReader::new(Cursor::new(content)))
+        .unwrap();
+    check_grouping(&mut parser).map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_grouping_valid() {
+    assert_eq!(grouping(b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+/subdir
+  just\\x20link s ../empty.txt
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+"), Ok(()));
+}
+
+#[test]
+fn test_grouping_misattributed_file() {
+    assert_eq!(grouping(b"\
+DIRSIGNATURE.v1 sha512/256 block_size=32768
+/
+  empty.txt f 0
+/subdir
+  other/empty.txt f 0
+c23f2579827456818fc855c458d1ad7339d144b57ee247a6628e4fc8e39958bb
+"), Err("Parse error at line 5: Invalid line: \"/subdir/other/empty.txt\" \
+             doesn't belong to directory \"/subdir\"".into()));
+}
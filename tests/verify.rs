@@ -0,0 +1,345 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use dir_signature::{HashType, ScannerConfig, v1};
+use dir_signature::v1::Parser;
+use dir_signature::v1::verify::{verify_dir, verify_dir_fast, verify_paths};
+use dir_signature::v1::verify::{verify_dir_with, Discrepancy, VerifyOptions};
+
+
+fn write_file(path: &Path, data: &[u8]) {
+    File::create(path).unwrap().write_all(data).unwrap();
+}
+
+fn scan(dir: &Path) -> Vec<u8> {
+    let mut cfg = ScannerConfig::new();
+    cfg.add_dir(dir, "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    buf
+}
+
+fn parser(buf: &[u8]) -> Parser<BufReader<Cursor<&[u8]>>> {
+    Parser::new(BufReader::new(Cursor::new(buf))).unwrap()
+}
+
+#[test]
+fn test_verify_unchanged() {
+    let buf = scan(Path::new("tests/dir2"));
+    assert_eq!(verify_dir(&mut parser(&buf), "tests/dir2").unwrap(), vec![]);
+    assert!(verify_dir_fast(&mut parser(&buf), "tests/dir2").unwrap());
+}
+
+#[test]
+fn test_verify_unchanged_blake3() {
+    let mut cfg = ScannerConfig::new();
+    cfg.hash(HashType::blake3_256());
+    cfg.add_dir("tests/dir2", "/");
+    let mut buf = Vec::new();
+    v1::scan(&cfg, &mut buf).unwrap();
+    assert_eq!(verify_dir(&mut parser(&buf), "tests/dir2").unwrap(), vec![]);
+    assert!(verify_dir_fast(&mut parser(&buf), "tests/dir2").unwrap());
+}
+
+#[test]
+fn test_verify_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    write_file(&root.join("a.txt"), b"hello\n");
+    write_file(&root.join("b.txt"), b"world\n");
+    write_file(&root.join("sub/c.txt"), b"test\n");
+    let buf = scan(root);
+
+    write_file(&root.join("a.txt"), b"HELLO\n");
+    fs::remove_file(root.join("b.txt")).unwrap();
+    write_file(&root.join("sub/c.txt"), b"changed\n");
+    write_file(&root.join("sub/d.txt"), b"new\n");
+    fs::create_dir(root.join("sub/x")).unwrap();
+
+    assert_eq!(verify_dir(&mut parser(&buf), root).unwrap(), vec![
+        Discrepancy::ContentMismatch(PathBuf::from("/a.txt")),
+        Discrepancy::Missing(PathBuf::from("/b.txt")),
+        Discrepancy::SizeMismatch {
+            path: PathBuf::from("/sub/c.txt"),
+            expected: 5,
+            actual: 8,
+        },
+        Discrepancy::Extra(PathBuf::from("/sub/d.txt")),
+        Discrepancy::Extra(PathBuf::from("/sub/x")),
+    ]);
+    assert!(!verify_dir_fast(&mut parser(&buf), root).unwrap());
+}
+
+#[test]
+fn test_verify_fast_stops_early() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_file(&root.join("a.txt"), b"hello\n");
+    write_file(&root.join("b.txt"), b"world\n");
+    let buf = scan(root);
+
+    write_file(&root.join("a.txt"), b"HELLO\n");
+    fs::set_permissions(root.join("b.txt"),
+        fs::Permissions::from_mode(0o000)).unwrap();
+
+    if File::open(root.join("b.txt")).is_err() {
+        // later file would fail verification if it was read
+        assert!(verify_dir(&mut parser(&buf), root).is_err());
+    }
+    assert!(!verify_dir_fast(&mut parser(&buf), root).unwrap());
+}
+
+#[test]
+fn test_verify_zero_byte_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    write_file(&root.join("empty.txt"), b"");
+    write_file(&root.join("sub/test.txt"), b"");
+    let buf = scan(root);
+    assert_eq!(verify_dir(&mut parser(&buf), root).unwrap(), vec![]);
+
+    write_file(&root.join("sub/test.txt"), b"x");
+    assert_eq!(verify_dir(&mut parser(&buf), root).unwrap(), vec![
+        Discrepancy::SizeMismatch {
+            path: PathBuf::from("/sub/test.txt"),
+            expected: 0,
+            actual: 1,
+        },
+    ]);
+}
+
+#[test]
+fn test_verify_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    write_file(&root.join("a.txt"), b"hello\n");
+    write_file(&root.join("b.txt"), b"world\n");
+    write_file(&root.join("sub/c.txt"), b"test\n");
+    write_file(&root.join("sub/d.txt"), b"more\n");
+    let buf = scan(root);
+
+    write_file(&root.join("a.txt"), b"HELLO\n");
+    write_file(&root.join("sub/c.txt"), b"TEST\n");
+
+    let paths = vec![PathBuf::from("/sub/d.txt"), PathBuf::from("/b.txt")];
+    assert_eq!(verify_paths(&mut parser(&buf), root, &paths).unwrap(),
+               vec![]);
+
+    fs::remove_file(root.join("sub/d.txt")).unwrap();
+    let paths = vec![
+        PathBuf::from("/sub/d.txt"),
+        PathBuf::from("/sub/c.txt"),
+        PathBuf::from("/b.txt"),
+    ];
+    assert_eq!(verify_paths(&mut parser(&buf), root, &paths).unwrap(), vec![
+        Discrepancy::ContentMismatch(PathBuf::from("/sub/c.txt")),
+        Discrepancy::Missing(PathBuf::from("/sub/d.txt")),
+    ]);
+}
+
+/// Copies `tests/dir1` into a temporary directory
+fn copy_dir1() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for name in &["hello.txt", "test.txt", "subdir/.hidden", "subdir/file.txt"]
+    {
+        let dest = dir.path().join(name);
+        fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        fs::copy(Path::new("tests/dir1").join(name), dest).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_verify_type_mismatch() {
+    use std::os::unix::fs::symlink;
+
+    let dir = copy_dir1();
+    let root = dir.path();
+    let buf = scan(root);
+    // file -> directory
+    fs::remove_file(root.join("hello.txt")).unwrap();
+    fs::create_dir(root.join("hello.txt")).unwrap();
+    // file -> symlink
+    fs::remove_file(root.join("test.txt")).unwrap();
+    symlink("hello.txt", root.join("test.txt")).unwrap();
+    // directory -> file
+    fs::remove_dir_all(root.join("subdir")).unwrap();
+    write_file(&root.join("subdir"), b"");
+
+    let expected = vec![
+        Discrepancy::TypeMismatch(PathBuf::from("/hello.txt")),
+        Discrepancy::TypeMismatch(PathBuf::from("/test.txt")),
+        Discrepancy::TypeMismatch(PathBuf::from("/subdir")),
+    ];
+    assert_eq!(verify_dir(&mut parser(&buf), root).unwrap(), expected);
+    assert_eq!(verify_dir_with(&mut parser(&buf), root,
+                               &VerifyOptions::new()).unwrap(),
+               expected);
+    assert_eq!(verify_dir_with(&mut parser(&buf), root,
+                               VerifyOptions::new().strict_types()).unwrap(),
+               expected[..1].to_vec());
+}
+
+#[test]
+fn test_verify_symlink_mismatch() {
+    use std::os::unix::fs::symlink;
+
+    let dir = copy_dir1();
+    let root = dir.path();
+    symlink("hello.txt", root.join("link")).unwrap();
+    let buf = scan(root);
+    // symlink -> file
+    fs::remove_file(root.join("link")).unwrap();
+    write_file(&root.join("link"), b"HeLLo\n");
+    // content change is reported but doesn't stop strict verification
+    write_file(&root.join("subdir/file.txt"), b"SomenDATA\n");
+
+    assert_eq!(verify_dir_with(&mut parser(&buf), root,
+                               VerifyOptions::new().strict_types()).unwrap(),
+        vec![Discrepancy::TypeMismatch(PathBuf::from("/link"))]);
+    fs::remove_file(root.join("link")).unwrap();
+    symlink("hello.txt", root.join("link")).unwrap();
+    assert_eq!(verify_dir_with(&mut parser(&buf), root,
+                               VerifyOptions::new().strict_types()).unwrap(),
+        vec![Discrepancy::ContentMismatch(
+            PathBuf::from("/subdir/file.txt"))]);
+}
+
+#[test]
+fn test_changed_heuristic() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    write_file(&root.join("a.txt"), b"hello\n");
+    write_file(&root.join("sub/b.txt"), b"world\n");
+    let buf = scan(root);
+    assert_eq!(v1::changed_heuristic(&mut parser(&buf), root).unwrap(),
+               Vec::<PathBuf>::new());
+
+    // same size, not detected without reading contents
+    write_file(&root.join("a.txt"), b"HELLO\n");
+    write_file(&root.join("sub/b.txt"), b"world, again\n");
+    assert_eq!(v1::changed_heuristic(&mut parser(&buf), root).unwrap(),
+               vec![PathBuf::from("/sub/b.txt")]);
+}
+
+#[test]
+fn test_verify_dir_streaming() {
+    use dir_signature::v1::verify::{verify_dir_streaming, FileVerifyResult};
+
+    let buf = scan(Path::new("tests/dir1"));
+    let mut results = Vec::new();
+    assert!(verify_dir_streaming(&mut parser(&buf), "tests/dir1",
+        |r| results.push(r)).unwrap());
+    let ok = |path: &str, bytes| FileVerifyResult {
+        path: PathBuf::from(path),
+        discrepancy: None,
+        bytes,
+    };
+    assert_eq!(results, vec![
+        ok("/hello.txt", 6),
+        ok("/test.txt", 0),
+        ok("/subdir/.hidden", 7),
+        ok("/subdir/file.txt", 10),
+    ]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_file(&root.join("a.txt"), b"hello\n");
+    write_file(&root.join("b.txt"), b"world\n");
+    let buf = scan(root);
+    write_file(&root.join("a.txt"), b"HELLO\n");
+    write_file(&root.join("c.txt"), b"new\n");
+    let mut results = Vec::new();
+    assert!(!verify_dir_streaming(&mut parser(&buf), root,
+        |r| results.push(r)).unwrap());
+    assert_eq!(results, vec![
+        FileVerifyResult {
+            path: PathBuf::from("/a.txt"),
+            discrepancy: Some(Discrepancy::ContentMismatch("/a.txt".into())),
+            bytes: 6,
+        },
+        ok("/b.txt", 6),
+        FileVerifyResult {
+            path: PathBuf::from("/c.txt"),
+            discrepancy: Some(Discrepancy::Extra("/c.txt".into())),
+            bytes: 0,
+        },
+    ]);
+}
+
+#[test]
+fn test_verify_ignore_exe_bit() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_file(&root.join("a.sh"), b"#!/bin/sh\n");
+    write_file(&root.join("b.txt"), b"hello\n");
+    let buf = scan(root);
+
+    fs::set_permissions(root.join("a.sh"),
+        fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(verify_dir(&mut parser(&buf), root).unwrap(),
+        vec![Discrepancy::ExeMismatch(PathBuf::from("/a.sh"))]);
+    assert_eq!(verify_dir_with(&mut parser(&buf), root,
+                               VerifyOptions::new().ignore_exe_bit()).unwrap(),
+               vec![]);
+
+    // content changes are still reported
+    write_file(&root.join("a.sh"), b"#!/bin/SH\n");
+    assert_eq!(verify_dir_with(&mut parser(&buf), root,
+                               VerifyOptions::new().ignore_exe_bit()).unwrap(),
+        vec![Discrepancy::ContentMismatch(PathBuf::from("/a.sh"))]);
+}
+
+#[test]
+fn test_verify_against_union() {
+    use dir_signature::v1::merge::MergedSignatures;
+    use dir_signature::v1::verify::verify_against_union;
+
+    let base = tempfile::tempdir().unwrap();
+    fs::create_dir(base.path().join("sub")).unwrap();
+    write_file(&base.path().join("a.txt"), b"hello\n");
+    write_file(&base.path().join("b.txt"), b"world\n");
+    write_file(&base.path().join("sub/c.txt"), b"test\n");
+    let overlay = tempfile::tempdir().unwrap();
+    fs::create_dir(overlay.path().join("sub")).unwrap();
+    write_file(&overlay.path().join("a.txt"), b"HELLO\n");
+    write_file(&overlay.path().join(".wh.b.txt"), b"");
+    write_file(&overlay.path().join("sub/d.txt"), b"new\n");
+
+    let base_buf = scan(base.path());
+    let mut cfg = ScannerConfig::new();
+    cfg.overlay_whiteouts();
+    cfg.add_dir(overlay.path(), "/");
+    let mut overlay_buf = Vec::new();
+    v1::scan(&cfg, &mut overlay_buf).unwrap();
+    let verify = |root: &Path| {
+        let mut merged = MergedSignatures::new(vec![
+            ("base", parser(&base_buf)),
+            ("overlay", parser(&overlay_buf)),
+        ]).unwrap();
+        verify_against_union(&mut merged, root).unwrap()
+    };
+
+    let composed = tempfile::tempdir().unwrap();
+    let root = composed.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    write_file(&root.join("a.txt"), b"HELLO\n");
+    write_file(&root.join("sub/c.txt"), b"test\n");
+    write_file(&root.join("sub/d.txt"), b"new\n");
+    assert_eq!(verify(root), vec![]);
+
+    write_file(&root.join("a.txt"), b"hello\n");
+    write_file(&root.join("b.txt"), b"world\n");
+    fs::remove_file(root.join("sub/d.txt")).unwrap();
+    assert_eq!(verify(root), vec![
+        Discrepancy::ContentMismatch(PathBuf::from("/a.txt")),
+        Discrepancy::Extra(PathBuf::from("/b.txt")),
+        Discrepancy::Missing(PathBuf::from("/sub/d.txt")),
+    ]);
+}